@@ -0,0 +1,197 @@
+use crate::{Error, Expr, Identifier};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Combinator {
+    App(Box<Combinator>, Box<Combinator>),
+    I,
+    K,
+    S,
+    Var(Identifier),
+}
+
+impl Combinator {
+    fn app(c_1: Self, c_2: Self) -> Self {
+        Self::App(Box::new(c_1), Box::new(c_2))
+    }
+
+    fn occurs(&self, x: &str) -> bool {
+        match self {
+            Self::App(c_1, c_2) => c_1.occurs(x) || c_2.occurs(x),
+            Self::Var(y) => x == y,
+            _ => false,
+        }
+    }
+
+    // Bracket abstraction: [x] x = I, [x] c = K c if x is not free in c,
+    // [x] (c_1 c_2) = S ([x] c_1) ([x] c_2).
+    fn abstract_(self, x: &str) -> Self {
+        match self {
+            Self::Var(y) if x == y => Self::I,
+            c if !c.occurs(x) => Self::app(Self::K, c),
+            Self::App(c_1, c_2) => {
+                Self::app(Self::app(Self::S, c_1.abstract_(x)), c_2.abstract_(x))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn reduce(&self) -> Self {
+        let mut head = self.to_owned();
+        let mut args = vec![];
+
+        loop {
+            match head {
+                Self::App(c_1, c_2) => {
+                    args.push(*c_2);
+                    head = *c_1;
+                }
+                Self::I if !args.is_empty() => {
+                    head = args.pop().unwrap();
+                }
+                Self::K if args.len() >= 2 => {
+                    head = args.pop().unwrap();
+                    args.pop();
+                }
+                Self::S if args.len() >= 3 => {
+                    let c_1 = args.pop().unwrap();
+                    let c_2 = args.pop().unwrap();
+                    let c_3 = args.pop().unwrap();
+                    head = Self::app(Self::app(c_1, c_3.clone()), Self::app(c_2, c_3));
+                }
+                _ => break,
+            }
+        }
+
+        args.into_iter()
+            .rev()
+            .fold(head, |c_1, c_2| Self::app(c_1, c_2.reduce()))
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        let var = |x: &str| Box::new(Expr::Var(x.to_owned()));
+        let lam = |x: &str, e: Expr| Expr::Lam(x.to_owned(), None, Box::new(e));
+
+        match self {
+            Self::App(c_1, c_2) => Expr::App(Box::new(c_1.to_expr()), Box::new(c_2.to_expr())),
+            Self::I => lam("x", *var("x")),
+            Self::K => lam("x", lam("y", *var("x"))),
+            Self::S => lam(
+                "x",
+                lam(
+                    "y",
+                    lam(
+                        "z",
+                        Expr::App(
+                            Box::new(Expr::App(var("x"), var("z"))),
+                            Box::new(Expr::App(var("y"), var("z"))),
+                        ),
+                    ),
+                ),
+            ),
+            Self::Var(x) => Expr::Var(x.to_owned()),
+        }
+    }
+}
+
+impl Expr {
+    pub fn to_combinators(&self) -> Result<Combinator, Error> {
+        match self {
+            Self::App(e_1, e_2) => Ok(Combinator::app(
+                e_1.to_combinators()?,
+                e_2.to_combinators()?,
+            )),
+            Self::Lam(x, _, e) => Ok(e.to_combinators()?.abstract_(x)),
+            Self::Let(bs, e) => {
+                let c = bs
+                    .iter()
+                    .rev()
+                    .fold(e.to_combinators()?, |c, (x, _)| c.abstract_(x));
+
+                bs.iter()
+                    .try_fold(c, |c, (_, e)| Ok(Combinator::app(c, e.to_combinators()?)))
+            }
+            Self::LetRec(_, _) => {
+                Err("recursive bindings have no combinator representation".into())
+            }
+            Self::Sub(x, e_1, e_2) => Ok(Combinator::app(
+                e_2.to_combinators()?.abstract_(x),
+                e_1.to_combinators()?,
+            )),
+            Self::Var(x) => Ok(Combinator::Var(x.to_owned())),
+            Self::Code
+            | Self::Fun(_, _)
+            | Self::Inst(_, _)
+            | Self::Int
+            | Self::List(_)
+            | Self::Pi(_, _, _)
+            | Self::Str
+            | Self::U(_) => Err("types have no combinator representation".into()),
+            Self::Cons(_, _)
+            | Self::IntLit(_)
+            | Self::ListRec(_, _, _)
+            | Self::Nil
+            | Self::Prim(_)
+            | Self::StrLit(_) => Err("primitives have no combinator representation".into()),
+            Self::Quote(_) | Self::Splice(_) => {
+                Err("quoted terms have no combinator representation".into())
+            }
+            Self::Error => Err("syntax error".into()),
+            Self::Hole(_) => Err("unsolved hole".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn combinator_reduction_works() {
+        // (\x. \y. x) a b => a
+        let e = Expr::App(
+            Box::new(Expr::App(
+                Box::new(Expr::Lam(
+                    "x".to_string(),
+                    None,
+                    Box::new(Expr::Lam(
+                        "y".to_string(),
+                        None,
+                        Box::new(Expr::Var("x".to_string())),
+                    )),
+                )),
+                Box::new(Expr::Var("a".to_string())),
+            )),
+            Box::new(Expr::Var("b".to_string())),
+        );
+
+        let c = e.to_combinators().unwrap();
+        assert_eq!(c.reduce(), Combinator::Var("a".to_string()));
+    }
+
+    #[test]
+    fn combinator_round_trip_works() {
+        // \f. \x. f x
+        let e = Expr::Lam(
+            "f".to_string(),
+            None,
+            Box::new(Expr::Lam(
+                "x".to_string(),
+                None,
+                Box::new(Expr::App(
+                    Box::new(Expr::Var("f".to_string())),
+                    Box::new(Expr::Var("x".to_string())),
+                )),
+            )),
+        );
+
+        let e_ = e.to_combinators().unwrap().to_expr();
+        let xs = HashSet::new();
+
+        assert_eq!(
+            e_.eval(&crate::Env::new()).quote(&xs),
+            e.eval(&crate::Env::new()).quote(&xs)
+        );
+    }
+}
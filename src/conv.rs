@@ -0,0 +1,555 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, mem,
+};
+
+use crate::{
+    diff::{children, TermDiff},
+    options::Guard,
+    store::Hash,
+    taken, Context, Env, Error, Expr, Identifier, Value,
+};
+
+// Which global definitions conversion may unfold.
+#[derive(Clone, Debug, Default)]
+pub enum Unfold {
+    #[default]
+    All,
+    None,
+    Only(HashSet<Identifier>),
+}
+
+impl Unfold {
+    pub fn allows(&self, x: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::None => false,
+            Self::Only(xs) => xs.contains(x),
+        }
+    }
+}
+
+// Whether two terms are definitionally equal, with the definitions the
+// policy does not allow to unfold standing for themselves.
+pub fn convertible(
+    e_1: &Expr,
+    e_2: &Expr,
+    d: &Env,
+    policy: &Unfold,
+    g: &Guard,
+) -> Result<bool, Error> {
+    g.convert();
+    let d_ = d.unfolding(policy.to_owned());
+    let xs = d.names();
+    let v_1 = e_1.eval_with(&d_, g)?.quote_with(&xs, g)?;
+    let v_2 = e_2.eval_with(&d_, g)?.quote_with(&xs, g)?;
+    Ok(v_1 == v_2)
+}
+
+// How many closures comparing the unfolded values may apply before
+// conversion turns to smart unfolding.
+pub const CONVERSION_BUDGET: usize = 1000;
+
+// Whether two values in the scope of `cx` and `d` are definitionally equal.
+// They are compared with the globals they keep folded left as written
+// first, which is cheap when both use the same definitions, then unfolded
+// everywhere within `CONVERSION_BUDGET`. Past it, only the globals met where
+// the forms differ are unfolded, those on both sides of a difference first.
+// What unfolding gives is remembered by the guard for the folded forms.
+pub fn convertible_lazily(
+    v_1: &Value,
+    v_2: &Value,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<bool, Error> {
+    g.convert();
+    let xs = taken(cx, d);
+
+    let quote = |v: &Value, policy: &Unfold, g: &Guard| {
+        let e = v.quote_unfolding(&xs, policy, g)?;
+        g.check_size(&e)?;
+        Ok::<_, Error>(e)
+    };
+
+    let (e_1, e_2) = (quote(v_1, &Unfold::None, g)?, quote(v_2, &Unfold::None, g)?);
+
+    if e_1 == e_2 {
+        return Ok(true);
+    }
+
+    if let Some(equal) = g.compared(&e_1, &e_2) {
+        return Ok(equal);
+    }
+
+    let equal = match g.within(CONVERSION_BUDGET, |g| {
+        Ok(quote(v_1, &Unfold::All, g)? == quote(v_2, &Unfold::All, g)?)
+    })? {
+        Some(equal) => equal,
+        None => {
+            let mut allowed = HashSet::new();
+
+            loop {
+                let policy = Unfold::Only(allowed.to_owned());
+                let diff = quote(v_1, &policy, g)?.diff(&quote(v_2, &policy, g)?);
+                let ys = unfoldable(&diff, &allowed, |y| d.global(y).is_some());
+
+                if diff.is_empty() || ys.is_empty() {
+                    break diff.is_empty();
+                }
+
+                allowed.extend(ys);
+            }
+        }
+    };
+
+    g.remember(e_1, e_2, equal);
+    Ok(equal)
+}
+
+// The globals smart unfolding unfolds next, where two normal forms differ:
+// those met on both sides of a difference if there are any, or else all
+// those met.
+fn unfoldable(
+    diff: &TermDiff,
+    allowed: &HashSet<Identifier>,
+    global: impl Fn(&str) -> bool,
+) -> Vec<Identifier> {
+    let unfoldable = |e: &Expr| {
+        let mut ys = e.free_vars();
+        ys.retain(|y| global(y) && !allowed.contains(y));
+        ys
+    };
+
+    let (mut ys, mut both) = (vec![], vec![]);
+
+    for c in &diff.changes {
+        let (ys_1, ys_2) = (unfoldable(&c.old), unfoldable(&c.new));
+        both.extend(ys_1.intersection(&ys_2).cloned());
+        ys.extend(ys_1.into_iter().chain(ys_2));
+    }
+
+    if !both.is_empty() {
+        ys = both;
+    }
+
+    ys.sort();
+    ys.dedup();
+    ys
+}
+
+// Whether two closed terms have the same type and the same normal form.
+pub fn defeq(e_1: &Expr, e_2: &Expr, cx: &Context, d: &Env) -> Result<bool, Error> {
+    let xs = HashSet::new();
+    let g = Guard::default();
+
+    if e_1.infer_with(cx, d, &g)?.quote(&xs) != e_2.infer_with(cx, d, &g)?.quote(&xs) {
+        return Ok(false);
+    }
+
+    convertible(e_1, e_2, d, &Unfold::All, &g)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    // Both sides were normalized, applying this many closures, and compared.
+    Compare { beta: usize, diff: TermDiff },
+    Unfold(Identifier),
+}
+
+// How two terms were found equal or not.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    pub equal: bool,
+    pub steps: Vec<Step>,
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            match step {
+                Step::Compare { beta, diff } if diff.is_empty() => {
+                    writeln!(f, "equal after {beta} beta steps")?
+                }
+                Step::Compare { beta, diff } => {
+                    writeln!(f, "different after {beta} beta steps:")?;
+
+                    for a in diff.to_string().lines() {
+                        writeln!(f, "  {a}")?;
+                    }
+                }
+                Step::Unfold(x) => writeln!(f, "unfolding {x}")?,
+            }
+        }
+
+        write!(f, "{}", if self.equal { "equal" } else { "not equal" })
+    }
+}
+
+// Decides conversion by smart unfolding, recording every step. Only the
+// definitions met where the normal forms differ are unfolded, and of those
+// the ones on both sides of a difference if there are any.
+pub fn why_equal(e_1: &Expr, e_2: &Expr, d: &Env, g: &Guard) -> Result<Trace, Error> {
+    let mut allowed = HashSet::new();
+    let mut steps = vec![];
+
+    loop {
+        let k = g.unfoldings();
+        let d_ = d.unfolding(Unfold::Only(allowed.to_owned()));
+        let xs = d.names();
+        let v_1 = e_1.eval_with(&d_, g)?.quote_with(&xs, g)?;
+        let v_2 = e_2.eval_with(&d_, g)?.quote_with(&xs, g)?;
+        let diff = v_1.diff(&v_2);
+        let equal = diff.is_empty();
+        let ys = unfoldable(&diff, &allowed, |y| d.contains_key(y));
+
+        steps.push(Step::Compare {
+            beta: g.unfoldings() - k,
+            diff,
+        });
+
+        if equal || ys.is_empty() {
+            return Ok(Trace { equal, steps });
+        }
+
+        for y in ys {
+            steps.push(Step::Unfold(y.to_owned()));
+            allowed.insert(y);
+        }
+    }
+}
+
+// Why a unification problem has no solution.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    // The solution of a variable mentions the variable itself.
+    Occurs {
+        meta: Identifier,
+        solution: Expr,
+    },
+    // The solution of a variable mentions variables bound inside the terms,
+    // which are not in scope where the variable stands.
+    Escape {
+        meta: Identifier,
+        vars: Vec<Identifier>,
+    },
+    // A variable would need two different solutions.
+    Conflict {
+        meta: Identifier,
+        first: Expr,
+        second: Expr,
+    },
+    // A variable applied to arguments, whose solution would have to be a
+    // function of them.
+    NonPattern {
+        meta: Identifier,
+        term: Expr,
+    },
+    // Two terms with different heads.
+    Rigid(Expr, Expr),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Occurs { meta, solution } => {
+                write!(f, "occurs check: {meta} cannot be solved by {solution}")
+            }
+            Self::Escape { meta, vars } => write!(
+                f,
+                "scope escape: the solution of {meta} mentions {}, not in its scope",
+                vars.join(", ")
+            ),
+            Self::Conflict {
+                meta,
+                first,
+                second,
+            } => write!(f, "conflict: {meta} is both {first} and {second}"),
+            Self::NonPattern { meta, term } => {
+                write!(f, "not a pattern: {meta} is applied to arguments in {term}")
+            }
+            Self::Rigid(e_1, e_2) => write!(f, "mismatch: {e_1} is not {e_2}"),
+        }
+    }
+}
+
+// Solves the variables of `metas`, occurring on either side, so that
+// `pattern` becomes convertible with `e`, by matching their normal forms. A
+// solution cannot mention bound variables. When there is no solution, the
+// first problem that has none tells why.
+pub fn unify(
+    pattern: &Expr,
+    e: &Expr,
+    metas: &HashSet<Identifier>,
+    d: &Env,
+    g: &Guard,
+) -> Result<Result<HashMap<Identifier, Expr>, Mismatch>, Error> {
+    let xs = d.names();
+    let p = pattern.eval_with(d, g)?.quote_with(&xs, g)?;
+    let e = e.eval_with(d, g)?.quote_with(&xs, g)?;
+    let mut sol = HashMap::new();
+    let xs = HashMap::new();
+    Ok(unify_in(&p, &e, metas, 0, &xs, &xs, &mut sol).map(|()| sol))
+}
+
+fn unify_in<'a>(
+    p: &'a Expr,
+    e: &'a Expr,
+    metas: &HashSet<Identifier>,
+    i: usize,
+    xs: &HashMap<&'a str, usize>,
+    ys: &HashMap<&'a str, usize>,
+    sol: &mut HashMap<Identifier, Expr>,
+) -> Result<(), Mismatch> {
+    let is_meta = |x: &str, xs: &HashMap<&str, usize>| metas.contains(x) && !xs.contains_key(x);
+
+    let mut solve = |x: &Identifier, e: &Expr, ys: &HashMap<&str, usize>| {
+        if *e == Expr::Var(x.to_owned()) {
+            return Ok(());
+        }
+
+        if e.occurs(x) {
+            return Err(Mismatch::Occurs {
+                meta: x.to_owned(),
+                solution: e.to_owned(),
+            });
+        }
+
+        let mut vars = ys
+            .keys()
+            .filter(|y| e.occurs(y))
+            .map(|y| y.to_string())
+            .collect::<Vec<_>>();
+
+        if !vars.is_empty() {
+            vars.sort();
+
+            return Err(Mismatch::Escape {
+                meta: x.to_owned(),
+                vars,
+            });
+        }
+
+        match sol.get(x) {
+            Some(e_) if e_ == e => Ok(()),
+            Some(e_) => Err(Mismatch::Conflict {
+                meta: x.to_owned(),
+                first: e_.to_owned(),
+                second: e.to_owned(),
+            }),
+            None => {
+                sol.insert(x.to_owned(), e.to_owned());
+                Ok(())
+            }
+        }
+    };
+
+    match (p, e) {
+        (Expr::Var(x), _) if is_meta(x, xs) => return solve(x, e, ys),
+        (_, Expr::Var(y)) if is_meta(y, ys) => return solve(y, p, xs),
+        _ => {}
+    }
+
+    let (ps, es) = (children(p), children(e));
+
+    if ps.is_empty()
+        || matches!(p, Expr::Let(_, _) | Expr::LetRec(_, _))
+        || mem::discriminant(p) != mem::discriminant(e)
+        || ps.len() != es.len()
+    {
+        if p.alpha_eq(e, i, xs, ys) {
+            return Ok(());
+        }
+
+        // A variable applied to arguments only matches an application.
+        for (e, xs) in [(p, xs), (e, ys)] {
+            let mut head = e;
+
+            while let Expr::App(e_1, _) = head {
+                head = e_1;
+            }
+
+            match head {
+                Expr::Var(x) if head != e && is_meta(x, xs) => {
+                    return Err(Mismatch::NonPattern {
+                        meta: x.to_owned(),
+                        term: e.to_owned(),
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        return Err(Mismatch::Rigid(p.to_owned(), e.to_owned()));
+    }
+
+    ps.into_iter()
+        .zip(es)
+        .try_for_each(|((p, x), (e, y))| match (x, y) {
+            (Some(x), Some(y)) => {
+                let mut xs_ = xs.to_owned();
+                xs_.insert(x, i);
+                let mut ys_ = ys.to_owned();
+                ys_.insert(y, i);
+                unify_in(p, e, metas, i + 1, &xs_, &ys_, sol)
+            }
+            _ => unify_in(p, e, metas, i, xs, ys, sol),
+        })
+}
+
+impl Expr {
+    // A hash of the normal form, equal for definitionally equal terms.
+    pub fn nf_hash(&self, d: &Env) -> Result<Hash, Error> {
+        let g = Guard::default();
+        let e = self.eval_with(d, &g)?.quote_with(&HashSet::new(), &g)?;
+        Ok(e.hash_with(&|_| None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::Driver, notation::Notations, parser::parse_expr, Prim};
+
+    #[test]
+    fn unfolding_follows_the_policy() {
+        let mut driver = Driver::new();
+        driver
+            .run("def one : Int := 1\ndef two : Int := int_add one one")
+            .unwrap();
+
+        let var = |x: &str| Box::new(Expr::Var(x.to_string()));
+        let g = Guard::default();
+        let e = Expr::App(
+            Box::new(Expr::App(Box::new(Expr::Prim(Prim::IntAdd)), var("one"))),
+            var("one"),
+        );
+
+        let conv =
+            |e: &Expr, policy| convertible(e, &Expr::IntLit(2), &driver.d, &policy, &g).unwrap();
+
+        let only = |x: &str| Unfold::Only(HashSet::from([x.to_string()]));
+        assert!(!conv(&var("two"), Unfold::None));
+        assert!(!conv(&var("two"), only("one")));
+        assert!(conv(&var("two"), only("two")));
+        assert!(conv(&e, only("one")));
+        assert!(conv(&e, Unfold::All));
+    }
+
+    #[test]
+    fn conversion_is_traced() {
+        let mut driver = Driver::new();
+        driver
+            .run(
+                "def id : Int -> Int := \\x. x\ndef two : Int := int_add 1 1\ndef three : Int := 3",
+            )
+            .unwrap();
+
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let g = Guard::default();
+        let trace = why_equal(&parse("id two"), &parse("2"), &driver.d, &g).unwrap();
+        assert!(trace.equal);
+        assert_eq!(
+            trace.to_string(),
+            "different after 0 beta steps:
+  at the root: id two ~> 2
+unfolding id
+unfolding two
+equal after 1 beta steps
+equal"
+        );
+
+        let trace = why_equal(&parse("two"), &parse("three"), &driver.d, &g).unwrap();
+        assert!(!trace.equal);
+        assert_eq!(trace.steps.len(), 4);
+    }
+
+    #[test]
+    fn conversion_turns_to_smart_unfolding() {
+        let mut driver = Driver::new();
+        driver
+            .run(
+                "def id : Int -> Int := \\x. x
+def twice : (Int -> Int) -> Int -> Int := \\f x. f (f x)
+def blow : Int -> Int := twice (twice (twice (twice (twice (twice (twice (twice (twice (twice (twice id))))))))))",
+            )
+            .unwrap();
+
+        let d = driver.d.gluing();
+        let eval = |a: &str| parse_expr(a, &mut Notations::new()).unwrap().eval(&d);
+        let (v_1, v_2) = (eval("f (blow 1) 1"), eval("f (blow 1) (id 1)"));
+        let cx = Context::new();
+
+        let g = Guard::default();
+        assert!(convertible_lazily(&v_1, &v_2, &cx, &driver.d, &g).unwrap());
+        assert!(g.unfoldings() < 2 * CONVERSION_BUDGET);
+
+        let g = Guard::default();
+        let v_3 = eval("f (blow 1) 2");
+        assert!(!convertible_lazily(&v_1, &v_3, &cx, &driver.d, &g).unwrap());
+    }
+
+    #[test]
+    fn normal_forms_are_compared() {
+        let mut driver = Driver::new();
+        driver
+            .run("def id : Int -> Int := \\x. x\ndef two : Int := int_add 1 1")
+            .unwrap();
+
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let (e_1, e_2) = (parse("id two"), parse("2"));
+        assert!(defeq(&e_1, &e_2, &driver.cx, &driver.d).unwrap());
+        assert_eq!(
+            e_1.nf_hash(&driver.d).unwrap(),
+            e_2.nf_hash(&driver.d).unwrap()
+        );
+        assert_ne!(
+            e_1.nf_hash(&driver.d).unwrap(),
+            parse("3").nf_hash(&driver.d).unwrap()
+        );
+        assert_eq!(parse("?h").nf_hash(&driver.d), Err("unsolved hole".into()));
+        assert!(!defeq(&e_1, &parse("3"), &driver.cx, &driver.d).unwrap());
+        assert!(!defeq(&e_1, &parse("\"2\""), &driver.cx, &driver.d).unwrap());
+    }
+
+    #[test]
+    fn unification_failures_are_explained() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let g = Guard::default();
+        let metas = HashSet::from(["A".to_string(), "F".to_string()]);
+
+        let why = |a: &str, b: &str| {
+            let m = unify(&parse(a), &parse(b), &metas, &crate::Env::new(), &g).unwrap();
+            m.unwrap_err().to_string()
+        };
+
+        let sol = unify(
+            &parse("List A"),
+            &parse("List Int"),
+            &metas,
+            &crate::Env::new(),
+            &g,
+        );
+        assert_eq!(sol.unwrap().unwrap()["A"], Expr::Int);
+        assert_eq!(
+            why("A", "List A"),
+            "occurs check: A cannot be solved by List A"
+        );
+        assert_eq!(
+            why("\\x. A", "\\y. y"),
+            "scope escape: the solution of A mentions y, not in its scope"
+        );
+        assert_eq!(
+            why("A -> A", "Int -> String"),
+            "conflict: A is both Int and String"
+        );
+        assert_eq!(
+            why("F Int", "String"),
+            "not a pattern: F is applied to arguments in F Int"
+        );
+        assert_eq!(
+            why("Int -> A", "String -> Int"),
+            "mismatch: Int is not String"
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::{HashMap, HashSet},
+    slice,
+};
+
+use crate::{
+    elab::{self, Hints},
+    freshen,
+    lexer::Pos,
+    options::Guard,
+    sized,
+    telescope::Telescope,
+    Context, Env, Error, Expr, Fix, Identifier, Neutral, Type, Value,
+};
+
+#[derive(Clone, Debug)]
+pub struct Def {
+    pub name: Identifier,
+    pub ty: Expr,
+    pub body: Expr,
+    pub doc: Option<String>,
+    // Plain comments met inside the definition, in order.
+    pub comments: Vec<String>,
+    // Whether the body stays hidden from importing modules.
+    pub opaque: bool,
+    // Whether the definition is hidden from importing modules altogether.
+    pub private: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum Decl {
+    Def(Def),
+    Mutual(Vec<Def>),
+}
+
+// section (A : U 0) (x : A) ... end, a block of declarations sharing the
+// variables of the section, which they are abstracted over when it ends.
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub vars: Telescope,
+    pub decls: Vec<(Pos, Decl)>,
+}
+
+impl Section {
+    // The declarations as they are outside the section. Every definition
+    // takes the variables as its first arguments, and refers to those of the
+    // section applied to them.
+    pub fn discharge(&self) -> Vec<(Pos, Decl)> {
+        let mut s = HashMap::new();
+        let mut decls = vec![];
+
+        for (pos, decl) in &self.decls {
+            let decl = match decl {
+                Decl::Def(def) => {
+                    let def = self.discharge_def(def, &s);
+                    s.insert(def.name.to_owned(), self.applied(&def.name));
+                    Decl::Def(def)
+                }
+                // Definitions of a mutual block refer to each other as well.
+                Decl::Mutual(defs) => {
+                    s.extend(
+                        defs.iter()
+                            .map(|def| (def.name.to_owned(), self.applied(&def.name))),
+                    );
+                    Decl::Mutual(defs.iter().map(|def| self.discharge_def(def, &s)).collect())
+                }
+            };
+
+            decls.push((*pos, decl));
+        }
+
+        decls
+    }
+
+    fn discharge_def(&self, def: &Def, s: &HashMap<Identifier, Expr>) -> Def {
+        Def {
+            ty: self.vars.pi(def.ty.instantiate(s)),
+            body: self.vars.lam(def.body.instantiate(s)),
+            ..def.to_owned()
+        }
+    }
+
+    // A definition of the section applied to its variables.
+    fn applied(&self, x: &str) -> Expr {
+        self.vars.apply(Expr::Var(x.to_owned()))
+    }
+}
+
+impl Def {
+    fn check_signature(&self, cx: &Context, d: &Env, g: &Guard) -> Result<Type, Error> {
+        self.ty.check_type(cx, d, g)
+    }
+}
+
+impl Decl {
+    // The definitions of the declaration, in order.
+    pub fn defs(&self) -> &[Def] {
+        match self {
+            Self::Def(def) => slice::from_ref(def),
+            Self::Mutual(defs) => defs,
+        }
+    }
+
+    pub fn check(&self, cx: &mut Context, d: &mut Env) -> Result<(), Error> {
+        self.check_with(cx, d, &Guard::default())
+    }
+
+    pub fn check_with(&self, cx: &mut Context, d: &mut Env, g: &Guard) -> Result<(), Error> {
+        self.elaborate(cx, d, &Hints::new(), g).map(|_| ())
+    }
+
+    // Checks the declaration and adds it to the context and environment,
+    // returning it with the bodies as elaborated.
+    pub fn elaborate(
+        &self,
+        cx: &mut Context,
+        d: &mut Env,
+        cs: &Hints,
+        g: &Guard,
+    ) -> Result<Decl, Error> {
+        match self {
+            Self::Def(def) => {
+                let t = def.check_signature(cx, d, g)?;
+                let body = elab::elaborate_check(&def.body, &t, cx, d, cs, g)?.into_term();
+                let v = body.eval_with(d, g)?;
+                cx.insert(def.name.to_owned(), t);
+                d.define(def.name.to_owned(), v);
+
+                Ok(Self::Def(Def {
+                    body,
+                    ..def.to_owned()
+                }))
+            }
+            Self::Mutual(defs) => check_mutual(defs, cx, d, cs, g).map(Self::Mutual),
+        }
+    }
+
+    // Adds a declaration checked before to the context and environment
+    // without checking it again. Types keep the globals they mention folded,
+    // as they do when checked.
+    pub fn load(&self, cx: &mut Context, d: &mut Env, g: &Guard) -> Result<(), Error> {
+        match self {
+            Self::Def(def) => {
+                let t = def.ty.eval_with(&d.gluing(), g)?;
+                let v = def.body.eval_with(d, g)?;
+                cx.insert(def.name.to_owned(), t);
+                d.define(def.name.to_owned(), v);
+            }
+            Self::Mutual(defs) => {
+                let ts = defs
+                    .iter()
+                    .map(|def| def.ty.eval_with(&d.gluing(), g))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                define_components(defs, d, g)?;
+                cx.extend(defs.iter().map(|def| def.name.to_owned()).zip(ts));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Signatures are checked first, against the declarations preceding the
+// block, and the bodies second, with every signature of the block in scope.
+// Recursive definitions must pass the termination checker, and occurrences
+// of a definition inside its own dependency cycle only unfold where the
+// argument it recurses on is a constructor.
+fn check_mutual(
+    defs: &[Def],
+    cx: &mut Context,
+    d: &mut Env,
+    cs: &Hints,
+    g: &Guard,
+) -> Result<Vec<Def>, Error> {
+    let mut ts = vec![];
+
+    for (k, def) in defs.iter().enumerate() {
+        if defs[..k].iter().any(|def_| def_.name == def.name) {
+            return Err("duplicate definition".into());
+        }
+
+        ts.push(def.check_signature(cx, d, g)?);
+    }
+
+    let mut cx_ = cx.to_owned();
+    cx_.extend(
+        defs.iter()
+            .map(|def| def.name.to_owned())
+            .zip(ts.iter().cloned()),
+    );
+
+    let defs = defs
+        .iter()
+        .zip(&ts)
+        .map(|(def, t)| {
+            Ok(Def {
+                body: elab::elaborate_check(&def.body, t, &cx_, d, cs, g)?.into_term(),
+                ..def.to_owned()
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    define_components(&defs, d, g)?;
+    *cx = cx_;
+    Ok(defs)
+}
+
+// The block is defined only once all of it passes, so that a block failing
+// partway defines nothing.
+fn define_components(defs: &[Def], d: &mut Env, g: &Guard) -> Result<(), Error> {
+    let names = defs
+        .iter()
+        .map(|def| def.name.to_owned())
+        .collect::<Vec<_>>();
+
+    let refers = |k: usize, j: usize| defs[k].body.occurs(&defs[j].name);
+
+    let vs = fixpoints(&names, refers, &d.names(), g, |k, vs| {
+        let mut d_ = d.to_owned();
+
+        for (x, v) in names.iter().zip(vs) {
+            d_.insert(x.to_owned(), v.to_owned());
+        }
+
+        defs[k].body.eval_with(&d_, g)
+    })?;
+
+    for (x, v) in names.iter().zip(vs) {
+        d.define(x.to_owned(), v);
+    }
+
+    Ok(())
+}
+
+// The values of a block of definitions that may refer to each other, in the
+// scope of the names `xs`. `refers(k, j)` tells whether the `k`th refers to
+// the `j`th, and `eval(k, vs)` evaluates the `k`th given the values of the
+// block so far. Definitions are evaluated a dependency cycle at a time, in
+// dependency order. Within a cycle, they refer to each other as fixpoints,
+// which unfold once the cycle is known to terminate.
+pub(crate) fn fixpoints(
+    names: &[Identifier],
+    refers: impl Fn(usize, usize) -> bool,
+    xs: &HashSet<&str>,
+    g: &Guard,
+    mut eval: impl FnMut(usize, &[Value]) -> Result<Value, Error>,
+) -> Result<Vec<Value>, Error> {
+    let fs = names
+        .iter()
+        .map(|x| Fix::new(&freshen(x.to_owned(), xs)))
+        .collect::<Vec<_>>();
+
+    let mut vs = fs
+        .iter()
+        .map(|f| Value::Neutral(Neutral::Fix(f.to_owned())))
+        .collect::<Vec<_>>();
+
+    let mut ys = xs.to_owned();
+    ys.extend(fs.iter().map(|f| f.x.as_str()));
+
+    for c in components(names.len(), &refers) {
+        let vs_ = c
+            .iter()
+            .map(|&k| eval(k, &vs))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if c.len() > 1 || refers(c[0], c[0]) {
+            let names_ = c.iter().map(|&k| fs[k].x.to_owned()).collect::<Vec<_>>();
+
+            let es = vs_
+                .iter()
+                .map(|v| v.quote_with(&ys, g))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if let Some(j) = sized::check_termination(&names_, &es)? {
+                for (&k, v) in c.iter().zip(&vs_) {
+                    fs[k].define(j, v.to_owned());
+                }
+            }
+        }
+
+        for (k, v) in c.into_iter().zip(vs_) {
+            vs[k] = v;
+        }
+    }
+
+    Ok(vs)
+}
+
+// Strongly connected components of the reference graph of a block of `n`
+// definitions, in dependency order (Tarjan).
+fn components(n: usize, refers: impl Fn(usize, usize) -> bool) -> Vec<Vec<usize>> {
+    struct State {
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        next: usize,
+        cs: Vec<Vec<usize>>,
+    }
+
+    fn visit(k: usize, edges: &[Vec<usize>], s: &mut State) {
+        let i = s.next;
+        s.next += 1;
+        s.index[k] = Some(i);
+        s.low[k] = i;
+        s.stack.push(k);
+        s.on_stack[k] = true;
+
+        for &j in &edges[k] {
+            match s.index[j] {
+                None => {
+                    visit(j, edges, s);
+                    s.low[k] = s.low[k].min(s.low[j]);
+                }
+                Some(i_) if s.on_stack[j] => s.low[k] = s.low[k].min(i_),
+                _ => {}
+            }
+        }
+
+        if s.low[k] == i {
+            let mut c = vec![];
+
+            loop {
+                let j = s.stack.pop().unwrap();
+                s.on_stack[j] = false;
+                c.push(j);
+
+                if j == k {
+                    break;
+                }
+            }
+
+            c.reverse();
+            s.cs.push(c);
+        }
+    }
+
+    let edges = (0..n)
+        .map(|k| (0..n).filter(|&j| refers(k, j)).collect())
+        .collect::<Vec<_>>();
+
+    let mut s = State {
+        index: vec![None; n],
+        low: vec![0; n],
+        stack: vec![],
+        on_stack: vec![false; n],
+        next: 0,
+        cs: vec![],
+    };
+
+    for k in 0..n {
+        if s.index[k].is_none() {
+            visit(k, &edges, &mut s);
+        }
+    }
+
+    s.cs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::driver::Driver;
+
+    fn var(x: &str) -> Box<Expr> {
+        Box::new(Expr::Var(x.to_string()))
+    }
+
+    fn def(name: &str, body: Expr) -> Def {
+        Def {
+            name: name.to_string(),
+            ty: Expr::Fun(var("A"), var("A")),
+            body,
+            doc: None,
+            comments: vec![],
+            opaque: false,
+            private: false,
+        }
+    }
+
+    #[test]
+    fn mutual_definitions_work() {
+        let mut cx = Context::new();
+        cx.insert("A".to_string(), Value::U(0));
+        let mut d = Env::new();
+
+        // f := \x. g x; g := \x. x; h := \x. h x
+        let f = def(
+            "f",
+            Expr::Lam(
+                "x".to_string(),
+                None,
+                Box::new(Expr::App(var("g"), var("x"))),
+            ),
+        );
+
+        let g = def("g", Expr::Lam("x".to_string(), None, var("x")));
+
+        let h = def(
+            "h",
+            Expr::Lam(
+                "x".to_string(),
+                None,
+                Box::new(Expr::App(var("h"), var("x"))),
+            ),
+        );
+
+        let e = Decl::Mutual(vec![f.to_owned(), g.to_owned(), h]).check(&mut cx, &mut d);
+        assert_eq!(e, Err("cannot show that recursion terminates".into()));
+        assert!(d.global("f").is_none() && d.global("g").is_none());
+
+        Decl::Mutual(vec![f, g]).check(&mut cx, &mut d).unwrap();
+
+        let xs = HashSet::new();
+        assert_eq!(
+            d["f"].quote(&xs),
+            Expr::Lam("x".to_string(), None, var("x"))
+        );
+
+        let e = Decl::Def(def(
+            "k",
+            Expr::Lam(
+                "x".to_string(),
+                None,
+                Box::new(Expr::App(var("k"), var("x"))),
+            ),
+        ));
+
+        assert_eq!(e.check(&mut cx, &mut d), Err("unknown identifier".into()));
+    }
+
+    #[test]
+    fn recursive_definitions_compute() {
+        let src = "mutual
+def len : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. int_add 1 (len t))
+end
+#eval len (cons 1 (cons 2 (cons 3 nil)))
+#eval \\(xs : List Int). len (cons 1 xs)";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(driver.out[0].1, "3");
+        assert_eq!(driver.out[1].1, "\\xs. int_add 1 (len xs)");
+    }
+}
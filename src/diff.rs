@@ -0,0 +1,152 @@
+use std::{collections::HashMap, fmt, mem};
+
+use crate::Expr;
+
+// A subterm of the old term replaced by one of the new term. The path leads
+// from the root to the subterm, by the index of the child taken at each step,
+// counting children in the order the zipper visits them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub path: Vec<usize>,
+    pub old: Expr,
+    pub new: Expr,
+}
+
+// The smallest subterms that differ between two terms, up to the names of
+// bound variables. It is empty exactly when the terms are alpha-equivalent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TermDiff {
+    pub changes: Vec<Change>,
+}
+
+impl TermDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for TermDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, c) in self.changes.iter().enumerate() {
+            if k > 0 {
+                writeln!(f)?;
+            }
+
+            if c.path.is_empty() {
+                write!(f, "at the root: ")?;
+            } else {
+                let path = c.path.iter().map(usize::to_string).collect::<Vec<_>>();
+                write!(f, "at {}: ", path.join("."))?;
+            }
+
+            write!(f, "{} ~> {}", c.old, c.new)?;
+        }
+
+        Ok(())
+    }
+}
+
+// The children of a term, each with the variable it binds if any. The body
+// of a let block binds several, which are left out, so it is compared as a
+// whole, and so are the bindings of a recursive one.
+pub(crate) fn children(e: &Expr) -> Vec<(&Expr, Option<&str>)> {
+    match e {
+        Expr::App(e_1, e_2) | Expr::Cons(e_1, e_2) | Expr::Fun(e_1, e_2) | Expr::Inst(e_1, e_2) => {
+            vec![(e_1, None), (e_2, None)]
+        }
+        Expr::Lam(x, None, e) => vec![(e, Some(x))],
+        Expr::Lam(x, Some(a), e) => vec![(a, None), (e, Some(x))],
+        Expr::Let(bs, e) => bs
+            .iter()
+            .map(|(_, e)| (e, None))
+            .chain([(&**e, None)])
+            .collect(),
+        Expr::LetRec(bs, e) => bs
+            .iter()
+            .flat_map(|(_, a, e)| [(a, None), (e, None)])
+            .chain([(&**e, None)])
+            .collect(),
+        Expr::List(e) | Expr::Quote(e) | Expr::Splice(e) => vec![(e, None)],
+        Expr::ListRec(e_1, e_2, e_3) => vec![(e_1, None), (e_2, None), (e_3, None)],
+        Expr::Pi(x, e_1, e_2) | Expr::Sub(x, e_1, e_2) => vec![(e_1, None), (e_2, Some(x))],
+        _ => vec![],
+    }
+}
+
+impl Expr {
+    pub fn diff(&self, other: &Self) -> TermDiff {
+        let mut d = TermDiff::default();
+        let xs = HashMap::new();
+        diff_in(self, other, &mut vec![], 0, &xs, &xs, &mut d);
+        d
+    }
+}
+
+fn diff_in<'a>(
+    e_1: &'a Expr,
+    e_2: &'a Expr,
+    path: &mut Vec<usize>,
+    i: usize,
+    xs: &HashMap<&'a str, usize>,
+    ys: &HashMap<&'a str, usize>,
+    d: &mut TermDiff,
+) {
+    let (es_1, es_2) = (children(e_1), children(e_2));
+
+    // Lambdas with and without an annotation have a different number of
+    // children, so they are replaced as a whole, and so are let blocks.
+    if es_1.is_empty()
+        || matches!(e_1, Expr::Let(_, _) | Expr::LetRec(_, _))
+        || mem::discriminant(e_1) != mem::discriminant(e_2)
+        || es_1.len() != es_2.len()
+    {
+        if !e_1.alpha_eq(e_2, i, xs, ys) {
+            d.changes.push(Change {
+                path: path.to_owned(),
+                old: e_1.to_owned(),
+                new: e_2.to_owned(),
+            });
+        }
+
+        return;
+    }
+
+    for (k, ((e_1, x), (e_2, y))) in es_1.into_iter().zip(es_2).enumerate() {
+        path.push(k);
+
+        match (x, y) {
+            (Some(x), Some(y)) => {
+                let mut xs_ = xs.to_owned();
+                xs_.insert(x, i);
+                let mut ys_ = ys.to_owned();
+                ys_.insert(y, i);
+                diff_in(e_1, e_2, path, i + 1, &xs_, &ys_, d);
+            }
+            _ => diff_in(e_1, e_2, path, i, xs, ys, d),
+        }
+
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{notation::Notations, parser::parse_expr};
+
+    #[test]
+    fn diffs_are_minimal_up_to_renaming() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+
+        let d = parse("\\x y. f x (g y)").diff(&parse("\\a b. f a (g b)"));
+        assert!(d.is_empty());
+
+        let d = parse("\\x y. f x (g y)").diff(&parse("\\a b. f b (g b)"));
+        assert_eq!(d.to_string(), "at 0.0.0.1: x ~> b");
+
+        let d = parse("cons 1 (cons 2 nil)").diff(&parse("cons 1 (cons 3 (cons 4 nil))"));
+        assert_eq!(d.to_string(), "at 1.0: 2 ~> 3\nat 1.1: nil ~> cons 4 nil");
+
+        let d = parse("\\x. x").diff(&parse("\\(x : Int). x"));
+        assert_eq!(d.to_string(), "at the root: \\x. x ~> \\(x : Int). x");
+    }
+}
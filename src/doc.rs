@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use crate::{
+    decl::{Decl, Def},
+    notation::Notations,
+    parser::{parse_program, Item, ParseError},
+    pretty::escape_html,
+};
+
+struct Module {
+    name: String,
+    ns: Notations,
+    defs: Vec<Def>,
+}
+
+fn anchor(module: &str, x: &str) -> String {
+    format!("{module}.{x}")
+}
+
+// Renders one HTML page documenting the definitions of the given modules,
+// given as pairs of module name and source. References to definitions are
+// resolved in the referring module first, then in the others in order.
+pub fn render_html(sources: &[(String, String)]) -> Result<String, (String, ParseError)> {
+    let mut modules = vec![];
+
+    for (name, src) in sources {
+        let mut ns = Notations::new();
+        let items = parse_program(src, &mut ns).map_err(|e| (name.to_owned(), e))?;
+        let mut defs = vec![];
+
+        for (_, item) in items {
+            match item {
+                Item::Decl(Decl::Def(def)) => defs.push(def),
+                Item::Decl(Decl::Mutual(defs_)) => defs.extend(defs_),
+                Item::Section(s) => {
+                    for (_, decl) in s.discharge() {
+                        match decl {
+                            Decl::Def(def) => defs.push(def),
+                            Decl::Mutual(defs_) => defs.extend(defs_),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        defs.retain(|def| !def.private);
+
+        modules.push(Module {
+            name: name.to_owned(),
+            ns,
+            defs,
+        });
+    }
+
+    let mut index = HashMap::<&str, Vec<&str>>::new();
+
+    for m in &modules {
+        for def in &m.defs {
+            index.entry(&def.name).or_default().push(&m.name);
+        }
+    }
+
+    let mut a = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Documentation</title>\n<style>\n.binder { color: purple; }\n.keyword { color: navy; font-weight: bold; }\n.literal { color: green; }\n.operator { color: olive; }\n.type { color: teal; }\n.universe { color: teal; font-weight: bold; }\n</style>\n</head>\n<body>\n<nav>\n<ul>\n",
+    );
+
+    for m in &modules {
+        let name = escape_html(&m.name);
+        a.push_str(&format!("<li><a href=\"#{name}\">{name}</a></li>\n"));
+    }
+
+    a.push_str("</ul>\n</nav>\n");
+
+    for m in &modules {
+        let links = |x: &str| {
+            let ms = index.get(x)?;
+
+            let module = ms
+                .iter()
+                .find(|&&module| module == m.name)
+                .unwrap_or(&ms[0]);
+
+            Some(anchor(module, x))
+        };
+
+        let name = escape_html(&m.name);
+        a.push_str(&format!("<section id=\"{name}\">\n<h2>{name}</h2>\n"));
+
+        for def in &m.defs {
+            a.push_str(&format!(
+                "<div class=\"def\" id=\"{}\">\n<pre><code>def <b>{}</b> : {}</code></pre>\n",
+                escape_html(&anchor(&m.name, &def.name)),
+                escape_html(&def.name),
+                def.ty.pretty(&m.ns).unicode(true).html(&links)
+            ));
+
+            for p in def.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+                a.push_str(&format!("<p>{}</p>\n", escape_html(p)));
+            }
+
+            a.push_str("</div>\n");
+        }
+
+        a.push_str("</section>\n");
+    }
+
+    a.push_str("</body>\n</html>\n");
+    Ok(a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doc_generation_works() {
+        let a = render_html(&[
+            (
+                "list".to_string(),
+                "--| Lists of integers.\n--|\n--| Used everywhere.\ndef Ints : U 0 := List Int"
+                    .to_string(),
+            ),
+            (
+                "main".to_string(),
+                "def sum : Ints -> Int := \\xs. ListRec xs 0 (\\h t r. int_add h r)".to_string(),
+            ),
+        ])
+        .unwrap();
+
+        assert!(a.contains("<div class=\"def\" id=\"list.Ints\">\n<pre><code>def <b>Ints</b> : <span class=\"universe\">U 0</span></code></pre>\n<p>Lists of integers.</p>\n<p>Used everywhere.</p>"));
+        assert!(a.contains("def <b>sum</b> : <a href=\"#list.Ints\">Ints</a> <span class=\"type\">→</span> <span class=\"type\">Int</span>"));
+        assert!(a.contains("<li><a href=\"#main\">main</a></li>"));
+    }
+}
@@ -0,0 +1,1079 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    decl::{Decl, Def},
+    elab::{self, Hints},
+    interface::{Interface, Signature},
+    lexer::Pos,
+    lint::{lint_def, Severity, Warning},
+    macros::Macros,
+    notation::Notations,
+    options::{CheckOptions, Guard},
+    parser::{
+        parse_header, parse_interface, parse_program, parse_program_recovering, Command, Item,
+        ParseError,
+    },
+    scope::Scope,
+    store::{Hash, Store},
+    Context, Env, Error, Expr, Identifier,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub pos: Pos,
+    pub message: Error,
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(e: ParseError) -> Self {
+        Self {
+            pos: e.pos,
+            message: e.message.into(),
+        }
+    }
+}
+
+// What checking a declaration took.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub pos: Pos,
+    // The names the declaration defines.
+    pub names: Vec<Identifier>,
+    pub time: Duration,
+    pub conversions: usize,
+    // The comparisons of types the cache answered, and those it had to
+    // leave to unfolding.
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    pub unfoldings: usize,
+    // The evaluations of let bindings saved by sharing their values.
+    pub shared: usize,
+    // The size of the largest type compared or normal form shown.
+    pub peak_size: usize,
+}
+
+// Shared, like everything else a driver holds, so that a checked
+// environment can be queried from several threads.
+pub type Loader = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+// Modules shipped with saida, checked when they are imported.
+const BUILTINS: [(&str, &str); 1] = [(
+    "Saida.Extensionality",
+    include_str!("builtins/extensionality.sd"),
+)];
+
+fn builtin(x: &str) -> Option<&'static str> {
+    BUILTINS.iter().find(|(y, _)| x == *y).map(|(_, src)| *src)
+}
+
+// Whether two declarations are written the same, as far as their
+// fingerprints tell, up to the names of bound variables.
+fn written_alike(defs_1: &[Def], defs_2: &[Def]) -> bool {
+    defs_1.len() == defs_2.len()
+        && defs_1.iter().zip(defs_2).all(|(def_1, def_2)| {
+            def_1.name == def_2.name
+                && def_1.doc == def_2.doc
+                && def_1.opaque == def_2.opaque
+                && def_1.private == def_2.private
+                && def_1.ty == def_2.ty
+                && def_1.body == def_2.body
+        })
+}
+
+#[derive(Clone, Default)]
+pub struct Driver {
+    pub cx: Context,
+    pub d: Env,
+    pub ns: Notations,
+    pub macros: Macros,
+    pub defs: BTreeMap<Identifier, Def>,
+    pub store: Store,
+    pub out: Vec<(Pos, String)>,
+    // One entry per declaration checked, in order.
+    pub profile: Vec<Profile>,
+    pub warnings: Vec<Warning>,
+    pub axioms: BTreeSet<Identifier>,
+    pub ansi: bool,
+    pub options: CheckOptions,
+    pub hints: Hints,
+    // Reads the interface file of an imported module.
+    pub loader: Option<Loader>,
+    // The qualified names defined by every loaded module.
+    pub exports: BTreeMap<Identifier, Vec<Identifier>>,
+    pub aliases: BTreeMap<Identifier, Identifier>,
+    pub scope: Scope,
+    pub interface: Interface,
+    // Declarations by fingerprint, as written and as elaborated, carried
+    // over from a previous run to skip checking those that did not change.
+    pub cache: HashMap<Hash, (Decl, Decl)>,
+    pub reused: usize,
+}
+
+impl Driver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Checks the declarations of a source file in order and runs its
+    // commands, collecting their output with the command's location. Stops
+    // at the first error.
+    pub fn run(&mut self, src: &str) -> Result<(), Diagnostic> {
+        self.load_imports(src)?;
+        let items = parse_program(src, &mut self.ns)?;
+        self.run_items(items)
+    }
+
+    // Checks items built rather than parsed, as `run` does those of a
+    // source file. Their positions are only used to report errors.
+    pub fn run_items(&mut self, items: Vec<(Pos, Item)>) -> Result<(), Diagnostic> {
+        let g = Guard::new(self.options.to_owned());
+
+        for (pos, item) in items {
+            self.item(pos, item, &g)
+                .map_err(|message| Diagnostic { pos, message })?;
+        }
+
+        Ok(())
+    }
+
+    // Checks a source file like `run`, but carries on past errors and
+    // returns all of them in order. Syntax errors are recovered from at the
+    // next declaration: a definition whose body did not parse is assumed to
+    // have its type, and one whose type did not is skipped, like a command
+    // that did not parse.
+    pub fn check(&mut self, src: &str) -> Vec<Diagnostic> {
+        if let Err(e) = self.load_imports(src) {
+            return vec![e];
+        }
+
+        let (items, es) = parse_program_recovering(src, &mut self.ns);
+        let mut es = es.into_iter().map(Diagnostic::from).collect::<Vec<_>>();
+        let g = Guard::new(self.options.to_owned());
+
+        for (pos, item) in items {
+            if let Err(message) = self.item(pos, item, &g) {
+                es.push(Diagnostic { pos, message });
+            }
+        }
+
+        es.sort_by_key(|e| e.pos);
+        es
+    }
+
+    // Checks several source files like `check`, each in a driver of its own,
+    // on as many threads as there are cores. Files sharing a prelude get
+    // drivers cloned from one that checked it.
+    pub fn check_all(jobs: &mut [(Driver, &str)]) -> Vec<Vec<Diagnostic>> {
+        let mut es = vec![vec![]; jobs.len()];
+        let n = thread::available_parallelism().map_or(1, usize::from);
+        let size = jobs.len().div_ceil(n).max(1);
+
+        thread::scope(|s| {
+            for (jobs, es) in jobs.chunks_mut(size).zip(es.chunks_mut(size)) {
+                s.spawn(|| {
+                    for ((driver, src), es) in jobs.iter_mut().zip(es) {
+                        *es = driver.check(src);
+                    }
+                });
+            }
+        });
+
+        es
+    }
+
+    // Loads the modules a source file imports, bringing their names and
+    // notations into scope.
+    pub fn load_imports(&mut self, src: &str) -> Result<(), Diagnostic> {
+        let h = parse_header(src)?;
+        self.interface.module = h.module;
+
+        for (pos, i) in h.imports {
+            self.load_import(&i.module, i.alias)
+                .map_err(|message| Diagnostic { pos, message })?;
+        }
+
+        Ok(())
+    }
+
+    fn load_import(&mut self, m: &str, alias: Option<Identifier>) -> Result<(), Error> {
+        self.import(m, &mut vec![])?;
+        let alias = alias.unwrap_or_else(|| m.to_owned());
+
+        for (x, y) in self.names(m) {
+            self.scope.add(format!("{alias}.{x}"), y);
+        }
+
+        self.hints.overloads = self.scope.overloads();
+        self.aliases.insert(alias, m.to_owned());
+        self.interface.imports.push(m.to_owned());
+        Ok(())
+    }
+
+    fn item(&mut self, pos: Pos, item: Item, g: &Guard) -> Result<(), Error> {
+        match item {
+            Item::Command(Command::Check(e) | Command::Eval(e)) if e.has_errors() => {}
+            Item::Command(c) => {
+                let text = self.command(&c, g)?;
+                self.out.push((pos, text));
+            }
+            Item::Decl(decl) => {
+                let names = match &decl {
+                    Decl::Def(def) => vec![def.name.to_owned()],
+                    Decl::Mutual(defs) => defs.iter().map(|def| def.name.to_owned()).collect(),
+                };
+
+                let (start, conversions, hits, misses, unfoldings, shared) = (
+                    Instant::now(),
+                    g.conversions(),
+                    g.cache_hits(),
+                    g.cache_misses(),
+                    g.unfoldings(),
+                    g.shared(),
+                );
+                g.take_peak_size();
+                let result = self.decl(pos, decl, g);
+                g.clear_cache();
+
+                self.profile.push(Profile {
+                    pos,
+                    names,
+                    time: start.elapsed(),
+                    conversions: g.conversions() - conversions,
+                    cache_hits: g.cache_hits() - hits,
+                    cache_misses: g.cache_misses() - misses,
+                    unfoldings: g.unfoldings() - unfoldings,
+                    shared: g.shared() - shared,
+                    peak_size: g.take_peak_size(),
+                });
+
+                result?;
+            }
+            // Axioms are exported as opaque definitions, listed as axioms so
+            // that importing modules keep track of them.
+            Item::Axiom(_, t) if t.has_errors() => {}
+            Item::Axiom(x, t) => {
+                let s = Signature {
+                    name: self.qualify(&x),
+                    ty: t.expand(&self.macros)?.resolve(&self.scope)?,
+                    body: None,
+                };
+
+                s.load(&mut self.cx, &mut self.d)?;
+                self.define(&s.name);
+                self.axioms.insert(s.name.to_owned());
+                self.interface.axioms.push(s.name.to_owned());
+                self.interface.sigs.push(s);
+            }
+            Item::Coercion(x) => {
+                let x = self.scope.resolve(&x)?.unwrap_or(&x).to_owned();
+                let t = self.cx.get(&x).ok_or("unknown identifier")?;
+                self.hints.coercions.insert(x.to_owned(), t)?;
+                self.interface.coercions.push(x);
+            }
+            Item::Instance(x) => {
+                let x = self.scope.resolve(&x)?.unwrap_or(&x).to_owned();
+                let t = self.cx.get(&x).ok_or("unknown identifier")?;
+                self.hints.instances.insert(x.to_owned(), t);
+                self.interface.instances.push(x);
+            }
+            Item::Comment(_) | Item::Import(_) | Item::Module(_) => {}
+            // Macros are expanded when they are declared, so the ones they
+            // use are those declared before them.
+            Item::Macro(mut m) => {
+                m.body = m.body.expand(&self.macros)?;
+                self.macros.insert(m.name.to_owned(), m);
+            }
+            Item::Notation(mut n) => {
+                if let Some(y) = self.scope.resolve(&n.name)? {
+                    n.name = y.to_owned();
+                }
+
+                self.ns.insert(n.to_owned());
+                self.interface.notations.push(n);
+            }
+            Item::Section(s) => {
+                for (pos, decl) in s.discharge() {
+                    self.item(pos, Item::Decl(decl), g)?;
+                }
+            }
+            Item::Open(x) => {
+                let m = self.aliases.get(&x).ok_or("unknown module")?;
+
+                for (x, y) in self.names(m) {
+                    self.scope.add(x, y);
+                }
+
+                self.hints.overloads = self.scope.overloads();
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checks a declaration, unless it is in the cache, and brings it into
+    // scope.
+    fn decl(&mut self, pos: Pos, decl: Decl, g: &Guard) -> Result<(), Error> {
+        let decl = self.resolve(decl)?;
+        let defs = decl.defs();
+
+        if defs
+            .iter()
+            .any(|def| !def.private && self.private(&def.ty, defs))
+        {
+            return Err("private definition in public type".into());
+        }
+
+        if defs.iter().any(|def| def.ty.has_errors()) {
+            return Ok(());
+        }
+
+        for def in defs {
+            for (l, message) in lint_def(def, &self.cx, &self.axioms) {
+                match self.options.lints.get(l) {
+                    Severity::Allow => {}
+                    Severity::Warn => self.warnings.push(Warning {
+                        pos,
+                        lint: l,
+                        message,
+                    }),
+                    Severity::Deny => return Err(l.error()),
+                }
+            }
+        }
+
+        // Bodies that did not parse are left out, as opaque ones are
+        // from an interface.
+        if defs.iter().any(|def| def.body.has_errors()) {
+            for def in defs {
+                let mut s = Signature::from(def);
+                s.body = None;
+                s.load(&mut self.cx, &mut self.d)?;
+                self.define(&def.name);
+            }
+
+            return Ok(());
+        }
+
+        let hints = self.hints.coercions.iter().map(|c| c.name.as_str());
+        let hints = hints.chain(self.hints.instances.iter().map(String::as_str));
+        let hints = hints.chain(self.hints.overloads.values().flatten().map(String::as_str));
+        let fp = self
+            .store
+            .fingerprint(defs, hints, &self.options, &self.cx, &self.d);
+
+        // A hit is only taken for the declaration it was stored for, which
+        // another one may share the fingerprint with.
+        let elaborated = match self.cache.get(&fp) {
+            Some((source, elaborated)) if written_alike(source.defs(), defs) => {
+                elaborated.load(&mut self.cx, &mut self.d, g)?;
+                self.reused += 1;
+                elaborated.to_owned()
+            }
+            _ => decl.elaborate(&mut self.cx, &mut self.d, &self.hints, g)?,
+        };
+
+        self.cache.insert(fp, (decl, elaborated.to_owned()));
+        let defs = elaborated.defs();
+
+        for def in defs {
+            self.define(&def.name);
+            self.store.insert(def);
+
+            // Importing modules cannot unfold a body mentioning
+            // private definitions, so it is exported as opaque.
+            if !def.private {
+                let mut s = Signature::from(def);
+
+                if s.body.as_ref().is_some_and(|e| self.private(e, defs)) {
+                    s.body = None;
+                }
+
+                self.interface.sigs.push(s);
+            }
+        }
+
+        self.defs
+            .extend(defs.iter().map(|def| (def.name.to_owned(), def.to_owned())));
+
+        Ok(())
+    }
+
+    // Loads the interface of a module and, first, those it imports, with
+    // `stack` holding the modules being loaded to detect cycles.
+    fn import(&mut self, x: &str, stack: &mut Vec<Identifier>) -> Result<(), Error> {
+        if self.exports.contains_key(x) {
+            return Ok(());
+        }
+
+        if stack.iter().any(|y| y == x) {
+            return Err("import cycle".into());
+        }
+
+        let src = match builtin(x) {
+            Some(src) => {
+                let mut m = Driver::new();
+                m.run(src).map_err(|e| e.message)?;
+                m.interface.to_string()
+            }
+            None => self
+                .loader
+                .as_ref()
+                .and_then(|load| load(x))
+                .ok_or("unknown module")?,
+        };
+
+        let i = parse_interface(&src).map_err(|e| e.message)?;
+
+        if i.module.as_ref().is_some_and(|y| y != x) {
+            return Err("interface does not match module".into());
+        }
+
+        stack.push(x.to_owned());
+
+        for y in &i.imports {
+            self.import(y, stack)?;
+        }
+
+        stack.pop();
+
+        let ys = self.load_interface(i)?;
+        self.exports.insert(x.to_owned(), ys);
+        Ok(())
+    }
+
+    // Brings the notations, definitions and hints of an interface into
+    // scope, returning the names it defines.
+    fn load_interface(&mut self, i: Interface) -> Result<Vec<Identifier>, Error> {
+        for n in i.notations {
+            self.ns.insert(n);
+        }
+
+        for s in &i.sigs {
+            s.load(&mut self.cx, &mut self.d)?;
+        }
+
+        for x in i.coercions {
+            let t = self.cx.get(&x).ok_or("unknown identifier")?;
+            self.hints.coercions.insert(x, t)?;
+        }
+
+        for x in i.instances {
+            let t = self.cx.get(&x).ok_or("unknown identifier")?;
+            self.hints.instances.insert(x, t);
+        }
+
+        self.axioms.extend(i.axioms);
+        Ok(i.sigs.into_iter().map(|s| s.name).collect())
+    }
+
+    // The names defined by a loaded module, unqualified and qualified.
+    fn names(&self, m: &str) -> Vec<(Identifier, Identifier)> {
+        let prefix = format!("{m}.");
+
+        self.exports[m]
+            .iter()
+            .map(|y| {
+                (
+                    y.strip_prefix(&prefix).unwrap_or(y).to_owned(),
+                    y.to_owned(),
+                )
+            })
+            .collect()
+    }
+
+    fn private(&self, e: &Expr, defs: &[Def]) -> bool {
+        self.defs
+            .values()
+            .chain(defs)
+            .any(|def| def.private && e.occurs(&def.name))
+    }
+
+    fn qualify(&self, x: &str) -> Identifier {
+        match &self.interface.module {
+            Some(m) => format!("{m}.{x}"),
+            None => x.to_owned(),
+        }
+    }
+
+    fn define(&mut self, y: &str) {
+        if let Some(x) = y.rsplit_once('.').map(|(_, x)| x.to_owned()) {
+            self.hints.overloads.remove(&x);
+            self.scope.define(x, y.to_owned());
+        }
+
+        self.hints.overloads.remove(y);
+        self.scope.define(y.to_owned(), y.to_owned());
+    }
+
+    // Qualifies the names a declaration defines and resolves the ones it
+    // refers to, with every definition of a mutual block in scope.
+    fn resolve(&self, decl: Decl) -> Result<Decl, Error> {
+        let mut scope = self.scope.to_owned();
+
+        let resolve = |def: Def, scope: &Scope| -> Result<Def, Error> {
+            Ok(Def {
+                name: self.qualify(&def.name),
+                ty: def.ty.expand(&self.macros)?.resolve(scope)?,
+                body: def.body.expand(&self.macros)?.resolve_overloaded(scope)?,
+                ..def
+            })
+        };
+
+        match decl {
+            Decl::Def(def) => Ok(Decl::Def(resolve(def, &scope)?)),
+            Decl::Mutual(defs) => {
+                for def in &defs {
+                    scope.define(def.name.to_owned(), self.qualify(&def.name));
+                }
+
+                let defs = defs
+                    .into_iter()
+                    .map(|def| resolve(def, &scope))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Decl::Mutual(defs))
+            }
+        }
+    }
+
+    pub(crate) fn command(&self, c: &Command, g: &Guard) -> Result<String, Error> {
+        let xs = self.cx.keys().map(String::as_str).collect::<HashSet<_>>();
+
+        match c {
+            Command::Check(e) => {
+                let e = e.expand(&self.macros)?.resolve_overloaded(&self.scope)?;
+                let t = elab::elaborate_infer(&e, &self.cx, &self.d, &self.hints, g)?
+                    .ty()
+                    .quote_with(&xs, g)?;
+                g.check_size(&t)?;
+
+                Ok(format!(
+                    "{} : {}",
+                    e.pretty(&self.ns).ansi(self.ansi),
+                    t.pretty(&self.ns).ansi(self.ansi)
+                ))
+            }
+            Command::Eval(e) => {
+                let e = e.expand(&self.macros)?.resolve_overloaded(&self.scope)?;
+                let e = elab::elaborate_infer(&e, &self.cx, &self.d, &self.hints, g)?.into_term();
+                let e_ = e.eval_with(&self.d, g)?.quote_with(&xs, g)?;
+                g.check_size(&e_)?;
+                Ok(e_.pretty(&self.ns).ansi(self.ansi).to_string())
+            }
+            Command::Print(x) => {
+                let x = self.scope.resolve(x)?.unwrap_or(x);
+                let def = self.defs.get(x).ok_or("unknown definition")?;
+
+                Ok(format!(
+                    "def {} : {} := {}",
+                    def.name,
+                    def.ty.pretty(&self.ns).ansi(self.ansi),
+                    def.body.pretty(&self.ns).ansi(self.ansi)
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::options::CancelToken;
+
+    use super::*;
+
+    #[test]
+    fn commands_work() {
+        let src = "infixl 6 _+_ := int_add
+def twice : (Int -> Int) -> Int -> Int := \\f x. f (f x)
+#check twice
+#eval twice (\\x. x + 1) 40
+#print twice
+#eval twice 1";
+
+        let mut driver = Driver::new();
+        let e = driver.run(src).unwrap_err();
+        assert_eq!(e.pos, Pos { line: 6, column: 1 });
+
+        assert_eq!(
+            driver.out,
+            vec![
+                (
+                    Pos { line: 3, column: 1 },
+                    "twice : (Int -> Int) -> Int -> Int".to_string()
+                ),
+                (Pos { line: 4, column: 1 }, "42".to_string()),
+                (
+                    Pos { line: 5, column: 1 },
+                    "def twice : (Int -> Int) -> Int -> Int := \\(f : Int -> Int) (x : Int). f (f x)"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sections_abstract_over_their_variables() {
+        let src = "section (A : U 0) (x : A)
+def const : A -> A := \\_. x
+def twice : A := const (const x)
+mutual
+def first : List A -> A := \\xs. ListRec xs x (\\h t r. h)
+end
+end
+#check twice
+#eval twice Int 3
+#eval first String \"a\" (cons \"b\" nil)
+def bad : Int := const 1";
+
+        let mut driver = Driver::new();
+        let e = driver.run(src).unwrap_err();
+        assert_eq!(
+            (e.pos.line, e.message.message()),
+            (11, "integer literal checked against a non-integer type")
+        );
+
+        let out = driver
+            .out
+            .iter()
+            .map(|(_, a)| a.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            out,
+            vec!["twice : forall (A : U 0) -> A -> A", "3", "\"b\""]
+        );
+    }
+
+    #[test]
+    fn checking_carries_on_past_errors() {
+        let src = "def one : Int := 1
+def two : Int := one +
+def three : Int := int_add one two
+#eval int_add three \"a\"
+#eval (one
+#eval three";
+
+        let mut driver = Driver::new();
+        let es = driver.check(src);
+
+        assert_eq!(
+            es.iter()
+                .map(|e| (e.pos.line, e.message.message()))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, "unknown operator"),
+                (4, "string literal checked against a non-string type"),
+                (6, "expected `)`")
+            ]
+        );
+
+        assert_eq!(
+            driver.out,
+            vec![(Pos { line: 6, column: 1 }, "int_add 1 two".to_string())]
+        );
+    }
+
+    #[test]
+    fn interfaces_work() {
+        let src = "module Nat
+infixl 6 _+_ := int_add
+def one : Int := 1
+opaque def two : Int := one + one";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+
+        let a = driver.interface.to_string();
+        assert_eq!(
+            a,
+            "module Nat\ninfixl 6 _+_ := int_add\ndef Nat.one : Int := 1\nopaque def Nat.two : Int\n"
+        );
+
+        let mut driver = Driver::new();
+        driver.loader = Some(Arc::new(move |x| (x == "Nat").then(|| a.to_owned())));
+        driver.run("import Nat\n#eval Nat.one + Nat.two").unwrap();
+        assert_eq!(driver.out[0].1, "1 + Nat.two");
+        assert_eq!(driver.interface.imports, vec!["Nat".to_string()]);
+
+        let e = Driver::new().run("import Nat").unwrap_err();
+        assert_eq!(e.message, "unknown module");
+    }
+
+    #[test]
+    fn private_definitions_stay_hidden() {
+        let src = "module M
+private def one : Int := 1
+def two : Int := int_add one one
+def three : Int := 3";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(
+            driver.interface.to_string(),
+            "module M\nopaque def M.two : Int\ndef M.three : Int := 3\n"
+        );
+
+        let e = Driver::new()
+            .run("private def T : U 0 := Int\ndef x : T := 1")
+            .unwrap_err();
+        assert_eq!(e.message, "private definition in public type");
+    }
+
+    #[test]
+    fn names_resolve() {
+        let load = |x: &str| match x {
+            "Data.A" => Some("module Data.A\ndef Data.A.x : Int := 1\n".to_string()),
+            "Data.B" => Some("module Data.B\ndef Data.B.x : Int := 2\n".to_string()),
+            _ => None,
+        };
+
+        let src = "import Data.A as A
+import Data.B
+#eval A.x
+open A
+#eval x
+def y : Int := Data.B.x
+open Data.B
+#eval y
+#eval x";
+
+        let mut driver = Driver::new();
+        driver.loader = Some(Arc::new(load));
+        let e = driver.run(src).unwrap_err();
+        assert_eq!(e.pos, Pos { line: 9, column: 1 });
+        assert_eq!(e.message, "ambiguous name");
+
+        let out = driver.out.into_iter().map(|(_, a)| a).collect::<Vec<_>>();
+        assert_eq!(out, vec!["1", "1", "2"]);
+    }
+
+    #[test]
+    fn ambiguous_names_follow_the_expected_type() {
+        let load = |x: &str| match x {
+            "Data.A" => Some(
+                "module Data.A
+def Data.A.zero : Int := 0
+def Data.A.succ : Int -> Int := \\n. int_add n 1
+"
+                .to_string(),
+            ),
+            "Data.B" => Some(
+                "module Data.B
+def Data.B.zero : String := \"zero\"
+def Data.B.succ : String -> String := \\a. str_append a \"!\"
+"
+                .to_string(),
+            ),
+            _ => None,
+        };
+
+        let src = "import Data.A
+import Data.B
+open Data.A
+open Data.B
+def n : Int := succ (succ zero)
+def s : String := succ zero
+#eval n
+#eval s
+#eval Data.B.zero
+#eval zero";
+
+        let mut driver = Driver::new();
+        driver.loader = Some(Arc::new(load));
+        let e = driver.run(src).unwrap_err();
+        assert_eq!((e.pos.line, e.message), (10, "ambiguous name".into()));
+
+        let out = driver.out.into_iter().map(|(_, a)| a).collect::<Vec<_>>();
+        assert_eq!(out, vec!["2", "\"zero!\"", "\"zero\""]);
+
+        let mut driver = Driver::new();
+        driver.loader = Some(Arc::new(load));
+        let e = driver
+            .run("import Data.A\nimport Data.B\nopen Data.A\nopen Data.B\ndef z : List Int := zero")
+            .unwrap_err();
+        assert_eq!(e.message, "ambiguous name fits no candidate");
+    }
+
+    #[test]
+    fn nested_ambiguous_names_are_pruned() {
+        let load = |x: &str| match x {
+            "Data.A" => Some(
+                "module Data.A
+def Data.A.zero : Int := 0
+def Data.A.succ : Int -> Int := \\n. int_add n 1
+"
+                .to_string(),
+            ),
+            "Data.B" => Some(
+                "module Data.B
+def Data.B.zero : String := \"zero\"
+def Data.B.succ : String -> String := \\a. str_append a \"!\"
+"
+                .to_string(),
+            ),
+            _ => None,
+        };
+
+        let n = "succ (".repeat(16) + "zero" + &")".repeat(16);
+        let src = format!(
+            "import Data.A\nimport Data.B\nopen Data.A\nopen Data.B\ndef n : Int := {n}\n#eval n"
+        );
+
+        let mut driver = Driver::new();
+        driver.loader = Some(Arc::new(load));
+        driver.run(&src).unwrap();
+        assert_eq!(driver.out[0].1, "16");
+
+        // Trying both candidates at every level would take some 2^16
+        // comparisons.
+        assert!(driver.profile.last().unwrap().conversions < 100);
+    }
+
+    #[test]
+    fn extensionality_is_imported() {
+        let src = "import Saida.Extensionality
+open Saida.Extensionality
+def id : Int -> Int := \\x. x
+def p : Eq (Int -> Int) id (\\x. x) := funext Int Int id (\\x. x) (\\x. refl Int x)";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(
+            driver
+                .warnings
+                .iter()
+                .map(|w| w.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["p uses the axiom Saida.Extensionality.funext"]
+        );
+    }
+
+    #[test]
+    fn files_are_checked_in_parallel_after_a_prelude() {
+        let mut prelude = Driver::new();
+        prelude.run("def one : Int := 1").unwrap();
+
+        let srcs = (0..8)
+            .map(|n| format!("def x : Int := int_add one {n}\n#eval x"))
+            .chain(["def y : String := one".to_owned()])
+            .collect::<Vec<_>>();
+        let mut jobs = srcs
+            .iter()
+            .map(|src| (prelude.to_owned(), src.as_str()))
+            .collect::<Vec<_>>();
+        let es = Driver::check_all(&mut jobs);
+
+        for (n, (driver, _)) in jobs.iter().take(8).enumerate() {
+            assert_eq!(driver.out[0].1, (n + 1).to_string());
+            assert!(es[n].is_empty());
+        }
+
+        assert_eq!(es[8][0].message, "type mismatch");
+        assert!(!prelude.cx.contains_key("x"));
+    }
+
+    #[test]
+    fn unchanged_declarations_are_reused() {
+        let src = "def one : Int := 1
+def two : Int := int_add one one
+def three : Int := int_add two one
+#eval three";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(driver.reused, 0);
+
+        let rerun = |cache, src: &str| {
+            let mut driver = Driver::new();
+            driver.cache = cache;
+            driver.run(src).unwrap();
+            driver
+        };
+
+        let driver = rerun(driver.cache, src);
+        assert_eq!(driver.reused, 3);
+        assert_eq!(driver.out[0].1, "3");
+
+        let driver = rerun(driver.cache, &src.replace("int_add one one", "2"));
+        assert_eq!(driver.reused, 1);
+        assert_eq!(driver.out[0].1, "3");
+
+        let driver = rerun(driver.cache, &src.replace(":= 1", ":= 2"));
+        assert_eq!(driver.reused, 0);
+        assert_eq!(driver.out[0].1, "6");
+    }
+
+    #[test]
+    fn declarations_are_reused_under_the_same_options() {
+        let rerun = |cache, options, src: &str| {
+            let mut driver = Driver::new();
+            driver.cache = cache;
+            driver.options = options;
+            driver
+                .run(src)
+                .map(|()| driver.cache)
+                .map_err(|e| e.message)
+        };
+
+        let src = "def f : Int -> Int := let A := Int in \\(y : A). y";
+        let cache = rerun(HashMap::new(), CheckOptions::default(), src).unwrap();
+        let options = CheckOptions {
+            opaque_lets: true,
+            ..CheckOptions::default()
+        };
+        let e = rerun(cache, options, src).unwrap_err();
+        assert_eq!(e, "type mismatch");
+
+        let src = "def e : U 1 := obs_eq Int 1 1";
+        let options = CheckOptions {
+            observational: true,
+            ..CheckOptions::default()
+        };
+        let cache = rerun(HashMap::new(), options, src).unwrap();
+        let e = rerun(cache, CheckOptions::default(), src).unwrap_err();
+        assert_eq!(e, "observational equality is not enabled");
+    }
+
+    #[test]
+    fn cache_hits_are_confirmed() {
+        let run = |cache, src: &str| {
+            let mut driver = Driver::new();
+            driver.cache = cache;
+            driver.run(src).unwrap();
+            driver
+        };
+
+        let (a, b) = (
+            "def one : Int := 1\n#eval one",
+            "def one : Int := 2\n#eval one",
+        );
+        let cached = run(HashMap::new(), a).cache.into_values();
+        let fps = run(HashMap::new(), b).cache.into_keys();
+
+        // As if both declarations had the same fingerprint.
+        let driver = run(fps.zip(cached).collect(), b);
+        assert_eq!(driver.reused, 0);
+        assert_eq!(driver.out[0].1, "2");
+    }
+
+    #[test]
+    fn declarations_are_profiled() {
+        let src = "def twice : (Int -> Int) -> Int -> Int := \\f x. f (f x)
+mutual
+def a : Int -> Int := \\x. x
+def b : Int := twice a 1
+end";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+
+        let ps = driver
+            .profile
+            .iter()
+            .map(|p| (p.pos.line, p.names.join(" "), p.conversions > 0))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            ps,
+            vec![(1, "twice".to_string(), true), (2, "a b".to_string(), true)]
+        );
+        assert!(driver.profile[1].peak_size > 0);
+    }
+
+    #[test]
+    fn checks_can_be_cancelled() {
+        let xs = (0..100).fold("nil".to_string(), |a, n| format!("cons {n} ({a})"));
+        let src = format!("#eval ListRec ({xs}) 0 (\\h t r. int_add h r)");
+
+        let mut driver = Driver::new();
+        driver.run(&src).unwrap();
+        assert_eq!(driver.out[0].1, "4950");
+
+        let token = CancelToken::new();
+        let mut driver = Driver::new();
+        driver.options.cancel = Some(token.clone());
+        token.cancel();
+        assert_eq!(driver.run(&src).unwrap_err().message, "check cancelled");
+
+        let mut driver = Driver::new();
+        driver.options.deadline = Some(Instant::now());
+        assert_eq!(driver.run(&src).unwrap_err().message, "deadline exceeded");
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let src = "def twice : (Int -> Int) -> Int -> Int := \\f x. f (f x)
+#eval twice (twice (\\x. int_add x 1)) 0
+#eval cons 1 (cons 2 (cons 3 nil))";
+
+        let run = |options: CheckOptions| {
+            let mut driver = Driver::new();
+            driver.options = options;
+            driver
+                .run(src)
+                .map(|()| driver.out.len())
+                .map_err(|e| e.message)
+        };
+
+        assert_eq!(run(CheckOptions::default()), Ok(2));
+
+        let options = CheckOptions {
+            max_unfoldings: Some(5),
+            ..CheckOptions::default()
+        };
+        assert_eq!(run(options), Err(Error::UnfoldLimit));
+
+        let options = CheckOptions {
+            max_depth: Some(3),
+            ..CheckOptions::default()
+        };
+        assert_eq!(run(options), Err(Error::DepthLimit));
+
+        let options = CheckOptions {
+            max_size: Some(6),
+            ..CheckOptions::default()
+        };
+        assert_eq!(run(options), Err(Error::SizeLimit));
+    }
+
+    #[test]
+    fn types_are_compared_before_unfolding() {
+        let src = "def id : Int -> Int := \\x. x
+def twice : (Int -> Int) -> Int -> Int := \\f x. f (f x)
+def blow : Int -> Int := twice (twice (twice (twice (twice (twice (twice (twice (twice (twice id)))))))))
+def T : Int -> U 0 := \\n. Int
+def x : T (blow 1) := 1
+def y : T (blow 1) := x";
+
+        let mut driver = Driver::new();
+        driver.options = CheckOptions {
+            max_unfoldings: Some(100),
+            ..CheckOptions::default()
+        };
+        driver.run(src).unwrap();
+    }
+
+    #[test]
+    fn comparisons_are_cached_within_a_declaration() {
+        let src = "def T : U 0 := Int
+def f : T -> Int := \\x. x
+def n : Int := 1
+def ys : List Int := cons (f n) (cons (f n) nil)
+def m : Int := f n";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+
+        // `n` is checked against `T` twice by the elaborator and twice by
+        // the kernel, and the cache is emptied between declarations.
+        let cache = |k: usize| (driver.profile[k].cache_hits, driver.profile[k].cache_misses);
+        assert_eq!(cache(3), (3, 1));
+        assert_eq!(cache(4), (1, 1));
+    }
+}
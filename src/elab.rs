@@ -0,0 +1,562 @@
+use std::collections::HashSet;
+
+use crate::{
+    bind, bind_let, check_annotation, conv::convertible_lazily, list_rec_step, mismatch,
+    options::Guard, quote_in, rec_values, scope::Overloads, taken, Bindings, CheckedExpr, Context,
+    Env, Error, Expr, Identifier, RecBindings, Type, Value,
+};
+
+#[derive(Clone, Debug)]
+pub struct Coercion {
+    pub name: Identifier,
+    pub from: Expr,
+    pub to: Expr,
+}
+
+// Functions the elaborator may insert where a term of type `from` is
+// expected to have type `to`.
+#[derive(Clone, Debug, Default)]
+pub struct Coercions {
+    cs: Vec<Coercion>,
+}
+
+impl Coercions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registers a global function, given its name and type.
+    pub fn insert(&mut self, name: Identifier, t: &Type) -> Result<(), Error> {
+        let Value::Fun(t_1, t_2) = t.unfolded(&Guard::default())? else {
+            return Err("coercion is not a function".into());
+        };
+
+        let xs = HashSet::new();
+
+        self.cs.push(Coercion {
+            name,
+            from: t_1.quote(&xs),
+            to: t_2.quote(&xs),
+        });
+
+        Ok(())
+    }
+
+    pub fn find(&self, from: &Expr, to: &Expr) -> Option<&Coercion> {
+        self.cs
+            .iter()
+            .rev()
+            .find(|c| &c.from == from && &c.to == to)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Coercion> {
+        self.cs.iter()
+    }
+}
+
+// Terms the elaborator may pass for instance arguments, found by their type.
+// Later instances take precedence, so those bound by a lambda under an
+// instance binder come before the global ones.
+#[derive(Clone, Debug, Default)]
+pub struct Instances {
+    is: Vec<(Identifier, Expr)>,
+}
+
+impl Instances {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: Identifier, t: &Type) {
+        self.is.push((name, t.quote(&HashSet::new())));
+    }
+
+    pub fn find(&self, t: &Expr) -> Option<&Identifier> {
+        self.is.iter().rev().find(|(_, t_)| t_ == t).map(|(x, _)| x)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Identifier> {
+        self.is.iter().map(|(x, _)| x)
+    }
+}
+
+// Everything the elaborator may insert into a term.
+#[derive(Clone, Debug, Default)]
+pub struct Hints {
+    pub coercions: Coercions,
+    pub instances: Instances,
+    pub overloads: Overloads,
+}
+
+impl Hints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// The binder of a lambda annotated with its type, unless the type mentions
+// a variable no name in scope refers to.
+fn annotate(t: &Type, cx: &Context, d: &Env, g: &Guard) -> Result<Option<Box<Expr>>, Error> {
+    Ok(quote_in(t, cx, d, g)?.map(Box::new))
+}
+
+// Elaborates the bindings of a let block, returning them with the context
+// and environment under it.
+fn block(
+    bs: &[(Identifier, Expr)],
+    zeta: bool,
+    cx: &Context,
+    d: &Env,
+    cs: &Hints,
+    g: &Guard,
+) -> Result<(Bindings, Context, Env), Error> {
+    let (mut bs_, mut cx_, mut d_) = (vec![], cx.to_owned(), d.to_owned());
+
+    for (k, (x, e)) in bs.iter().enumerate() {
+        if bs[..k].iter().any(|(y, _)| x == y) {
+            return Err("duplicate definition".into());
+        }
+
+        let (e_, t) = infer(e, cx, d, cs, g)?;
+        let v = zeta.then(|| e_.eval_with(d, g)).transpose()?;
+        (cx_, d_) = bind_let(x, t, v, &cx_, &d_);
+        bs_.push((x.to_owned(), e_));
+    }
+
+    Ok((bs_, cx_, d_))
+}
+
+// Elaborates the bindings of a recursive let block against their types,
+// with the whole block bound, returning them with the context and
+// environment under it.
+fn rec_block(
+    bs: &[(Identifier, Expr, Expr)],
+    zeta: bool,
+    cx: &Context,
+    d: &Env,
+    cs: &Hints,
+    g: &Guard,
+) -> Result<(RecBindings, Context, Env), Error> {
+    let mut ts = vec![];
+
+    for (k, (x, a, _)) in bs.iter().enumerate() {
+        if bs[..k].iter().any(|(y, _, _)| x == y) {
+            return Err("duplicate definition".into());
+        }
+
+        ts.push(a.check_type(cx, d, g)?);
+    }
+
+    let (mut cx_1, mut d_1) = (cx.to_owned(), d.to_owned());
+
+    for ((x, _, _), t) in bs.iter().zip(&ts) {
+        (cx_1, d_1) = bind(x, t.to_owned(), &cx_1, &d_1);
+    }
+
+    let bs_ = bs
+        .iter()
+        .zip(&ts)
+        .map(|((x, a, e), t)| Ok((x.to_owned(), a.to_owned(), check(e, t, &cx_1, &d_1, cs, g)?)))
+        .collect::<Result<RecBindings, Error>>()?;
+
+    let vs = rec_values(&bs_, cx, d, g)?;
+    let (mut cx_, mut d_) = (cx.to_owned(), d.to_owned());
+
+    for (((x, _, _), t), v) in bs.iter().zip(ts).zip(vs) {
+        (cx_, d_) = bind_let(x, t, zeta.then_some(v), &cx_, &d_);
+    }
+
+    Ok((bs_, cx_, d_))
+}
+
+// The candidates for the name at the head of a term, if it is ambiguous
+// rather than bound.
+fn overloaded<'a>(e: &Expr, cx: &Context, cs: &'a Hints) -> Option<&'a [Identifier]> {
+    let mut head = e;
+
+    while let Expr::App(e_1, _) = head {
+        head = e_1;
+    }
+
+    match head {
+        Expr::Var(x) if !cx.contains_key(x) => cs.overloads.get(x).map(Vec::as_slice),
+        _ => None,
+    }
+}
+
+// Whether a candidate of type `t` applied to `n` arguments may have the type
+// `expected`, judged by the forms of the types alone, so that the candidates
+// which cannot are dropped before the term is elaborated with each of them.
+// Whatever the forms do not tell, such as a dependent codomain or a coercion
+// bridging the two types, leaves the candidate in.
+fn may_fit(t: &Type, n: usize, expected: &Type, cs: &Hints, g: &Guard) -> bool {
+    let (mut t, mut n) = (t, n);
+
+    loop {
+        match t.unfolded(g) {
+            Ok(Value::Fun(_, t_2)) if n > 0 => {
+                t = t_2;
+                n -= 1;
+            }
+            Ok(Value::Pi(_, _)) if n > 0 => return true,
+            Ok(t_) if n > 0 => return form(t_).is_none(),
+            Ok(t_) => {
+                let Ok(expected) = expected.unfolded(g) else {
+                    return true;
+                };
+
+                return cs.coercions.iter().next().is_some()
+                    || form(t_).zip(form(expected)).is_none_or(|(a, b)| a == b);
+            }
+            Err(_) => return true,
+        }
+    }
+}
+
+// The form of a type as far as telling candidates apart goes, unless it is
+// not known or its arguments are found by instance search.
+fn form(t: &Type) -> Option<&'static str> {
+    match t {
+        Value::Code => Some("code"),
+        Value::Fun(_, _) | Value::Pi(_, _) => Some("function"),
+        Value::Int => Some("int"),
+        Value::List(_) => Some("list"),
+        Value::Str => Some("string"),
+        Value::U(_) => Some("universe"),
+        _ => None,
+    }
+}
+
+// The number of arguments the head of a term is applied to.
+fn arity(e: &Expr) -> usize {
+    match e {
+        Expr::App(e_1, _) => arity(e_1) + 1,
+        _ => 0,
+    }
+}
+
+// The term with the name at its head replaced.
+fn with_head(e: &Expr, y: &str) -> Expr {
+    match e {
+        Expr::App(e_1, e_2) => Expr::App(Box::new(with_head(e_1, y)), e_2.to_owned()),
+        _ => Expr::Var(y.to_owned()),
+    }
+}
+
+// Checks a term like `Expr::check` does, returning it with a coercion
+// applied wherever the types would otherwise mismatch, with instance
+// arguments filled in and with the binder of every lambda annotated.
+pub fn check(
+    e: &Expr,
+    t: &Type,
+    cx: &Context,
+    d: &Env,
+    cs: &Hints,
+    g: &Guard,
+) -> Result<Expr, Error> {
+    g.tick()?;
+
+    // An ambiguous name stands for the one candidate the term checks with.
+    // Elaborating the term with every candidate would take time exponential
+    // in how deeply ambiguous names nest, so those whose type cannot fit are
+    // left out first.
+    if let Some(ys) = overloaded(e, cx, cs) {
+        let n = arity(e);
+        let mut es = ys
+            .iter()
+            .filter(|&y| cx.get(y).is_none_or(|t_y| may_fit(t_y, n, t, cs, g)))
+            .filter_map(|y| check(&with_head(e, y), t, cx, d, cs, g).ok())
+            .collect::<Vec<_>>();
+
+        return match es.len() {
+            0 => Err("ambiguous name fits no candidate".into()),
+            1 => Ok(es.remove(0)),
+            _ => Err("ambiguous name".into()),
+        };
+    }
+    let b = Box::new;
+
+    match (e, t.unfolded(g)?) {
+        (Expr::Cons(e_1, e_2), Type::List(t_1)) => Ok(Expr::Cons(
+            b(check(e_1, t_1, cx, d, cs, g)?),
+            b(check(e_2, t, cx, d, cs, g)?),
+        )),
+        (Expr::Lam(x, a, e_), Type::Fun(t_1, _) | Type::Pi(t_1, _)) => {
+            check_annotation(a.as_deref(), t_1, cx, d, g)?;
+            let (cx_, d_) = bind(x, t_1.as_ref().to_owned(), cx, d);
+            let t_2 = t.codomain(d_[x].to_owned(), g)?;
+            let e_ = check(e_, &t_2, &cx_, &d_, cs, g)?;
+            Ok(Expr::Lam(x.to_owned(), annotate(t_1, cx, d, g)?, b(e_)))
+        }
+        (Expr::Lam(x, a, e_), Type::Inst(t_1, t_2)) => {
+            check_annotation(a.as_deref(), t_1, cx, d, g)?;
+            let (cx_, d_) = bind(x, t_1.as_ref().to_owned(), cx, d);
+            let mut cs_ = cs.to_owned();
+            cs_.instances.insert(x.to_owned(), t_1);
+            let e_ = check(e_, t_2, &cx_, &d_, &cs_, g)?;
+            Ok(Expr::Lam(x.to_owned(), annotate(t_1, cx, d, g)?, b(e_)))
+        }
+        // Without an annotation, there is no type to coerce from.
+        (Expr::Lam(_, None, _), _) => Err(mismatch(e, t)),
+        (Expr::Let(bs, e_), _) => {
+            let (bs_, cx_, d_) = block(bs, g.zeta(), cx, d, cs, g)?;
+            Ok(Expr::Let(bs_, b(check(e_, t, &cx_, &d_, cs, g)?)))
+        }
+        (Expr::LetRec(bs, e_), _) => {
+            let (bs_, cx_, d_) = rec_block(bs, g.zeta(), cx, d, cs, g)?;
+            Ok(Expr::LetRec(bs_, b(check(e_, t, &cx_, &d_, cs, g)?)))
+        }
+        (Expr::ListRec(e_1, e_2, e_3), _) => {
+            let (e_1_, t_1) = infer(e_1, cx, d, cs, g)?;
+            let e_2_ = check(e_2, t, cx, d, cs, g)?;
+            let e_3_ = check(e_3, &list_rec_step(t_1, t.to_owned(), g)?, cx, d, cs, g)?;
+            Ok(Expr::ListRec(b(e_1_), b(e_2_), b(e_3_)))
+        }
+        (Expr::Sub(x, e_1, e_2), _) => {
+            let (e_1_, t_1) = infer(e_1, cx, d, cs, g)?;
+            let v = g.zeta().then(|| e_1_.eval_with(d, g)).transpose()?;
+            let (cx_, d_) = bind_let(x, t_1, v, cx, d);
+            Ok(Expr::Sub(
+                x.to_owned(),
+                b(e_1_),
+                b(check(e_2, t, &cx_, &d_, cs, g)?),
+            ))
+        }
+        (
+            Expr::Cons(_, _)
+            | Expr::Fun(_, _)
+            | Expr::List(_)
+            | Expr::Nil
+            | Expr::Pi(_, _, _)
+            | Expr::U(_),
+            _,
+        ) => {
+            e.check_with(t, cx, d, g)?;
+            Ok(e.to_owned())
+        }
+        _ => {
+            let (e_, t_) = infer(e, cx, d, cs, g)?;
+
+            // Universes are cumulative.
+            if let (Value::U(i), Value::U(j)) = (t_.unfolded(g)?, t.unfolded(g)?) {
+                return if i <= j {
+                    Ok(e_)
+                } else {
+                    Err("universe inconsistency".into())
+                };
+            }
+
+            if convertible_lazily(&t_, t, cx, d, g)? {
+                return Ok(e_);
+            }
+
+            let xs = taken(cx, d);
+            let (from, to) = (t_.quote_with(&xs, g)?, t.quote_with(&xs, g)?);
+
+            match cs.coercions.find(&from, &to) {
+                Some(c) => Ok(Expr::App(b(Expr::Var(c.name.to_owned())), b(e_))),
+                None => Err(mismatch(e, t)),
+            }
+        }
+    }
+}
+
+pub fn infer(
+    e: &Expr,
+    cx: &Context,
+    d: &Env,
+    cs: &Hints,
+    g: &Guard,
+) -> Result<(Expr, Type), Error> {
+    g.tick()?;
+
+    match e {
+        Expr::Var(x) if !cx.contains_key(x) && cs.overloads.contains_key(x) => {
+            Err("ambiguous name".into())
+        }
+        Expr::Var(x) => {
+            let mut e_ = e.to_owned();
+            let mut t = cx.get(x).cloned().ok_or("unknown identifier")?;
+
+            while let Value::Inst(t_1, t_2) = t.unfolded(g)? {
+                let y = cs
+                    .instances
+                    .find(&t_1.quote_with(&HashSet::new(), g)?)
+                    .ok_or("no instance found")?;
+
+                e_ = Expr::App(Box::new(e_), Box::new(Expr::Var(y.to_owned())));
+                t = t_2.as_ref().to_owned();
+            }
+
+            Ok((e_, t))
+        }
+        Expr::App(e_1, e_2) => {
+            let (e_1_, t) = infer(e_1, cx, d, cs, g)?;
+
+            let (e_2_, t) = match t.unfolded(g)? {
+                Value::Fun(t_1, t_2) => (check(e_2, t_1, cx, d, cs, g)?, t_2.as_ref().to_owned()),
+                Value::Pi(t_1, c) => {
+                    let e_2_ = check(e_2, t_1, cx, d, cs, g)?;
+                    let t = c.apply_with(e_2_.eval_with(d, g)?, g)?;
+                    (e_2_, t)
+                }
+                _ => return Err("not a function".into()),
+            };
+
+            Ok((Expr::App(Box::new(e_1_), Box::new(e_2_)), t))
+        }
+        Expr::Let(bs, e_) => {
+            let (bs_, cx_, d_) = block(bs, true, cx, d, cs, g)?;
+            let (e_, t) = infer(e_, &cx_, &d_, cs, g)?;
+            Ok((Expr::Let(bs_, Box::new(e_)), t))
+        }
+        Expr::LetRec(bs, e_) => {
+            let (bs_, cx_, d_) = rec_block(bs, true, cx, d, cs, g)?;
+            let (e_, t) = infer(e_, &cx_, &d_, cs, g)?;
+            Ok((Expr::LetRec(bs_, Box::new(e_)), t))
+        }
+        Expr::Sub(x, e_1, e_2) => {
+            let (e_1_, t_1) = infer(e_1, cx, d, cs, g)?;
+            let mut cx_ = cx.to_owned();
+            cx_.insert(x.to_owned(), t_1);
+            let mut d_ = d.to_owned();
+            d_.insert(x.to_owned(), e_1_.eval_with(d, g)?);
+            let (e_2_, t) = infer(e_2, &cx_, &d_, cs, g)?;
+            Ok((Expr::Sub(x.to_owned(), Box::new(e_1_), Box::new(e_2_)), t))
+        }
+        Expr::Lam(x, Some(a), e_) => {
+            let t_1 = a.check_type(cx, d, g)?;
+            let (cx_, d_) = bind(x, t_1.to_owned(), cx, d);
+            let (e_, _) = infer(e_, &cx_, &d_, cs, g)?;
+            let e_ = Expr::Lam(x.to_owned(), Some(a.to_owned()), Box::new(e_));
+            let t = e_.infer_with(cx, d, g)?;
+            Ok((e_, t))
+        }
+        _ => Ok((e.to_owned(), e.infer_with(cx, d, g)?)),
+    }
+}
+
+// Elaboration is not trusted, so the terms it produces are checked by the
+// kernel again, which leaves them with their type for the rest of the
+// pipeline, so that nothing needs inferring again.
+pub fn elaborate_check(
+    e: &Expr,
+    t: &Type,
+    cx: &Context,
+    d: &Env,
+    cs: &Hints,
+    g: &Guard,
+) -> Result<CheckedExpr, Error> {
+    check(e, t, cx, d, cs, g)?.certify(t, cx, d, g)
+}
+
+pub fn elaborate_infer(
+    e: &Expr,
+    cx: &Context,
+    d: &Env,
+    cs: &Hints,
+    g: &Guard,
+) -> Result<CheckedExpr, Error> {
+    let (e_, _) = infer(e, cx, d, cs, g)?;
+    e_.certify_infer(cx, d, g)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::driver::Driver;
+
+    #[test]
+    fn coercions_are_inserted() {
+        let src = "def show : Int -> String := int_show
+coercion show
+def greeting : String := str_append \"n = \" (int_add 40 2)
+#eval greeting
+#print greeting";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(driver.out[0].1, "\"n = 42\"");
+        assert_eq!(
+            driver.out[1].1,
+            "def greeting : String := str_append \"n = \" (show (int_add 40 2))"
+        );
+
+        let e = Driver::new().run("def x : String := 1").unwrap_err();
+        assert_eq!(
+            e.message,
+            "integer literal checked against a non-integer type"
+        );
+    }
+
+    #[test]
+    fn annotated_lambdas_are_inferred() {
+        let src = "def T : U 0 := Int
+#check \\(x : T) (f : Int -> String). f x
+#eval (\\(x y : Int). int_mul x y) 6 7";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(
+            driver.out[0].1,
+            "\\(x : T) (f : Int -> String). f x : Int -> (Int -> String) -> String"
+        );
+        assert_eq!(driver.out[1].1, "42");
+
+        let e = Driver::new()
+            .run("def f : Int -> Int := \\(x : String). x")
+            .unwrap_err();
+        assert_eq!(e.message, "type mismatch");
+
+        let mut driver = Driver::new();
+        driver.run("#check \\(A : U 0) (x : A). x").unwrap();
+        assert_eq!(
+            driver.out[0].1,
+            "\\(A : U 0) (x : A). x : forall (A : U 0) -> A -> A"
+        );
+    }
+
+    #[test]
+    fn annotations_refer_to_binders() {
+        let src = "def A' : U 0 := Int
+def f : forall (A : U 0) -> A -> (forall (B : U 0) -> B -> A) := \\A x A y. x
+def g : forall (A : U 0) -> A -> (forall (B : U 0) -> A -> A) := \\A x A y. y
+#print f
+#print g";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(
+            driver.out[0].1,
+            "def f : forall (A : U 0) -> A -> (forall (B : U 0) -> B -> A) := \\(A : U 0) (x : A) (A : U 0) (y : A). x"
+        );
+
+        // The type of y is the shadowed A, which no name refers to.
+        assert_eq!(
+            driver.out[1].1,
+            "def g : forall (A : U 0) -> A -> U 0 -> A -> A := \\(A : U 0) (x : A) (A : U 0) y. y"
+        );
+    }
+
+    #[test]
+    fn instances_are_found() {
+        let src = "def show : Int -> String := int_show
+instance show
+def describe : [Int -> String] -> Int -> String := \\s n. str_append \"n = \" (s n)
+def twice : [Int -> String] -> Int -> String := \\s n. str_append (describe n) (describe n)
+#eval twice 1
+#print twice";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(driver.out[0].1, "\"n = 1n = 1\"");
+        assert_eq!(
+            driver.out[1].1,
+            "def twice : [Int -> String] -> Int -> String := \\(s : Int -> String) (n : Int). str_append (describe s n) (describe s n)"
+        );
+
+        let e = Driver::new()
+            .run("def f : [Int] -> Int := \\x. x\n#eval f")
+            .unwrap_err();
+        assert_eq!(e.message, "no instance found");
+    }
+}
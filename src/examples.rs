@@ -0,0 +1,99 @@
+use crate::{driver::Diagnostic, lexer::Pos, session::Checker};
+
+// A development written in saida, for embedders and benchmarks wanting
+// realistic programs. It refers to the definitions of the examples it
+// requires, which are loaded first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Example {
+    pub name: &'static str,
+    pub requires: &'static [&'static str],
+    pub src: &'static str,
+}
+
+// In order, each after those it requires.
+pub const EXAMPLES: [Example; 3] = [
+    Example {
+        name: "Examples.Peano",
+        requires: &[],
+        src: include_str!("examples/peano.sd"),
+    },
+    Example {
+        name: "Examples.Vectors",
+        requires: &["Examples.Peano"],
+        src: include_str!("examples/vectors.sd"),
+    },
+    Example {
+        name: "Examples.Equality",
+        requires: &["Examples.Peano"],
+        src: include_str!("examples/equality.sd"),
+    },
+];
+
+pub fn example(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|e| e.name == name)
+}
+
+// Adds an example to a checker, with those it requires that the checker
+// has not been given, returning the output of its commands.
+pub fn load(checker: &mut Checker, name: &str) -> Result<Vec<String>, Diagnostic> {
+    let e = example(name).ok_or_else(|| Diagnostic {
+        pos: Pos { line: 1, column: 1 },
+        message: "unknown module".into(),
+    })?;
+
+    for &x in e.requires {
+        if !loaded(checker, x) {
+            load(checker, x)?;
+        }
+    }
+
+    checker.add_declaration(e.src)
+}
+
+// A checker given every example.
+pub fn checker() -> Result<Checker, Diagnostic> {
+    let mut checker = Checker::new();
+
+    for e in EXAMPLES {
+        if !loaded(&checker, e.name) {
+            load(&mut checker, e.name)?;
+        }
+    }
+
+    Ok(checker)
+}
+
+fn loaded(checker: &Checker, name: &str) -> bool {
+    let prefix = format!("{name}.");
+    checker.driver().defs.keys().any(|x| x.starts_with(&prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Expr;
+
+    #[test]
+    fn examples_check() {
+        let checker = checker().unwrap();
+        assert_eq!(
+            checker.normalize("to_int (mul three three)").unwrap(),
+            Expr::IntLit(9)
+        );
+        assert_eq!(
+            checker.normalize("vsum two v123").unwrap_err().message,
+            "type mismatch"
+        );
+
+        for e in EXAMPLES {
+            let mut checker = Checker::new();
+            checker.options_mut().lints.deny_warnings();
+            load(&mut checker, e.name).unwrap();
+            assert!(e.requires.iter().all(|&x| loaded(&checker, x)));
+        }
+
+        let out = load(&mut Checker::new(), "Examples.Vectors").unwrap();
+        assert_eq!(out, vec!["cons 10 (cons 20 (cons 30 nil))", "6"]);
+        assert!(load(&mut Checker::new(), "Examples.Missing").is_err());
+    }
+}
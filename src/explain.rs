@@ -0,0 +1,187 @@
+use std::fmt;
+
+use crate::Error;
+
+// A longer description of an error, shown by `saida explain`, with a
+// program that makes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    pub code: &'static str,
+    pub message: &'static str,
+    pub text: &'static str,
+    pub example: &'static str,
+}
+
+// The errors with a code, which never changes once given, so that new ones
+// go last.
+pub const EXPLANATIONS: [Explanation; 15] = [
+    Explanation {
+        code: "E0001",
+        message: "type mismatch",
+        text: "A term does not have the type expected where it stands. Types are
+compared after evaluating them, so two types that compute to the same one
+are not told apart.",
+        example: "def n : Int := int_show 1",
+    },
+    Explanation {
+        code: "E0002",
+        message: "unknown identifier",
+        text: "A name is neither bound by a binder around it nor defined or
+imported before it. Definitions may only refer to those above them, except
+within a `mutual` block.",
+        example: "def n : Int := m",
+    },
+    Explanation {
+        code: "E0003",
+        message: "not a function",
+        text: "A term is applied to an argument, but its type is not a function
+type.",
+        example: "def n : Int := 1 2",
+    },
+    Explanation {
+        code: "E0004",
+        message: "not a type",
+        text: "A term stands where a type is expected, such as the type of a
+definition or of a binder, but it is not one: its type is not a universe.",
+        example: "def n : 1 := 1",
+    },
+    Explanation {
+        code: "E0005",
+        message: "duplicate definition",
+        text: "A name is bound twice by the same `let` block, whose bindings are
+all in scope at once in its body.",
+        example: "def n : Int := let x := 1; x := 2 in x",
+    },
+    Explanation {
+        code: "E0006",
+        message: "unsolved hole",
+        text: "A definition contains a hole, `?h`, standing for a term yet to be
+written. Holes may be left in the REPL, which shows their goals, but not in
+checked files.",
+        example: "def n : Int := ?h",
+    },
+    Explanation {
+        code: "E0007",
+        message: "cannot show that recursion terminates",
+        text: "A definition of a `mutual` block calls one of the block on
+arguments that are not smaller than its own. Recursive calls must be on
+the tail of a list the definition was given.",
+        example: "mutual
+def loop : List Int -> Int := \\xs. loop xs
+end",
+    },
+    Explanation {
+        code: "E0008",
+        message: "universe inconsistency",
+        text: "A type lives in a higher universe than the one expected. `U n`
+has the type `U (n + 1)`, so no universe contains itself.",
+        example: "def T : U 0 := U 0 -> Int",
+    },
+    Explanation {
+        code: "E0009",
+        message: "could not infer type",
+        text: "The type of a term is needed but cannot be worked out from the
+term alone, as for a lambda without an annotation on its binder. Annotating
+it, or the definition it is in, gives the type.",
+        example: "#check \\x. x",
+    },
+    Explanation {
+        code: "E0010",
+        message: "unknown module",
+        text: "An imported module is neither shipped with saida nor found as an
+interface file. `Data.Nat` is looked up in `Data/Nat.sdi`, which `saida
+build` writes.",
+        example: "import Data.Missing",
+    },
+    Explanation {
+        code: "E0011",
+        message: "no instance found",
+        text: "An instance argument, whose type is written in brackets, was not
+given, and no declared instance has its type.",
+        example: "def f : [Int] -> Int := \\x. x
+#eval f",
+    },
+    Explanation {
+        code: "E0012",
+        message: "private definition in public type",
+        text: "The type of a definition a module exports refers to one of its
+private definitions, which importing modules could not see.",
+        example: "private def T : U 0 := Int
+def x : T := 1",
+    },
+    Explanation {
+        code: "E0013",
+        message: "definition uses an axiom",
+        text: "A definition refers to an axiom while the `axiom` lint is denied.
+Axioms are assumed rather than proved, so results depending on them may be
+denied in a project that must not have any.",
+        example: "axiom lem : forall (P : U 0) -> P
+def n : Int := lem Int",
+    },
+    Explanation {
+        code: "E0014",
+        message: "unused binder",
+        text: "A binder is never referred to while the `unused-binder` lint is
+denied. Binders meant to go unused are named with a leading `_`.",
+        example: "def k : Int -> Int -> Int := \\x y. x",
+    },
+    Explanation {
+        code: "E0015",
+        message: "lambda checked against a non-function type",
+        text: "A lambda stands where the type expected is not a function type,
+so it has no argument to bind. The definition may have been given too few
+arrows in its type, or the lambda too many binders.",
+        example: "def n : Int := \\x. x",
+    },
+];
+
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS.iter().find(|e| e.code == code)
+}
+
+impl Error {
+    pub fn code(self) -> Option<&'static str> {
+        let message = self.message();
+        EXPLANATIONS
+            .iter()
+            .find(|e| e.message == message)
+            .map(|e| e.code)
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {}\n\n{}\n\nFor example:\n",
+            self.code, self.message, self.text
+        )?;
+
+        for line in self.example.lines() {
+            writeln!(f, "    {line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::Driver;
+
+    #[test]
+    fn examples_make_their_errors() {
+        for e in EXPLANATIONS {
+            let mut driver = Driver::new();
+            driver.options.lints.deny_warnings();
+            let message = driver.run(e.example).unwrap_err().message;
+            assert_eq!((e.code, message.message()), (e.code, e.message));
+            assert_eq!(message.code(), Some(e.code));
+        }
+
+        let codes = EXPLANATIONS.map(|e| e.code);
+        assert!(codes.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(Error::from("expected identifier").code(), None);
+    }
+}
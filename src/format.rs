@@ -0,0 +1,547 @@
+use crate::{
+    decl::{Decl, Def},
+    lexer::Pos,
+    notation::{Fixity, Notations},
+    parser::{parse_program, Command, Import, Item, ParseError},
+    pretty::{operator, precedence, quote_str, APP, ARROW, ATOM, LAM},
+    Expr,
+};
+
+// How many columns formatted source may take where it can be broken.
+pub const WIDTH: usize = 80;
+
+// A document laid out by Wadler's algorithm: every group is printed on one
+// line if it fits, and otherwise has its lines broken, indented by the
+// nesting they are in.
+#[derive(Clone, Debug)]
+pub enum Doc {
+    Text(String),
+    // A space, or a line break when the group around it is broken.
+    Line,
+    Nest(usize, Box<Doc>),
+    Concat(Vec<Doc>),
+    Group(Box<Doc>),
+}
+
+fn text(a: impl Into<String>) -> Doc {
+    Doc::Text(a.into())
+}
+
+fn nest(i: usize, d: Doc) -> Doc {
+    Doc::Nest(i, Box::new(d))
+}
+
+fn group(d: Doc) -> Doc {
+    Doc::Group(Box::new(d))
+}
+
+impl Doc {
+    pub fn render(&self, width: usize) -> String {
+        let mut a = String::new();
+        let mut column = 0;
+        let mut stack = vec![(0, false, self)];
+
+        while let Some((i, flat, d)) = stack.pop() {
+            match d {
+                Doc::Text(b) => {
+                    a.push_str(b);
+                    column += b.chars().count();
+                }
+                Doc::Line if flat => {
+                    a.push(' ');
+                    column += 1;
+                }
+                Doc::Line => {
+                    a.push('\n');
+                    a.extend(std::iter::repeat_n(' ', i));
+                    column = i;
+                }
+                Doc::Nest(j, d) => stack.push((i + j, flat, d)),
+                Doc::Concat(ds) => stack.extend(ds.iter().rev().map(|d| (i, flat, d))),
+                Doc::Group(d) => {
+                    let fits = flat || {
+                        let mut rest = stack.to_owned();
+                        rest.push((i, true, d));
+                        fits(width.saturating_sub(column), rest)
+                    };
+
+                    stack.push((i, fits, d));
+                }
+            }
+        }
+
+        a
+    }
+}
+
+// Whether what is left to print fits in `w` columns up to the next line
+// break.
+fn fits(mut w: usize, mut stack: Vec<(usize, bool, &Doc)>) -> bool {
+    while let Some((i, flat, d)) = stack.pop() {
+        match d {
+            Doc::Text(a) => match w.checked_sub(a.chars().count()) {
+                Some(w_) => w = w_,
+                None => return false,
+            },
+            Doc::Line if flat => match w.checked_sub(1) {
+                Some(w_) => w = w_,
+                None => return false,
+            },
+            Doc::Line => return true,
+            Doc::Nest(j, d) => stack.push((i + j, flat, d)),
+            Doc::Concat(ds) => stack.extend(ds.iter().rev().map(|d| (i, flat, d))),
+            Doc::Group(d) => stack.push((i, flat, d)),
+        }
+    }
+
+    true
+}
+
+impl Expr {
+    // The layout of a term, with the same parentheses and notations as
+    // `Expr::pretty` uses.
+    pub fn doc(&self, ns: &Notations) -> Doc {
+        expr_doc(self, LAM, ns)
+    }
+}
+
+fn binder_doc(x: &str, a: Option<&Expr>, ns: &Notations) -> Doc {
+    match a {
+        Some(a) => Doc::Concat(vec![
+            text(format!("({x} : ")),
+            expr_doc(a, LAM, ns),
+            text(")"),
+        ]),
+        None => text(x),
+    }
+}
+
+// A head applied to arguments, which are indented when they do not fit.
+fn spine_doc(head: Doc, args: Vec<Doc>) -> Doc {
+    let args = args.into_iter().flat_map(|d| [Doc::Line, d]).collect();
+    group(Doc::Concat(vec![head, nest(2, Doc::Concat(args))]))
+}
+
+fn expr_doc(e: &Expr, p: u8, ns: &Notations) -> Doc {
+    let q = precedence(e, ns);
+
+    if q < p {
+        return Doc::Concat(vec![text("("), nest(1, expr_doc(e, LAM, ns)), text(")")]);
+    }
+
+    if let Some((n, e_1, e_2)) = operator(e, ns) {
+        let (p_1, p_2) = match n.fixity {
+            Fixity::Infix => (q + 1, q + 1),
+            Fixity::Infixl => (q, q + 1),
+            Fixity::Infixr => (q + 1, q),
+        };
+
+        return group(Doc::Concat(vec![
+            expr_doc(e_1, p_1, ns),
+            nest(
+                2,
+                Doc::Concat(vec![
+                    Doc::Line,
+                    text(format!("{} ", n.symbol)),
+                    expr_doc(e_2, p_2, ns),
+                ]),
+            ),
+        ]));
+    }
+
+    let arrow = |e_1: Doc, e_2: &Expr| {
+        group(Doc::Concat(vec![
+            e_1,
+            text(" ->"),
+            Doc::Line,
+            expr_doc(e_2, ARROW, ns),
+        ]))
+    };
+
+    match e {
+        Expr::App(_, _) => {
+            let mut args = vec![];
+            let mut e = e;
+
+            while let Expr::App(e_1, e_2) = e {
+                if operator(e, ns).is_some() {
+                    break;
+                }
+
+                args.push(expr_doc(e_2, ATOM, ns));
+                e = e_1;
+            }
+
+            args.reverse();
+            spine_doc(expr_doc(e, APP, ns), args)
+        }
+        Expr::Cons(e_1, e_2) => spine_doc(
+            text("cons"),
+            vec![expr_doc(e_1, ATOM, ns), expr_doc(e_2, ATOM, ns)],
+        ),
+        Expr::Fun(e_1, e_2) => arrow(expr_doc(e_1, ARROW + 1, ns), e_2),
+        Expr::Pi(x, e_1, e_2) if !e_2.occurs(x) => arrow(expr_doc(e_1, ARROW + 1, ns), e_2),
+        Expr::Inst(e_1, e_2) => arrow(
+            Doc::Concat(vec![text("["), expr_doc(e_1, LAM, ns), text("]")]),
+            e_2,
+        ),
+        Expr::Lam(x, a, e) => {
+            let mut head = vec![text("\\"), binder_doc(x, a.as_deref(), ns)];
+            let mut e = e.as_ref();
+
+            while let Expr::Lam(y, a, e_) = e {
+                head.push(text(" "));
+                head.push(binder_doc(y, a.as_deref(), ns));
+                e = e_;
+            }
+
+            head.push(text("."));
+
+            group(Doc::Concat(vec![
+                Doc::Concat(head),
+                nest(2, Doc::Concat(vec![Doc::Line, expr_doc(e, LAM, ns)])),
+            ]))
+        }
+        Expr::List(e) => spine_doc(text("List"), vec![expr_doc(e, ATOM, ns)]),
+        Expr::Quote(e) => spine_doc(text("quote"), vec![expr_doc(e, ATOM, ns)]),
+        Expr::Splice(e) => spine_doc(text("splice"), vec![expr_doc(e, ATOM, ns)]),
+        Expr::ListRec(e_1, e_2, e_3) => spine_doc(
+            text("ListRec"),
+            vec![
+                expr_doc(e_1, ATOM, ns),
+                expr_doc(e_2, ATOM, ns),
+                expr_doc(e_3, ATOM, ns),
+            ],
+        ),
+        Expr::Pi(x, e_1, e_2) => {
+            let mut head = vec![text("forall "), binder_doc(x, Some(e_1), ns)];
+            let mut e = e_2.as_ref();
+
+            while let Expr::Pi(y, e_1, e_2) = e {
+                if !e_2.occurs(y) {
+                    break;
+                }
+
+                head.push(text(" "));
+                head.push(binder_doc(y, Some(e_1), ns));
+                e = e_2;
+            }
+
+            head.push(text(" ->"));
+
+            group(Doc::Concat(vec![
+                Doc::Concat(head),
+                nest(2, Doc::Concat(vec![Doc::Line, expr_doc(e, LAM, ns)])),
+            ]))
+        }
+        Expr::Let(bs, e) => {
+            let mut es = vec![];
+
+            for (k, (x, e_1)) in bs.iter().enumerate() {
+                if k > 0 {
+                    es.push(text(";"));
+                }
+
+                es.push(Doc::Line);
+                es.push(text(format!("{x} := ")));
+                es.push(expr_doc(e_1, LAM, ns));
+            }
+
+            group(Doc::Concat(vec![
+                group(Doc::Concat(vec![
+                    text("let"),
+                    nest(2, Doc::Concat(es)),
+                    Doc::Line,
+                    text("in"),
+                ])),
+                Doc::Line,
+                expr_doc(e, LAM, ns),
+            ]))
+        }
+        Expr::LetRec(bs, e) => {
+            let mut es = vec![];
+
+            for (k, (x, a, e_1)) in bs.iter().enumerate() {
+                if k > 0 {
+                    es.push(text(";"));
+                }
+
+                es.push(Doc::Line);
+                es.push(text(format!("{x} : ")));
+                es.push(expr_doc(a, LAM, ns));
+                es.push(text(" := "));
+                es.push(expr_doc(e_1, LAM, ns));
+            }
+
+            group(Doc::Concat(vec![
+                group(Doc::Concat(vec![
+                    text("let rec"),
+                    nest(2, Doc::Concat(es)),
+                    Doc::Line,
+                    text("in"),
+                ])),
+                Doc::Line,
+                expr_doc(e, LAM, ns),
+            ]))
+        }
+        Expr::StrLit(a) => text(quote_str(a)),
+        Expr::Sub(x, e_1, e_2) => group(Doc::Concat(vec![
+            group(Doc::Concat(vec![
+                text(format!("let {x} :=")),
+                nest(2, Doc::Concat(vec![Doc::Line, expr_doc(e_1, LAM, ns)])),
+                Doc::Line,
+                text("in"),
+            ])),
+            Doc::Line,
+            expr_doc(e_2, LAM, ns),
+        ])),
+        _ => text(e.pretty(ns).to_string()),
+    }
+}
+
+fn def_doc(def: &Def, ns: &Notations) -> Doc {
+    let mut head = String::new();
+
+    if def.private {
+        head.push_str("private ");
+    }
+
+    if def.opaque {
+        head.push_str("opaque ");
+    }
+
+    head.push_str(&format!("def {} :", def.name));
+
+    group(Doc::Concat(vec![
+        group(Doc::Concat(vec![
+            text(head),
+            nest(2, Doc::Concat(vec![Doc::Line, def.ty.doc(ns)])),
+            text(" :="),
+        ])),
+        nest(2, Doc::Concat(vec![Doc::Line, def.body.doc(ns)])),
+    ]))
+}
+
+fn write_decl(a: &mut String, decl: &Decl, ns: &Notations, width: usize) {
+    match decl {
+        Decl::Def(def) => write_def(a, def, ns, width),
+        Decl::Mutual(defs) => {
+            a.push_str("mutual\n");
+
+            for def in defs {
+                write_def(a, def, ns, width);
+            }
+
+            a.push_str("end\n");
+        }
+    }
+}
+
+// Comments go on the lines before the definition, those from inside it
+// first, since there is no telling where they belong once its lines are
+// broken anew.
+fn write_def(a: &mut String, def: &Def, ns: &Notations, width: usize) {
+    for b in &def.comments {
+        a.push_str(&format!("--{b}\n"));
+    }
+
+    for b in def.doc.iter().flat_map(|doc| doc.lines()) {
+        a.push_str(&format!("--| {b}\n"));
+    }
+
+    a.push_str(&def_doc(def, ns).render(width));
+    a.push('\n');
+}
+
+// Re-prints a source file in the canonical layout, breaking the lines of
+// declarations and commands that are longer than `width`. Blank lines
+// between items are kept, and notations are given by `ns` and the ones the
+// file declares.
+pub fn format_source(src: &str, ns: &mut Notations, width: usize) -> Result<String, ParseError> {
+    let items = parse_program(src, ns)?;
+    let lines = src.lines().collect::<Vec<_>>();
+    let mut ns_ = ns.to_owned();
+    let mut a = String::new();
+
+    for (k, (Pos { line, .. }, item)) in items.iter().enumerate() {
+        // Doc comments are part of the definition they precede.
+        let docs = match item {
+            Item::Decl(Decl::Def(def)) => def.doc.as_ref().map_or(0, |doc| doc.lines().count()),
+            _ => 0,
+        };
+
+        if k > 0
+            && line
+                .checked_sub(docs + 2)
+                .is_some_and(|j| lines.get(j).is_some_and(|b| b.trim().is_empty()))
+        {
+            a.push('\n');
+        }
+
+        match item {
+            Item::Axiom(x, t) => {
+                let d = spine_doc(text(format!("axiom {x} :")), vec![t.doc(&ns_)]);
+                a.push_str(&d.render(width));
+                a.push('\n');
+            }
+            Item::Coercion(x) => a.push_str(&format!("coercion {x}\n")),
+            Item::Comment(b) => a.push_str(&format!("--{b}\n")),
+            Item::Command(c) => {
+                let d = match c {
+                    Command::Check(e) => spine_doc(text("#check"), vec![e.doc(&ns_)]),
+                    Command::Eval(e) => spine_doc(text("#eval"), vec![e.doc(&ns_)]),
+                    Command::Print(x) => text(format!("#print {x}")),
+                };
+
+                a.push_str(&d.render(width));
+                a.push('\n');
+            }
+            Item::Decl(decl) => write_decl(&mut a, decl, &ns_, width),
+            Item::Import(Import { module, alias }) => {
+                a.push_str(&format!("import {module}"));
+
+                if let Some(alias) = alias {
+                    a.push_str(&format!(" as {alias}"));
+                }
+
+                a.push('\n');
+            }
+            Item::Instance(x) => a.push_str(&format!("instance {x}\n")),
+            Item::Macro(m) => {
+                let mut head = format!("macro {}", m.name);
+
+                for x in &m.params {
+                    head.push_str(&format!(" {x}"));
+                }
+
+                let d = Doc::Concat(vec![
+                    text(format!("{head} :=")),
+                    nest(2, Doc::Concat(vec![Doc::Line, m.body.doc(&ns_)])),
+                ]);
+
+                a.push_str(&group(d).render(width));
+                a.push('\n');
+            }
+            Item::Module(x) => a.push_str(&format!("module {x}\n")),
+            Item::Notation(n) => {
+                a.push_str(&format!(
+                    "{} {} _{}_ := {}\n",
+                    n.fixity.keyword(),
+                    n.precedence,
+                    n.symbol,
+                    n.name
+                ));
+
+                ns_.insert(n.to_owned());
+            }
+            Item::Open(x) => a.push_str(&format!("open {x}\n")),
+            Item::Section(s) => {
+                let vars = s.vars.iter().map(|(x, t)| binder_doc(x, Some(t), &ns_));
+                a.push_str(&spine_doc(text("section"), vars.collect()).render(width));
+                a.push('\n');
+
+                for (_, decl) in &s.decls {
+                    write_decl(&mut a, decl, &ns_, width);
+                }
+
+                a.push_str("end\n");
+            }
+        }
+    }
+
+    Ok(a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_are_formatted() {
+        let src = "module Main
+import Data.List   as L
+
+infixl 6 _+_ := int_add
+--| Adds up a list.
+def sum : List Int -> Int := \\xs.   ListRec xs 0 (\\h t r. h + r)
+
+#eval sum (cons 1 (cons 2 nil))
+macro twice f x :=   f (f x)
+";
+
+        let expected = "module Main
+import Data.List as L
+
+infixl 6 _+_ := int_add
+--| Adds up a list.
+def sum : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. h + r)
+
+#eval sum (cons 1 (cons 2 nil))
+macro twice f x := f (f x)
+";
+
+        let a = format_source(src, &mut Notations::new(), WIDTH).unwrap();
+        assert_eq!(a, expected);
+        assert_eq!(format_source(&a, &mut Notations::new(), WIDTH).unwrap(), a);
+
+        let src_1 =
+            "section (A   : U 0) (x : A)\ndef y : A :=  x\nmutual\ndef z : A := x\nend\nend\n";
+        let a_1 = format_source(src_1, &mut Notations::new(), WIDTH).unwrap();
+        assert_eq!(
+            a_1,
+            "section (A : U 0) (x : A)\ndef y : A := x\nmutual\ndef z : A := x\nend\nend\n"
+        );
+
+        let a = format_source(src, &mut Notations::new(), 40).unwrap();
+        assert_eq!(
+            a.lines().skip(5).take(3).collect::<Vec<_>>(),
+            vec![
+                "def sum : List Int -> Int :=",
+                "  \\xs. ListRec xs 0 (\\h t r. h + r)",
+                "",
+            ]
+        );
+
+        let a = format_source(src, &mut Notations::new(), 30).unwrap();
+        assert_eq!(
+            a.lines().skip(5).collect::<Vec<_>>(),
+            vec![
+                "def sum : List Int -> Int :=",
+                "  \\xs.",
+                "    ListRec",
+                "      xs",
+                "      0",
+                "      (\\h t r. h + r)",
+                "",
+                "#eval",
+                "  sum (cons 1 (cons 2 nil))",
+                "macro twice f x := f (f x)",
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let src = "-- Numbers.
+def one : Int := -- the first
+  1
+
+-- The end.
+";
+
+        let mut ns = Notations::new();
+        let items = parse_program(src, &mut ns).unwrap();
+        let Item::Decl(Decl::Def(def)) = &items[1].1 else {
+            panic!()
+        };
+
+        assert_eq!(def.comments, vec![" the first"]);
+
+        let a = format_source(src, &mut ns, WIDTH).unwrap();
+        assert_eq!(
+            a,
+            "-- Numbers.\n-- the first\ndef one : Int := 1\n\n-- The end.\n"
+        );
+        assert_eq!(format_source(&a, &mut ns, WIDTH).unwrap(), a);
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{
+    testing::{check_term, idempotence, strictness, Generator, Rng, Terms},
+    Env,
+};
+
+// Checks every property of `testing` on a term decoded from the input, the
+// first byte giving its size and the others the choices made generating it,
+// so that any input is a well-typed term. Panics on a counterexample.
+pub fn fuzz_check(bytes: &[u8]) {
+    let mut rng = Rng::from_bytes(bytes);
+    let size = rng.below(6);
+
+    if let Err(e) = check_term(&mut Terms, &mut rng, size, &Env::new()) {
+        panic!("{e}");
+    }
+}
+
+// Evaluates a term decoded from the input like `fuzz_check` does, checking
+// only the properties of normalization.
+pub fn fuzz_eval(bytes: &[u8]) {
+    let mut rng = Rng::from_bytes(bytes);
+    let size = rng.below(6);
+    let (e, _) = Terms.generate(&mut rng, size);
+    let d = Env::new();
+
+    if let Err(e) = idempotence(&e, &d).and_then(|()| strictness(&e, &d)) {
+        panic!("{e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_input_is_a_term() {
+        let mut rng = Rng::new(7);
+
+        for n in 0..200 {
+            let bytes = (0..n).map(|_| rng.next_u64() as u8).collect::<Vec<_>>();
+            fuzz_check(&bytes);
+            fuzz_eval(&bytes);
+        }
+    }
+}
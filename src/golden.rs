@@ -0,0 +1,199 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use crate::driver::Driver;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Missing,
+    Blessed,
+    // Two runs disagreed, with the diff between them.
+    Unstable(String),
+}
+
+// How many times a file is checked to tell whether its output is
+// reproducible.
+pub const RUNS: usize = 4;
+
+// The output of checking a source file, one command result or error per
+// line, as committed in `.expected` files.
+pub fn render(src: &str) -> String {
+    render_with(&mut Driver::new(), src)
+}
+
+fn render_with(driver: &mut Driver, src: &str) -> String {
+    let es = driver.check(src);
+    let mut a = String::new();
+
+    for (pos, text) in &driver.out {
+        a.push_str(&format!("{}:{}: {text}\n", pos.line, pos.column));
+    }
+
+    for e in es {
+        a.push_str(&format!(
+            "{}:{}: error: {}\n",
+            e.pos.line, e.pos.column, e.message
+        ));
+    }
+
+    a
+}
+
+// A line diff from the expected to the actual output, based on their
+// longest common subsequence.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let xs = expected.lines().collect::<Vec<_>>();
+    let ys = actual.lines().collect::<Vec<_>>();
+    let mut lcs = vec![vec![0; ys.len() + 1]; xs.len() + 1];
+
+    for i in (0..xs.len()).rev() {
+        for j in (0..ys.len()).rev() {
+            lcs[i][j] = if xs[i] == ys[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut a = String::new();
+
+    while i < xs.len() || j < ys.len() {
+        if i < xs.len() && j < ys.len() && xs[i] == ys[j] {
+            a.push_str(&format!(" {}\n", xs[i]));
+            i += 1;
+            j += 1;
+        } else if i < xs.len() && (j == ys.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            a.push_str(&format!("-{}\n", xs[i]));
+            i += 1;
+        } else {
+            a.push_str(&format!("+{}\n", ys[j]));
+            j += 1;
+        }
+    }
+
+    a
+}
+
+// Everything checking a source file produces: its rendered output, its
+// warnings and its interface. It is checked on a thread of its own, so that
+// hash maps iterate in a different order on every run.
+fn artifacts(src: &str) -> String {
+    let src = src.to_owned();
+
+    thread::spawn(move || {
+        let mut driver = Driver::new();
+        let mut a = render_with(&mut driver, &src);
+
+        for w in &driver.warnings {
+            a.push_str(&format!(
+                "{}:{}: warning: {} [{}]\n",
+                w.pos.line,
+                w.pos.column,
+                w.message,
+                w.lint.name()
+            ));
+        }
+
+        a.push_str(&driver.interface.to_string());
+        a
+    })
+    .join()
+    .expect("checking panicked")
+}
+
+// The `.sd` files of a directory, in order.
+fn sources(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "sd"));
+    paths.sort();
+    Ok(paths)
+}
+
+// Checks every `.sd` file of a directory `RUNS` times and compares what the
+// runs produce, which golden files and certificates rely on being the same.
+pub fn check_determinism(dir: &Path) -> io::Result<Vec<(PathBuf, Outcome)>> {
+    let mut outcomes = vec![];
+
+    for path in sources(dir)? {
+        let src = fs::read_to_string(&path)?;
+        let first = artifacts(&src);
+
+        let outcome = (1..RUNS)
+            .map(|_| artifacts(&src))
+            .find(|a| *a != first)
+            .map_or(Outcome::Pass, |a| Outcome::Unstable(diff(&first, &a)));
+
+        outcomes.push((path, outcome));
+    }
+
+    Ok(outcomes)
+}
+
+// Runs every `.sd` file of a directory and compares its output against the
+// `.expected` file next to it, or writes that file when blessing.
+pub fn run_dir(dir: &Path, bless: bool) -> io::Result<Vec<(PathBuf, Outcome)>> {
+    let mut outcomes = vec![];
+
+    for path in sources(dir)? {
+        let actual = render(&fs::read_to_string(&path)?);
+        let expected_path = path.with_extension("expected");
+
+        let outcome = if bless {
+            fs::write(&expected_path, &actual)?;
+            Outcome::Blessed
+        } else {
+            match fs::read_to_string(&expected_path) {
+                Ok(expected) if expected == actual => Outcome::Pass,
+                Ok(expected) => Outcome::Fail(diff(&expected, &actual)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Outcome::Missing,
+                Err(e) => return Err(e),
+            }
+        };
+
+        outcomes.push((path, outcome));
+    }
+
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_diff_works() {
+        let actual = render("#eval int_add 1 2\n#check \"a\"\n#eval x");
+        assert_eq!(
+            actual,
+            "1:1: 3\n2:1: \"a\" : String\n3:1: error: unknown identifier\n"
+        );
+
+        assert_eq!(
+            diff("1:1: 3\n2:1: 4\n", &actual),
+            " 1:1: 3\n-2:1: 4\n+2:1: \"a\" : String\n+3:1: error: unknown identifier\n"
+        );
+    }
+
+    #[test]
+    fn golden_files_pass() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+        for (path, outcome) in run_dir(&dir, false).unwrap() {
+            assert_eq!(outcome, Outcome::Pass, "{}", path.display());
+        }
+
+        for (path, outcome) in check_determinism(&dir).unwrap() {
+            assert_eq!(outcome, Outcome::Pass, "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    options::{unguarded, Guard},
+    Closure, Level, Neutral, Prim, Value,
+};
+
+// The outermost form of a value, for tactics and decision procedures that
+// would otherwise quote it to match on the term.
+#[derive(Clone)]
+pub enum Head<'a> {
+    Lam(&'a Closure),
+    U(Level),
+    // A function type, dependent or not, with its domain.
+    Pi(&'a Value),
+    // A value of a data type built by its constructors: a literal, a list
+    // or a quoted term.
+    Constructor,
+    // A type other than a universe or a function type.
+    Type,
+    // A primitive given too few arguments to compute, with those it has.
+    Prim(Prim, &'a [Value]),
+    Neutral(Stuck<'a>),
+}
+
+// What a neutral value is stuck on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Var<'a> {
+    // A local or global variable, by name.
+    Var(&'a str),
+    // A primitive one of whose arguments is neutral.
+    Prim(Prim),
+}
+
+// An elimination applied to the head of a neutral value.
+#[derive(Clone, Copy)]
+pub enum Elim<'a> {
+    App(&'a Value),
+    ListRec(&'a Value, &'a Value),
+}
+
+// A neutral value as its head and the eliminations applied to it, the
+// innermost first. The arguments of a stuck primitive come first.
+#[derive(Clone)]
+pub struct Stuck<'a> {
+    pub head: Var<'a>,
+    pub spine: Vec<Elim<'a>>,
+}
+
+impl Value {
+    pub fn head(&self) -> Head<'_> {
+        match self {
+            Self::Lam(c) => Head::Lam(c),
+            &Self::U(i) => Head::U(i),
+            Self::Fun(v, _) | Self::Inst(v, _) | Self::Pi(v, _) => Head::Pi(v),
+            Self::Cons(..) | Self::IntLit(_) | Self::Nil | Self::Quote(_) | Self::StrLit(_) => {
+                Head::Constructor
+            }
+            Self::Code | Self::Int | Self::List(_) | Self::Str => Head::Type,
+            Self::Glued(v) => unguarded(v.unfolded(&Guard::default())).head(),
+            Self::Prim(p, vs) => Head::Prim(*p, vs),
+            Self::Neutral(n) => Head::Neutral(n.stuck()),
+        }
+    }
+}
+
+impl Neutral {
+    pub fn stuck(&self) -> Stuck<'_> {
+        let mut spine = vec![];
+        let mut n = self;
+
+        let head = loop {
+            match n {
+                Self::App(n_, v) => {
+                    spine.push(Elim::App(v));
+                    n = n_;
+                }
+                Self::ListRec(n_, v_1, v_2) => {
+                    spine.push(Elim::ListRec(v_1, v_2));
+                    n = n_;
+                }
+                Self::Prim(p, vs) => {
+                    spine.extend(vs.iter().rev().map(Elim::App));
+                    break Var::Prim(*p);
+                }
+                Self::Fix(f) => break Var::Var(&f.x),
+                Self::Var(x) => break Var::Var(x),
+            }
+        };
+
+        spine.reverse();
+        Stuck { head, spine }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation::Notations, parser::parse_expr, Env};
+
+    #[test]
+    fn neutrals_are_split_into_head_and_spine() {
+        let e = parse_expr(
+            "ListRec (f (int_add 1 x)) 0 (\\h t r. r)",
+            &mut Notations::new(),
+        )
+        .unwrap();
+        let v = e.eval(&Env::new());
+
+        let Head::Neutral(s) = v.head() else {
+            panic!("expected a neutral value");
+        };
+        assert_eq!(s.head, Var::Var("f"));
+        assert!(matches!(
+            s.spine[..],
+            [Elim::App(Value::Neutral(n)), Elim::ListRec(Value::IntLit(0), Value::Lam(_))]
+                if matches!(n.stuck().head, Var::Prim(Prim::IntAdd))
+                    && matches!(n.stuck().spine[..], [Elim::App(Value::IntLit(1)), Elim::App(_)])
+        ));
+
+        assert!(matches!(Value::Nil.head(), Head::Constructor));
+        assert!(matches!(Value::U(2).head(), Head::U(2)));
+        assert!(matches!(
+            Value::Fun(Box::new(Value::Int), Box::new(Value::Str)).head(),
+            Head::Pi(Value::Int)
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{diff::children, freshen, sized, Expr, Identifier, Level, Prim};
+
+// An alternative to `Value` whose closures are Rust functions, built by
+// compiling a term once, so that variables are found by position instead of
+// being looked up by name whenever they are evaluated.
+#[derive(Clone)]
+pub enum Value {
+    Code,
+    Cons(Box<Value>, Box<Value>),
+    Fun(Box<Value>, Box<Value>),
+    Inst(Box<Value>, Box<Value>),
+    Int,
+    IntLit(i64),
+    Lam(Identifier, Rc<dyn Fn(Value) -> Value>),
+    List(Box<Value>),
+    Neutral(Neutral),
+    Nil,
+    Pi(Identifier, Box<Value>, Rc<dyn Fn(Value) -> Value>),
+    Prim(Prim, Vec<Value>),
+    Quote(Box<Expr>),
+    Str,
+    StrLit(String),
+    U(Level),
+}
+
+#[derive(Clone)]
+pub enum Neutral {
+    App(Box<Neutral>, Box<Value>),
+    ListRec(Box<Neutral>, Box<Value>, Box<Value>),
+    Prim(Prim, Vec<Value>),
+    Var(Identifier),
+}
+
+pub type Globals = HashMap<Identifier, Value>;
+
+// Compiled code, taking the values of the local variables in scope,
+// outermost first.
+pub type Code = Rc<dyn Fn(&[Value]) -> Value>;
+
+// Free variables are resolved once, to the value of a global or to
+// themselves.
+pub fn compile(e: &Expr, globals: &Globals) -> Code {
+    compile_in(e, globals, &mut vec![])
+}
+
+fn compile_in<'a>(e: &'a Expr, globals: &Globals, bs: &mut Vec<&'a str>) -> Code {
+    let mut go = |e: &'a Expr| compile_in(e, globals, bs);
+
+    match e {
+        Expr::App(e_1, e_2) => {
+            let (c_1, c_2) = (go(e_1), go(e_2));
+            Rc::new(move |vs| c_1(vs).apply(c_2(vs)))
+        }
+        Expr::Code => Rc::new(|_| Value::Code),
+        Expr::Cons(e_1, e_2) => {
+            let (c_1, c_2) = (go(e_1), go(e_2));
+            Rc::new(move |vs| Value::Cons(Box::new(c_1(vs)), Box::new(c_2(vs))))
+        }
+        Expr::Fun(e_1, e_2) => {
+            let (c_1, c_2) = (go(e_1), go(e_2));
+            Rc::new(move |vs| Value::Fun(Box::new(c_1(vs)), Box::new(c_2(vs))))
+        }
+        Expr::Inst(e_1, e_2) => {
+            let (c_1, c_2) = (go(e_1), go(e_2));
+            Rc::new(move |vs| Value::Inst(Box::new(c_1(vs)), Box::new(c_2(vs))))
+        }
+        // Holes and syntax errors do not type check, so they are only met in
+        // terms evaluated without checking, where they stand for themselves.
+        Expr::Error => Rc::new(|_| Value::Neutral(Neutral::Var("<error>".to_owned()))),
+        Expr::Hole(h) => {
+            let x = format!("?{h}");
+            Rc::new(move |_| Value::Neutral(Neutral::Var(x.to_owned())))
+        }
+        Expr::Int => Rc::new(|_| Value::Int),
+        &Expr::IntLit(n) => Rc::new(move |_| Value::IntLit(n)),
+        Expr::Lam(x, _, e) => {
+            bs.push(x);
+            let c = compile_in(e, globals, bs);
+            bs.pop();
+            let x = x.to_owned();
+            Rc::new(move |vs| Value::Lam(x.to_owned(), close(&c, vs)))
+        }
+        Expr::Let(bs_, e) => {
+            let cs = bs_
+                .iter()
+                .map(|(_, e)| compile_in(e, globals, bs))
+                .collect::<Vec<_>>();
+            bs.extend(bs_.iter().map(|(x, _)| x.as_str()));
+            let c = compile_in(e, globals, bs);
+            bs.truncate(bs.len() - bs_.len());
+
+            Rc::new(move |vs| {
+                let mut vs_ = vs.to_vec();
+                vs_.extend(cs.iter().map(|c| c(vs)));
+                c(&vs_)
+            })
+        }
+        // The bindings of a recursive block see each other as fixpoints,
+        // which find the values of the block once it is computed.
+        Expr::LetRec(bs_, e) => {
+            let names = bs_.iter().map(|(x, _, _)| x.to_owned()).collect::<Vec<_>>();
+            let bodies = bs_.iter().map(|(_, _, e)| e.to_owned()).collect::<Vec<_>>();
+            let j = sized::check_termination(&names, &bodies).ok().flatten();
+            bs.extend(bs_.iter().map(|(x, _, _)| x.as_str()));
+            let cs = bs_
+                .iter()
+                .map(|(_, _, e)| compile_in(e, globals, bs))
+                .collect::<Vec<_>>();
+            let c = compile_in(e, globals, bs);
+            bs.truncate(bs.len() - bs_.len());
+
+            Rc::new(move |vs| {
+                let block = Rc::new(RefCell::new(vec![]));
+                let mut vs_ = vs.to_vec();
+                vs_.extend(
+                    names
+                        .iter()
+                        .enumerate()
+                        .map(|(k, x)| fixpoint(x, k, j, &block, vec![])),
+                );
+                *block.borrow_mut() = cs.iter().map(|c| c(&vs_)).collect::<Vec<_>>();
+                vs_.truncate(vs.len());
+                vs_.extend(block.borrow().iter().cloned());
+                c(&vs_)
+            })
+        }
+        Expr::List(e) => {
+            let c = go(e);
+            Rc::new(move |vs| Value::List(Box::new(c(vs))))
+        }
+        Expr::ListRec(e_1, e_2, e_3) => {
+            let (c_1, c_2, c_3) = (go(e_1), go(e_2), go(e_3));
+            Rc::new(move |vs| c_1(vs).list_rec(c_2(vs), c_3(vs)))
+        }
+        Expr::Nil => Rc::new(|_| Value::Nil),
+        Expr::Pi(x, e_1, e_2) => {
+            let c_1 = go(e_1);
+            bs.push(x);
+            let c_2 = compile_in(e_2, globals, bs);
+            bs.pop();
+            let x = x.to_owned();
+            Rc::new(move |vs| Value::Pi(x.to_owned(), Box::new(c_1(vs)), close(&c_2, vs)))
+        }
+        &Expr::Prim(p) => Rc::new(move |_| Value::Prim(p, vec![])),
+        Expr::Quote(e) => {
+            let e = e.to_owned();
+            Rc::new(move |_| Value::Quote(e.to_owned()))
+        }
+        // The spliced term is only known once the code is run, so it is
+        // compiled then, seeing the globals but no local variables. Checking
+        // makes sure the code is known.
+        Expr::Splice(e) => {
+            let c = go(e);
+            let globals = globals.to_owned();
+
+            Rc::new(move |vs| match c(vs) {
+                Value::Quote(e) => compile(&e, &globals)(&[]),
+                _ => Value::Neutral(Neutral::Var("<error>".to_owned())),
+            })
+        }
+        Expr::Str => Rc::new(|_| Value::Str),
+        Expr::StrLit(a) => {
+            let a = a.to_owned();
+            Rc::new(move |_| Value::StrLit(a.to_owned()))
+        }
+        Expr::Sub(x, e_1, e_2) => {
+            let c_1 = go(e_1);
+            bs.push(x);
+            let c_2 = compile_in(e_2, globals, bs);
+            bs.pop();
+
+            Rc::new(move |vs| {
+                let mut vs_ = vs.to_vec();
+                vs_.push(c_1(vs));
+                c_2(&vs_)
+            })
+        }
+        &Expr::U(i) => Rc::new(move |_| Value::U(i)),
+        Expr::Var(x) => match bs.iter().rposition(|y| y == x) {
+            Some(k) => Rc::new(move |vs| vs[k].clone()),
+            None => {
+                let v = globals
+                    .get(x)
+                    .cloned()
+                    .unwrap_or_else(|| Value::Neutral(Neutral::Var(x.to_owned())));
+
+                Rc::new(move |_| v.clone())
+            }
+        },
+    }
+}
+
+// Code under a binder, as a function from the value of the binder.
+fn close(c: &Code, vs: &[Value]) -> Rc<dyn Fn(Value) -> Value> {
+    let (c, vs) = (c.clone(), vs.to_vec());
+
+    Rc::new(move |v| {
+        let mut vs_ = vs.clone();
+        vs_.push(v);
+        c(&vs_)
+    })
+}
+
+// The `k`th binding of a recursive block, given `args`. It unfolds once
+// given the `j`th argument, the one recursion shrinks, if that is a
+// constructor, and stands for itself otherwise, so that quoting it stops.
+fn fixpoint(
+    x: &str,
+    k: usize,
+    j: Option<usize>,
+    block: &Rc<RefCell<Vec<Value>>>,
+    args: Vec<Value>,
+) -> Value {
+    let stuck = |x: &str, args: Vec<Value>| {
+        Value::Neutral(args.into_iter().fold(Neutral::Var(x.to_owned()), |n, v| {
+            Neutral::App(Box::new(n), Box::new(v))
+        }))
+    };
+
+    let Some(j) = j else {
+        return stuck(x, args);
+    };
+
+    let (x, block) = (x.to_owned(), block.clone());
+
+    Value::Lam(
+        "x".to_owned(),
+        Rc::new(move |v| {
+            let mut args = args.clone();
+            args.push(v);
+
+            match args.get(j) {
+                None => fixpoint(&x, k, Some(j), &block, args),
+                Some(Value::Cons(_, _) | Value::Nil) => {
+                    let v = block.borrow()[k].clone();
+                    args.into_iter().fold(v, Value::apply)
+                }
+                Some(_) => stuck(&x, args),
+            }
+        }),
+    )
+}
+
+impl Value {
+    pub fn apply(self, v: Value) -> Value {
+        match self {
+            Self::Lam(_, f) => f(v),
+            Self::Neutral(n) => Self::Neutral(Neutral::App(Box::new(n), Box::new(v))),
+            Self::Prim(p, mut vs) => {
+                vs.push(v);
+                prim(p, vs)
+            }
+            _ => panic!(),
+        }
+    }
+
+    pub fn list_rec(self, v_1: Value, v_2: Value) -> Value {
+        match self {
+            Self::Cons(h, t) => {
+                let v = t.as_ref().to_owned().list_rec(v_1, v_2.clone());
+                v_2.apply(*h).apply(*t).apply(v)
+            }
+            Self::Neutral(n) => {
+                Self::Neutral(Neutral::ListRec(Box::new(n), Box::new(v_1), Box::new(v_2)))
+            }
+            Self::Nil => v_1,
+            _ => panic!(),
+        }
+    }
+
+    pub fn quote(&self, xs: &HashSet<&str>) -> Expr {
+        let q = |v: &Value| Box::new(v.quote(xs));
+
+        match self {
+            Self::Code => Expr::Code,
+            Self::Cons(v_1, v_2) => Expr::Cons(q(v_1), q(v_2)),
+            Self::Fun(v_1, v_2) => Expr::Fun(q(v_1), q(v_2)),
+            Self::Inst(v_1, v_2) => Expr::Inst(q(v_1), q(v_2)),
+            Self::Int => Expr::Int,
+            &Self::IntLit(n) => Expr::IntLit(n),
+            Self::Lam(x, f) => {
+                let (x_, e) = quote_under(x, f.as_ref(), xs);
+                Expr::Lam(x_, None, Box::new(e))
+            }
+            Self::List(v) => Expr::List(q(v)),
+            Self::Neutral(n) => n.quote(xs),
+            Self::Nil => Expr::Nil,
+            Self::Pi(x, v, f) => {
+                let (x_, e) = quote_under(x, f.as_ref(), xs);
+
+                if e.occurs(&x_) {
+                    Expr::Pi(x_, q(v), Box::new(e))
+                } else {
+                    Expr::Fun(q(v), Box::new(e))
+                }
+            }
+            Self::Prim(p, vs) => quote_prim(*p, vs, xs),
+            Self::Quote(e) => Expr::Quote(e.to_owned()),
+            Self::Str => Expr::Str,
+            Self::StrLit(a) => Expr::StrLit(a.to_owned()),
+            &Self::U(i) => Expr::U(i),
+        }
+    }
+}
+
+fn quote_under(x: &str, f: &dyn Fn(Value) -> Value, xs: &HashSet<&str>) -> (Identifier, Expr) {
+    let x_ = freshen(x.to_owned(), xs);
+    let v = f(Value::Neutral(Neutral::Var(x_.clone())));
+    let mut xs_ = xs.to_owned();
+    xs_.insert(&x_);
+    let e = v.quote(&xs_);
+    (x_, e)
+}
+
+impl Neutral {
+    fn quote(&self, xs: &HashSet<&str>) -> Expr {
+        match self {
+            Self::App(n, v) => Expr::App(Box::new(n.quote(xs)), Box::new(v.quote(xs))),
+            Self::ListRec(n, v_1, v_2) => Expr::ListRec(
+                Box::new(n.quote(xs)),
+                Box::new(v_1.quote(xs)),
+                Box::new(v_2.quote(xs)),
+            ),
+            Self::Prim(p, vs) => quote_prim(*p, vs, xs),
+            Self::Var(x) => Expr::Var(x.to_owned()),
+        }
+    }
+}
+
+fn quote_prim(p: Prim, vs: &[Value], xs: &HashSet<&str>) -> Expr {
+    vs.iter().fold(Expr::Prim(p), |e, v| {
+        Expr::App(Box::new(e), Box::new(v.quote(xs)))
+    })
+}
+
+fn prim(p: Prim, vs: Vec<Value>) -> Value {
+    if vs.len() < p.arity() {
+        return Value::Prim(p, vs);
+    }
+
+    let quote = |e| Value::Quote(Box::new(e));
+
+    match (p, vs.as_slice()) {
+        (Prim::CodeApp, [Value::Quote(e_1), Value::Quote(e_2)]) => {
+            quote(Expr::App(e_1.to_owned(), e_2.to_owned()))
+        }
+        (Prim::CodeArgs, [Value::Quote(e)]) => {
+            children(e).into_iter().rev().fold(Value::Nil, |v, (e, _)| {
+                Value::Cons(Box::new(quote(e.to_owned())), Box::new(v))
+            })
+        }
+        (Prim::CodeInt, [Value::IntLit(n)]) => quote(Expr::IntLit(*n)),
+        (Prim::CodeKind, [Value::Quote(e)]) => Value::StrLit(e.kind().to_owned()),
+        (Prim::CodeName, [Value::Quote(e)]) => Value::StrLit(e.name().to_owned()),
+        (Prim::CodeVar, [Value::StrLit(x)]) => {
+            quote(Prim::from_name(x).map_or_else(|| Expr::Var(x.to_owned()), Expr::Prim))
+        }
+        (Prim::IntAdd, [Value::IntLit(m), Value::IntLit(n)]) => Value::IntLit(m.wrapping_add(*n)),
+        (Prim::IntMul, [Value::IntLit(m), Value::IntLit(n)]) => Value::IntLit(m.wrapping_mul(*n)),
+        (Prim::IntShow, [Value::IntLit(n)]) => Value::StrLit(n.to_string()),
+        (Prim::IntSub, [Value::IntLit(m), Value::IntLit(n)]) => Value::IntLit(m.wrapping_sub(*n)),
+        (Prim::StrAppend, [Value::StrLit(a), Value::StrLit(b)]) => Value::StrLit(format!("{a}{b}")),
+        (Prim::StrLength, [Value::StrLit(a)]) => Value::IntLit(a.chars().count() as i64),
+        // Observational equality is left stuck here: only the kernel
+        // computes it.
+        _ => Value::Neutral(Neutral::Prim(p, vs)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation::Notations, parser::parse_expr};
+
+    #[test]
+    fn compiled_terms_agree_with_the_kernel() {
+        let srcs = [
+            "(\\f x. f (f x)) (\\n. int_add n 1) 40",
+            "\\y. (\\x y. x) y",
+            "ListRec (cons 1 (cons 2 (cons 3 nil))) 0 (\\h t r. int_mul h (int_add r 1))",
+            "\\n. str_append (int_show n) \"!\"",
+            "let k := \\x y. x in k one 2",
+            "let rec len : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. int_add 1 (len t)) \
+             in \\xs. len (cons one xs)",
+        ];
+
+        let mut globals = Globals::new();
+        globals.insert("one".to_string(), Value::IntLit(1));
+        let mut d = crate::Env::new();
+        d.insert("one".to_string(), crate::Value::IntLit(1));
+        let xs = HashSet::new();
+
+        for src in srcs {
+            let e = parse_expr(src, &mut Notations::new()).unwrap();
+            let v = compile(&e, &globals)(&[]);
+            assert_eq!(v.quote(&xs), e.eval(&d).quote(&xs), "{src}");
+        }
+    }
+}
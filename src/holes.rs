@@ -0,0 +1,377 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    bind, bind_let, bind_rec_block, check_annotation, conv::convertible_lazily, freshen,
+    list_rec_step, options::Guard, quote_in, taken, telescope::Telescope, Context, Env, Error,
+    Expr, Identifier, Neutral, Type, Value,
+};
+
+// A hole with the type of the term expected in its place and the local
+// variables in scope there, innermost last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Goal {
+    pub name: Identifier,
+    pub ty: Expr,
+    pub context: Telescope,
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, (x, t)) in self.context.iter().enumerate() {
+            let sep = if k + 1 < self.context.len() { "," } else { "" };
+            write!(f, "{x} : {t}{sep} ")?;
+        }
+
+        write!(f, "|- ?{} : {}", self.name, self.ty)
+    }
+}
+
+// The names of the holes of a term, in order.
+pub fn holes(e: &Expr) -> Vec<Identifier> {
+    match e {
+        Expr::App(e_1, e_2)
+        | Expr::Cons(e_1, e_2)
+        | Expr::Fun(e_1, e_2)
+        | Expr::Inst(e_1, e_2)
+        | Expr::Pi(_, e_1, e_2)
+        | Expr::Sub(_, e_1, e_2) => [holes(e_1), holes(e_2)].concat(),
+        Expr::Hole(h) => vec![h.to_owned()],
+        Expr::Lam(_, a, e) => [a.as_deref().map_or(vec![], holes), holes(e)].concat(),
+        Expr::Let(bs, e) => bs
+            .iter()
+            .flat_map(|(_, e)| holes(e))
+            .chain(holes(e))
+            .collect(),
+        Expr::LetRec(bs, e) => bs
+            .iter()
+            .flat_map(|(_, a, e)| [holes(a), holes(e)].concat())
+            .chain(holes(e))
+            .collect(),
+        Expr::List(e) => holes(e),
+        Expr::ListRec(e_1, e_2, e_3) => [holes(e_1), holes(e_2), holes(e_3)].concat(),
+        _ => vec![],
+    }
+}
+
+// Checks a term with holes against a type like `Expr::check` does, returning
+// the goals of its holes. Holes may only stand where the expected type is
+// known from the context.
+pub fn goals(e: &Expr, t: &Type, cx: &Context, d: &Env, g: &Guard) -> Result<Vec<Goal>, Error> {
+    let mut gs = vec![];
+    goals_in(e, t, cx, d, g, &mut vec![], &mut gs)?;
+    Ok(gs)
+}
+
+// Types in goals are written with the names of the binders in scope, as far
+// as those refer to the variables they mention.
+fn quote_goal(t: &Type, cx: &Context, d: &Env, g: &Guard) -> Result<Expr, Error> {
+    match quote_in(t, cx, d, g)? {
+        Some(e) => Ok(e),
+        None => t.quote_with(&taken(cx, d), g),
+    }
+}
+
+fn goals_in(
+    e: &Expr,
+    t: &Type,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+    bs: &mut Vec<(Identifier, Expr)>,
+    gs: &mut Vec<Goal>,
+) -> Result<(), Error> {
+    g.tick()?;
+
+    // Checks the body of a lambda of type `t`.
+    let mut under = |x: &str, t_1: &Type, e: &Expr, t: &Type| {
+        let (cx_, d_) = bind(x, t_1.to_owned(), cx, d);
+        let t_2 = t.codomain(d_[x].to_owned(), g)?;
+        bs.push((x.to_owned(), quote_goal(t_1, cx, d, g)?));
+        goals_in(e, &t_2, &cx_, &d_, g, bs, gs)?;
+        bs.pop();
+        Ok(())
+    };
+
+    match (e, t.unfolded(g)?) {
+        (Expr::Hole(h), _) => {
+            gs.push(Goal {
+                name: h.to_owned(),
+                ty: quote_goal(t, cx, d, g)?,
+                // Binders named `_` cannot be referred to, so they are not
+                // shown.
+                context: bs.iter().filter(|(x, _)| x != "_").cloned().collect(),
+            });
+
+            Ok(())
+        }
+        (Expr::Lam(x, a, e_), Type::Fun(t_1, _) | Type::Inst(t_1, _) | Type::Pi(t_1, _)) => {
+            check_annotation(a.as_deref(), t_1, cx, d, g)?;
+            under(x, t_1, e_, t)
+        }
+        (Expr::Cons(e_1, e_2), Type::List(t_1)) => {
+            goals_in(e_1, t_1, cx, d, g, bs, gs)?;
+            goals_in(e_2, t, cx, d, g, bs, gs)
+        }
+        (Expr::ListRec(e_1, e_2, e_3), _) => {
+            let t_1 = e_1.infer_with(cx, d, g)?;
+            goals_in(e_2, t, cx, d, g, bs, gs)?;
+            goals_in(e_3, &list_rec_step(t_1, t.to_owned(), g)?, cx, d, g, bs, gs)
+        }
+        (Expr::Let(bs_, e), _) => {
+            let (mut cx_, mut d_) = (cx.to_owned(), d.to_owned());
+
+            for (k, (x, e_1)) in bs_.iter().enumerate() {
+                if bs_[..k].iter().any(|(y, _)| x == y) {
+                    return Err("duplicate definition".into());
+                }
+
+                let t_1 = e_1.infer_with(cx, d, g)?;
+                bs.push((x.to_owned(), quote_goal(&t_1, cx, d, g)?));
+                let v = g.zeta().then(|| e_1.eval_with(d, g)).transpose()?;
+                (cx_, d_) = bind_let(x, t_1, v, &cx_, &d_);
+            }
+
+            goals_in(e, t, &cx_, &d_, g, bs, gs)?;
+            bs.truncate(bs.len() - bs_.len());
+            Ok(())
+        }
+        (Expr::LetRec(bs_, e), _) => {
+            let mut ts = vec![];
+
+            for (k, (x, a, _)) in bs_.iter().enumerate() {
+                if bs_[..k].iter().any(|(y, _, _)| x == y) {
+                    return Err("duplicate definition".into());
+                }
+
+                ts.push(a.check_type(cx, d, g)?);
+            }
+
+            let (mut cx_, mut d_) = (cx.to_owned(), d.to_owned());
+
+            for ((x, _, _), t_1) in bs_.iter().zip(&ts) {
+                bs.push((x.to_owned(), quote_goal(t_1, cx, d, g)?));
+                (cx_, d_) = bind(x, t_1.to_owned(), &cx_, &d_);
+            }
+
+            for ((_, _, e_1), t_1) in bs_.iter().zip(&ts) {
+                goals_in(e_1, t_1, &cx_, &d_, g, bs, gs)?;
+            }
+
+            // Bindings with holes have no values, so the body only sees them
+            // as variables.
+            if bs_.iter().all(|(_, _, e_1)| holes(e_1).is_empty()) {
+                (cx_, d_) = bind_rec_block(bs_, g.zeta(), cx, d, g)?;
+            }
+
+            goals_in(e, t, &cx_, &d_, g, bs, gs)?;
+            bs.truncate(bs.len() - bs_.len());
+            Ok(())
+        }
+        (Expr::Sub(x, e_1, e_2), _) => {
+            let t_1 = e_1.infer_with(cx, d, g)?;
+            bs.push((x.to_owned(), quote_goal(&t_1, cx, d, g)?));
+            let v = g.zeta().then(|| e_1.eval_with(d, g)).transpose()?;
+            let (cx_, d_) = bind_let(x, t_1, v, cx, d);
+            goals_in(e_2, t, &cx_, &d_, g, bs, gs)?;
+            bs.pop();
+            Ok(())
+        }
+        // Only arguments may have holes, the function giving their type.
+        (Expr::App(e_1, e_2), _) if !holes(e_2).is_empty() => {
+            let t_ = e_1.infer_with(cx, d, g)?;
+
+            let Value::Fun(t_1, t_2) = t_.unfolded(g)? else {
+                return Err("not a function".into());
+            };
+
+            goals_in(e_2, t_1, cx, d, g, bs, gs)?;
+
+            if !convertible_lazily(t_2, t, cx, d, g)? {
+                return Err("type mismatch".into());
+            }
+
+            Ok(())
+        }
+        _ => e.check_with(t, cx, d, g),
+    }
+}
+
+// Replaces a hole of a term.
+pub fn fill(e: &Expr, h: &str, e_: &Expr) -> Expr {
+    let b = Box::new;
+    let go = |e: &Expr| b(fill(e, h, e_));
+
+    match e {
+        Expr::App(e_1, e_2) => Expr::App(go(e_1), go(e_2)),
+        Expr::Cons(e_1, e_2) => Expr::Cons(go(e_1), go(e_2)),
+        Expr::Fun(e_1, e_2) => Expr::Fun(go(e_1), go(e_2)),
+        Expr::Hole(h_) if h_ == h => e_.to_owned(),
+        Expr::Inst(e_1, e_2) => Expr::Inst(go(e_1), go(e_2)),
+        Expr::Lam(x, a, e) => Expr::Lam(x.to_owned(), a.as_deref().map(go), go(e)),
+        Expr::Let(bs, e) => Expr::Let(
+            bs.iter()
+                .map(|(x, e)| (x.to_owned(), fill(e, h, e_)))
+                .collect(),
+            go(e),
+        ),
+        Expr::LetRec(bs, e) => Expr::LetRec(
+            bs.iter()
+                .map(|(x, a, e)| (x.to_owned(), fill(a, h, e_), fill(e, h, e_)))
+                .collect(),
+            go(e),
+        ),
+        Expr::List(e) => Expr::List(go(e)),
+        Expr::ListRec(e_1, e_2, e_3) => Expr::ListRec(go(e_1), go(e_2), go(e_3)),
+        Expr::Pi(x, e_1, e_2) => Expr::Pi(x.to_owned(), go(e_1), go(e_2)),
+        Expr::Sub(x, e_1, e_2) => Expr::Sub(x.to_owned(), go(e_1), go(e_2)),
+        _ => e.to_owned(),
+    }
+}
+
+// Replaces the hole `h` of `e`, a term of type `t`, by an elimination of the
+// local variable `x` with one case per constructor of its type, each a new
+// hole.
+pub fn split(
+    e: &Expr,
+    t: &Type,
+    h: &str,
+    x: &str,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<Expr, Error> {
+    let gs = goals(e, t, cx, d, g)?;
+    let goal = gs
+        .iter()
+        .find(|goal| goal.name == h)
+        .ok_or("unknown hole")?;
+
+    let (_, t_x) = goal
+        .context
+        .iter()
+        .rev()
+        .find(|(y, _)| y == x)
+        .ok_or("unknown identifier")?;
+
+    let hs = holes(e);
+    let hs = hs.iter().map(String::as_str).collect();
+    let h_nil = freshen(format!("{h}_nil"), &hs);
+    let h_cons = freshen(format!("{h}_cons"), &hs);
+
+    let mut xs = cx.keys().map(String::as_str).collect::<HashSet<_>>();
+    xs.extend(goal.context.iter().map(|(y, _)| y.as_str()));
+    let y = freshen("y".to_owned(), &xs);
+    let ys = freshen("ys".to_owned(), &xs);
+    let r = freshen("r".to_owned(), &xs);
+
+    let b = Box::new;
+
+    let e_ = match t_x {
+        Expr::List(_) => Expr::ListRec(
+            b(Expr::Var(x.to_owned())),
+            b(Expr::Hole(h_nil)),
+            b(Expr::Lam(
+                y,
+                None,
+                b(Expr::Lam(
+                    ys,
+                    None,
+                    b(Expr::Lam(r, None, b(Expr::Hole(h_cons)))),
+                )),
+            )),
+        ),
+        _ => return Err("not an inductive type".into()),
+    };
+
+    Ok(fill(e, h, &e_))
+}
+
+// Replaces the hole `h` of `e`, a term of type `t`, by lambdas binding the
+// arguments of its type, around a hole of the same name. No type has a
+// single constructor to introduce otherwise.
+pub fn refine(
+    e: &Expr,
+    t: &Type,
+    h: &str,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<Expr, Error> {
+    let gs = goals(e, t, cx, d, g)?;
+    let goal = gs
+        .iter()
+        .find(|goal| goal.name == h)
+        .ok_or("unknown hole")?;
+    let mut xs = cx.keys().map(String::as_str).collect::<HashSet<_>>();
+    xs.extend(goal.context.iter().map(|(y, _)| y.as_str()));
+    let mut ys = vec![];
+    let mut t = goal.ty.eval_with(d, g)?;
+
+    loop {
+        let xs_ = xs.iter().copied().chain(ys.iter().map(String::as_str));
+
+        t = match t {
+            Value::Fun(_, t_2) | Value::Inst(_, t_2) => {
+                ys.push(freshen("x".to_owned(), &xs_.collect()));
+                *t_2
+            }
+            Value::Pi(_, c) => {
+                let y = freshen(c.x.as_str().to_owned(), &xs_.collect());
+                ys.push(y.to_owned());
+                c.apply_with(Value::Neutral(Neutral::Var(y)), g)?
+            }
+            _ => break,
+        };
+    }
+
+    if ys.is_empty() {
+        return Err("nothing to refine".into());
+    }
+
+    let e_ = ys.into_iter().rev().fold(Expr::Hole(h.to_owned()), |e, y| {
+        Expr::Lam(y, None, Box::new(e))
+    });
+
+    Ok(fill(e, h, &e_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::Driver, notation::Notations, parser::parse_expr};
+
+    #[test]
+    fn holes_are_split() {
+        let mut driver = Driver::new();
+        driver.run("def N : U 0 := Int").unwrap();
+
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let g = Guard::default();
+        let t = parse("List N -> Int -> N").eval(&driver.d);
+        let e = parse("\\xs n. int_add n ?h");
+
+        let gs = goals(&e, &t, &driver.cx, &driver.d, &g).unwrap();
+        assert_eq!(
+            gs.iter().map(Goal::to_string).collect::<Vec<_>>(),
+            vec!["xs : List Int, n : Int |- ?h : Int"]
+        );
+
+        let e = split(&e, &t, "h", "xs", &driver.cx, &driver.d, &g).unwrap();
+        assert_eq!(
+            e.to_string(),
+            "\\xs n. int_add n (ListRec xs ?h_nil (\\y ys r. ?h_cons))"
+        );
+
+        let gs = goals(&e, &t, &driver.cx, &driver.d, &g).unwrap();
+        assert_eq!(
+            gs.iter().map(Goal::to_string).collect::<Vec<_>>(),
+            vec![
+                "xs : List Int, n : Int |- ?h_nil : Int",
+                "xs : List Int, n : Int, y : Int, ys : List Int, r : Int |- ?h_cons : Int"
+            ]
+        );
+
+        let e = split(&e, &t, "h_nil", "n", &driver.cx, &driver.d, &g);
+        assert_eq!(e.unwrap_err(), "not an inductive type");
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use crate::{
+    decl::Def, notation::Notation, options::Guard, Context, Env, Error, Expr, Identifier, Neutral,
+    Value,
+};
+
+// What an importing module sees of a definition: its type, and its body
+// unless the definition is opaque.
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub name: Identifier,
+    pub ty: Expr,
+    pub body: Option<Expr>,
+}
+
+// The contents of a `.sdi` file, enough to check dependent modules without
+// loading the bodies of opaque definitions.
+#[derive(Clone, Debug, Default)]
+pub struct Interface {
+    pub module: Option<Identifier>,
+    pub imports: Vec<Identifier>,
+    pub notations: Vec<Notation>,
+    pub sigs: Vec<Signature>,
+    pub coercions: Vec<Identifier>,
+    pub instances: Vec<Identifier>,
+    pub axioms: Vec<Identifier>,
+}
+
+impl From<&Def> for Signature {
+    fn from(def: &Def) -> Self {
+        Self {
+            name: def.name.to_owned(),
+            ty: def.ty.to_owned(),
+            body: (!def.opaque).then(|| def.body.to_owned()),
+        }
+    }
+}
+
+impl Signature {
+    // Bodies were checked when the interface was written, so only the type
+    // is checked again. A body edited since then may fail to evaluate, which
+    // is an error rather than a panic.
+    pub fn load(&self, cx: &mut Context, d: &mut Env) -> Result<(), Error> {
+        let g = Guard::default();
+        let t = self.ty.check_type(cx, d, &g)?;
+
+        let v = match &self.body {
+            Some(e) => e.eval_with(d, &g)?,
+            None => Value::Neutral(Neutral::Var(self.name.to_owned())),
+        };
+
+        cx.insert(self.name.to_owned(), t);
+        d.define(self.name.to_owned(), v);
+        Ok(())
+    }
+}
+
+// Terms are printed without notations, so that an interface parses before
+// the notations of its imports are known.
+impl fmt::Display for Interface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(x) = &self.module {
+            writeln!(f, "module {x}")?;
+        }
+
+        for x in &self.imports {
+            writeln!(f, "import {x}")?;
+        }
+
+        for n in &self.notations {
+            writeln!(
+                f,
+                "{} {} _{}_ := {}",
+                n.fixity.keyword(),
+                n.precedence,
+                n.symbol,
+                n.name
+            )?;
+        }
+
+        for s in &self.sigs {
+            match &s.body {
+                Some(e) => writeln!(f, "def {} : {} := {e}", s.name, s.ty)?,
+                None => writeln!(f, "opaque def {} : {}", s.name, s.ty)?,
+            }
+        }
+
+        for x in &self.coercions {
+            writeln!(f, "coercion {x}")?;
+        }
+
+        for x in &self.instances {
+            writeln!(f, "instance {x}")?;
+        }
+
+        for x in &self.axioms {
+            writeln!(f, "axiom {x}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,2253 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Index,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    conv::{convertible_lazily, Unfold},
+    decl::fixpoints,
+    diff::children,
+    names::{NameSupply, Primes},
+    options::{unguarded, Guard},
+    symbol::Symbol,
+    term::Term,
+    Error,
+};
+
+pub type Identifier = String;
+
+// The values of variables. Locals are kept by binder level, innermost
+// last, in front of the globals, which the environments extending one
+// another share rather than copy.
+#[derive(Clone, Default)]
+pub struct Env {
+    globals: Arc<HashMap<Identifier, Value>>,
+    locals: Vec<(Symbol, Value)>,
+    // The globals evaluation may unfold, all of them when unset. The others
+    // stand for themselves.
+    unfold: Option<Arc<Unfold>>,
+    // Whether the globals evaluation unfolds are kept folded alongside, as
+    // they are in types.
+    glued: bool,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Binds a local variable at the next level, shadowing any variable of
+    // the same name.
+    pub fn insert(&mut self, x: Identifier, v: Value) {
+        self.push(Symbol::intern(&x), v);
+    }
+
+    pub(crate) fn push(&mut self, x: Symbol, v: Value) {
+        self.locals.push((x, v));
+    }
+
+    // Defines a global, replacing any previous definition of it.
+    pub fn define(&mut self, x: Identifier, v: Value) {
+        Arc::make_mut(&mut self.globals).insert(x, v);
+    }
+
+    // Looks a variable up by name, for callers holding names rather than
+    // levels. Evaluation finds locals by level instead.
+    pub fn get(&self, x: &str) -> Option<&Value> {
+        let local =
+            Symbol::lookup(x).and_then(|x| self.locals.iter().rev().find(|&&(y, _)| y == x));
+
+        match local {
+            Some((_, v)) => Some(v),
+            None => self.globals.get(x),
+        }
+    }
+
+    pub fn global(&self, x: &str) -> Option<&Value> {
+        self.globals.get(x)
+    }
+
+    // The definition of a global as evaluation finds it, if the policy of
+    // the environment allows unfolding it.
+    pub fn unfold(&self, x: &str) -> Option<&Value> {
+        match &self.unfold {
+            Some(policy) if !policy.allows(x) => None,
+            _ => self.globals.get(x),
+        }
+    }
+
+    // The names of the locals, by level.
+    pub fn local_symbols(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.locals.iter().map(|&(x, _)| x)
+    }
+
+    pub fn contains_key(&self, x: &str) -> bool {
+        self.get(x).is_some()
+    }
+
+    // The same environment, unfolding only the globals `policy` allows.
+    pub fn unfolding(&self, policy: Unfold) -> Self {
+        Self {
+            unfold: Some(Arc::new(policy)),
+            ..self.to_owned()
+        }
+    }
+
+    // The same environment, keeping the globals evaluation unfolds folded
+    // alongside.
+    pub fn gluing(&self) -> Self {
+        Self {
+            glued: true,
+            ..self.to_owned()
+        }
+    }
+
+    // The value a global evaluates to, folded alongside if the environment
+    // keeps it so, or the global itself if it may not be unfolded.
+    pub(crate) fn global_value(&self, x: &str) -> Value {
+        match self.unfold(x) {
+            Some(v) if self.glued => Value::Glued(Box::new(Glued::new(x, v))),
+            Some(v) => v.to_owned(),
+            None => Value::Neutral(Neutral::Var(x.to_owned())),
+        }
+    }
+
+    // The names of the variables, shadowed locals included.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.globals
+            .keys()
+            .map(String::as_str)
+            .chain(self.locals.iter().map(|(x, _)| x.as_str()))
+    }
+
+    // The names of the variables, with those of the fresh variables the
+    // locals bound by binders stand for, which quotation keeps apart.
+    pub fn names(&self) -> HashSet<&str> {
+        let vars = self.locals.iter().filter_map(|(_, v)| match v {
+            Value::Neutral(Neutral::Var(x)) => Some(x.as_str()),
+            _ => None,
+        });
+
+        self.keys().chain(vars).collect()
+    }
+
+    // The number of locals, which is the level the next one is bound at.
+    pub fn level(&self) -> usize {
+        self.locals.len()
+    }
+
+    // The value of the local bound at level `k`.
+    pub fn at(&self, k: usize) -> &Value {
+        &self.locals[k].1
+    }
+}
+
+impl Index<&str> for Env {
+    type Output = Value;
+
+    fn index(&self, x: &str) -> &Value {
+        self.get(x).expect("unknown variable")
+    }
+}
+
+impl<const N: usize> From<[(Identifier, Value); N]> for Env {
+    fn from(vs: [(Identifier, Value); N]) -> Self {
+        vs.into_iter().collect()
+    }
+}
+
+impl FromIterator<(Identifier, Value)> for Env {
+    fn from_iter<T: IntoIterator<Item = (Identifier, Value)>>(vs: T) -> Self {
+        Self {
+            globals: Arc::default(),
+            locals: vs
+                .into_iter()
+                .map(|(x, v)| (Symbol::intern(&x), v))
+                .collect(),
+            unfold: None,
+            glued: false,
+        }
+    }
+}
+
+pub type Context = BTreeMap<Identifier, Type>;
+
+pub type Type = Value;
+
+// The bindings of a let block, in order.
+pub type Bindings<N = Identifier> = Vec<(N, Expr<N>)>;
+
+// The bindings of a recursive let block, each with its type, in order.
+pub type RecBindings<N = Identifier> = Vec<(N, Expr<N>, Expr<N>)>;
+
+pub type Level = u8;
+
+#[derive(Clone, Debug)]
+pub enum Expr<N = Identifier> {
+    App(Box<Expr<N>>, Box<Expr<N>>),
+    // The type of quoted terms.
+    Code,
+    Cons(Box<Expr<N>>, Box<Expr<N>>),
+    // A part of a source file that did not parse, left in place by error
+    // recovery so that the rest can still be checked.
+    Error,
+    Fun(Box<Expr<N>>, Box<Expr<N>>),
+    // ?h, a part of a term left to be filled in, which never type checks.
+    Hole(N),
+    // [A] -> B, a function whose argument is found by instance search.
+    Inst(Box<Expr<N>>, Box<Expr<N>>),
+    Int,
+    IntLit(i64),
+    // \x. e, or \(x : A). e with the type of the binder given.
+    Lam(N, Option<Box<Expr<N>>>, Box<Expr<N>>),
+    // let x_1 := e_1; ...; x_n := e_n in e, binding every x_i at once. The
+    // bindings are in the scope of the block, so they cannot refer to each
+    // other.
+    Let(Bindings<N>, Box<Expr<N>>),
+    // let rec x_1 : A_1 := e_1; ...; x_n : A_n := e_n in e, whose bindings
+    // may refer to each other. The types are in the scope of the block, and
+    // the bindings must pass the termination checker like a `mutual` block.
+    LetRec(RecBindings<N>, Box<Expr<N>>),
+    List(Box<Expr<N>>),
+    ListRec(Box<Expr<N>>, Box<Expr<N>>, Box<Expr<N>>),
+    Nil,
+    // Π (x : A) -> B, a function type whose codomain may mention the argument.
+    Pi(N, Box<Expr<N>>, Box<Expr<N>>),
+    Prim(Prim),
+    // quote e, the syntax of a term as a value of type `Code`. It is not
+    // checked until it is spliced.
+    Quote(Box<Expr<N>>),
+    // splice e, the term whose syntax `e` computes to, checked where it is
+    // spliced.
+    Splice(Box<Expr<N>>),
+    Str,
+    StrLit(String),
+    Sub(N, Box<Expr<N>>, Box<Expr<N>>),
+    U(Level),
+    Var(N),
+}
+
+// Terms can be given names of another type by embedders, such as interned
+// symbols or indices, and converted back to be checked.
+impl<N> Expr<N> {
+    pub fn map_names<'a, M>(&'a self, f: &mut impl FnMut(&'a N) -> M) -> Expr<M> {
+        let mut b = |e: &'a Expr<N>| Box::new(e.map_names(f));
+
+        match self {
+            Self::App(e_1, e_2) => Expr::App(b(e_1), b(e_2)),
+            Self::Code => Expr::Code,
+            Self::Cons(e_1, e_2) => Expr::Cons(b(e_1), b(e_2)),
+            Self::Error => Expr::Error,
+            Self::Fun(e_1, e_2) => Expr::Fun(b(e_1), b(e_2)),
+            Self::Hole(h) => Expr::Hole(f(h)),
+            Self::Inst(e_1, e_2) => Expr::Inst(b(e_1), b(e_2)),
+            Self::Int => Expr::Int,
+            &Self::IntLit(n) => Expr::IntLit(n),
+            Self::Lam(x, a, e) => {
+                let x = f(x);
+                let a = a.as_ref().map(|a| Box::new(a.map_names(f)));
+                Expr::Lam(x, a, Box::new(e.map_names(f)))
+            }
+            Self::Let(bs, e) => {
+                let bs = bs.iter().map(|(x, e)| (f(x), e.map_names(f))).collect();
+                Expr::Let(bs, Box::new(e.map_names(f)))
+            }
+            Self::LetRec(bs, e) => {
+                let bs = bs
+                    .iter()
+                    .map(|(x, a, e)| (f(x), a.map_names(f), e.map_names(f)))
+                    .collect();
+                Expr::LetRec(bs, Box::new(e.map_names(f)))
+            }
+            Self::List(e) => Expr::List(b(e)),
+            Self::ListRec(e_1, e_2, e_3) => Expr::ListRec(b(e_1), b(e_2), b(e_3)),
+            Self::Nil => Expr::Nil,
+            Self::Pi(x, e_1, e_2) => {
+                let x = f(x);
+                Expr::Pi(x, Box::new(e_1.map_names(f)), Box::new(e_2.map_names(f)))
+            }
+            &Self::Prim(p) => Expr::Prim(p),
+            Self::Quote(e) => Expr::Quote(b(e)),
+            Self::Splice(e) => Expr::Splice(b(e)),
+            Self::Str => Expr::Str,
+            Self::StrLit(s) => Expr::StrLit(s.to_owned()),
+            Self::Sub(x, e_1, e_2) => {
+                let x = f(x);
+                Expr::Sub(x, Box::new(e_1.map_names(f)), Box::new(e_2.map_names(f)))
+            }
+            &Self::U(i) => Expr::U(i),
+            Self::Var(x) => Expr::Var(f(x)),
+        }
+    }
+}
+
+impl<'a> From<&'a Expr> for Expr<&'a str> {
+    fn from(e: &'a Expr) -> Self {
+        e.map_names(&mut |x| x.as_str())
+    }
+}
+
+impl From<Expr<&str>> for Expr {
+    fn from(e: Expr<&str>) -> Self {
+        e.map_names(&mut |&x| x.to_owned())
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.alpha_eq(other, 0, &HashMap::new(), &HashMap::new())
+    }
+}
+
+impl Expr {
+    pub fn alpha_eq(
+        &self,
+        other: &Self,
+        i: usize,
+        xs: &HashMap<&str, usize>,
+        ys: &HashMap<&str, usize>,
+    ) -> bool {
+        match (self, other) {
+            (Self::App(e_1, e_2), Self::App(e_3, e_4)) => {
+                e_1.alpha_eq(e_3, i, xs, ys) && e_2.alpha_eq(e_4, i, xs, ys)
+            }
+            (Self::Lam(x, a_1, e_1), Self::Lam(y, a_2, e_2)) => {
+                match (a_1, a_2) {
+                    (None, None) => {}
+                    (Some(a_1), Some(a_2)) if a_1.alpha_eq(a_2, i, xs, ys) => {}
+                    _ => return false,
+                }
+
+                e_1.alpha_eq(
+                    e_2,
+                    i + 1,
+                    &{
+                        let mut xs_ = xs.to_owned();
+                        xs_.insert(x, i);
+                        xs_
+                    },
+                    &{
+                        let mut ys_ = ys.to_owned();
+                        ys_.insert(y, i);
+                        ys_
+                    },
+                )
+            }
+            (Self::Cons(e_1, e_2), Self::Cons(e_3, e_4))
+            | (Self::Fun(e_1, e_2), Self::Fun(e_3, e_4))
+            | (Self::Inst(e_1, e_2), Self::Inst(e_3, e_4)) => {
+                e_1.alpha_eq(e_3, i, xs, ys) && e_2.alpha_eq(e_4, i, xs, ys)
+            }
+            (Self::Let(bs_1, e_1), Self::Let(bs_2, e_2)) => {
+                let (mut xs_, mut ys_) = (xs.to_owned(), ys.to_owned());
+
+                for (k, ((x, e_3), (y, e_4))) in bs_1.iter().zip(bs_2).enumerate() {
+                    if !e_3.alpha_eq(e_4, i, xs, ys) {
+                        return false;
+                    }
+
+                    xs_.insert(x, i + k);
+                    ys_.insert(y, i + k);
+                }
+
+                bs_1.len() == bs_2.len() && e_1.alpha_eq(e_2, i + bs_1.len(), &xs_, &ys_)
+            }
+            // The types are compared outside the block, and the bindings
+            // inside it.
+            (Self::LetRec(bs_1, e_1), Self::LetRec(bs_2, e_2)) => {
+                let (mut xs_, mut ys_) = (xs.to_owned(), ys.to_owned());
+
+                for (k, ((x, a_1, _), (y, a_2, _))) in bs_1.iter().zip(bs_2).enumerate() {
+                    if !a_1.alpha_eq(a_2, i, xs, ys) {
+                        return false;
+                    }
+
+                    xs_.insert(x, i + k);
+                    ys_.insert(y, i + k);
+                }
+
+                let i_ = i + bs_1.len();
+
+                bs_1.len() == bs_2.len()
+                    && bs_1
+                        .iter()
+                        .zip(bs_2)
+                        .all(|((_, _, e_3), (_, _, e_4))| e_3.alpha_eq(e_4, i_, &xs_, &ys_))
+                    && e_1.alpha_eq(e_2, i_, &xs_, &ys_)
+            }
+            (Self::List(e_1), Self::List(e_2))
+            | (Self::Quote(e_1), Self::Quote(e_2))
+            | (Self::Splice(e_1), Self::Splice(e_2)) => e_1.alpha_eq(e_2, i, xs, ys),
+            (Self::ListRec(e_1, e_2, e_3), Self::ListRec(e_4, e_5, e_6)) => {
+                e_1.alpha_eq(e_4, i, xs, ys)
+                    && e_2.alpha_eq(e_5, i, xs, ys)
+                    && e_3.alpha_eq(e_6, i, xs, ys)
+            }
+            (Self::Pi(x, e_1, e_2), Self::Pi(y, e_3, e_4))
+            | (Self::Sub(x, e_1, e_2), Self::Sub(y, e_3, e_4)) => {
+                e_1.alpha_eq(e_3, i, xs, ys)
+                    && e_2.alpha_eq(
+                        e_4,
+                        i + 1,
+                        &{
+                            let mut xs_ = xs.to_owned();
+                            xs_.insert(x, i);
+                            xs_
+                        },
+                        &{
+                            let mut ys_ = ys.to_owned();
+                            ys_.insert(y, i);
+                            ys_
+                        },
+                    )
+            }
+            (Self::Code, Self::Code)
+            | (Self::Error, Self::Error)
+            | (Self::Int, Self::Int)
+            | (Self::Nil, Self::Nil)
+            | (Self::Str, Self::Str) => true,
+            (Self::IntLit(m), Self::IntLit(n)) => m == n,
+            (Self::Prim(p), Self::Prim(q)) => p == q,
+            (Self::StrLit(a), Self::StrLit(b)) => a == b,
+            (Self::U(i), Self::U(j)) => i == j,
+            (Self::Var(x), Self::Var(y)) => match (xs.get(x.as_str()), ys.get(y.as_str())) {
+                (None, None) => x == y,
+                (Some(j), Some(k)) => j == k,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        1 + match self {
+            Self::App(e_1, e_2)
+            | Self::Cons(e_1, e_2)
+            | Self::Fun(e_1, e_2)
+            | Self::Inst(e_1, e_2) => e_1.size() + e_2.size(),
+            Self::Lam(_, a, e) => a.as_ref().map_or(0, |a| a.size()) + e.size(),
+            Self::Let(bs, e) => bs.iter().map(|(_, e)| e.size()).sum::<usize>() + e.size(),
+            Self::LetRec(bs, e) => {
+                bs.iter()
+                    .map(|(_, a, e)| a.size() + e.size())
+                    .sum::<usize>()
+                    + e.size()
+            }
+            Self::List(e) | Self::Quote(e) | Self::Splice(e) => e.size(),
+            Self::ListRec(e_1, e_2, e_3) => e_1.size() + e_2.size() + e_3.size(),
+            Self::Pi(_, e_1, e_2) | Self::Sub(_, e_1, e_2) => e_1.size() + e_2.size(),
+            _ => 0,
+        }
+    }
+
+    pub fn free_vars(&self) -> HashSet<Identifier> {
+        match self {
+            Self::App(e_1, e_2)
+            | Self::Cons(e_1, e_2)
+            | Self::Fun(e_1, e_2)
+            | Self::Inst(e_1, e_2) => &e_1.free_vars() | &e_2.free_vars(),
+            Self::Lam(x, a, e) => {
+                let mut xs = e.free_vars();
+                xs.remove(x);
+                xs.extend(a.iter().flat_map(|a| a.free_vars()));
+                xs
+            }
+            Self::Let(bs, e) => {
+                let mut xs = e.free_vars();
+
+                for (x, _) in bs {
+                    xs.remove(x);
+                }
+
+                xs.extend(bs.iter().flat_map(|(_, e)| e.free_vars()));
+                xs
+            }
+            Self::LetRec(bs, e) => {
+                let mut xs = e.free_vars();
+                xs.extend(bs.iter().flat_map(|(_, _, e)| e.free_vars()));
+
+                for (x, _, _) in bs {
+                    xs.remove(x);
+                }
+
+                xs.extend(bs.iter().flat_map(|(_, a, _)| a.free_vars()));
+                xs
+            }
+            Self::List(e) => e.free_vars(),
+            Self::ListRec(e_1, e_2, e_3) => {
+                &(&e_1.free_vars() | &e_2.free_vars()) | &e_3.free_vars()
+            }
+            Self::Pi(x, e_1, e_2) | Self::Sub(x, e_1, e_2) => {
+                let mut xs = e_2.free_vars();
+                xs.remove(x);
+                &xs | &e_1.free_vars()
+            }
+            Self::Quote(e) | Self::Splice(e) => e.free_vars(),
+            Self::Var(x) => HashSet::from([x.to_owned()]),
+            _ => HashSet::new(),
+        }
+    }
+
+    pub fn occurs(&self, x: &str) -> bool {
+        match self {
+            Self::App(e_1, e_2)
+            | Self::Cons(e_1, e_2)
+            | Self::Fun(e_1, e_2)
+            | Self::Inst(e_1, e_2) => e_1.occurs(x) || e_2.occurs(x),
+            Self::Lam(y, a, e) => {
+                a.as_ref().is_some_and(|a| a.occurs(x)) || (x != y && e.occurs(x))
+            }
+            Self::Let(bs, e) => {
+                bs.iter().any(|(_, e)| e.occurs(x))
+                    || (bs.iter().all(|(y, _)| x != y) && e.occurs(x))
+            }
+            Self::LetRec(bs, e) => {
+                bs.iter().any(|(_, a, _)| a.occurs(x))
+                    || (bs.iter().all(|(y, _, _)| x != y)
+                        && (e.occurs(x) || bs.iter().any(|(_, _, e)| e.occurs(x))))
+            }
+            Self::List(e) => e.occurs(x),
+            Self::ListRec(e_1, e_2, e_3) => e_1.occurs(x) || e_2.occurs(x) || e_3.occurs(x),
+            Self::Pi(y, e_1, e_2) | Self::Sub(y, e_1, e_2) => {
+                e_1.occurs(x) || (x != y && e_2.occurs(x))
+            }
+            Self::Quote(e) | Self::Splice(e) => e.occurs(x),
+            Self::Var(y) => x == y,
+            _ => false,
+        }
+    }
+
+    // How many times `x` occurs free.
+    pub fn uses(&self, x: &str) -> usize {
+        match self {
+            Self::App(e_1, e_2)
+            | Self::Cons(e_1, e_2)
+            | Self::Fun(e_1, e_2)
+            | Self::Inst(e_1, e_2) => e_1.uses(x) + e_2.uses(x),
+            Self::Lam(y, a, e) => {
+                a.as_ref().map_or(0, |a| a.uses(x)) + if x != y { e.uses(x) } else { 0 }
+            }
+            Self::Let(bs, e) => {
+                let k = bs.iter().map(|(_, e)| e.uses(x)).sum::<usize>();
+
+                if bs.iter().all(|(y, _)| x != y) {
+                    k + e.uses(x)
+                } else {
+                    k
+                }
+            }
+            Self::LetRec(bs, e) => {
+                let k = bs.iter().map(|(_, a, _)| a.uses(x)).sum::<usize>();
+
+                if bs.iter().all(|(y, _, _)| x != y) {
+                    k + bs.iter().map(|(_, _, e)| e.uses(x)).sum::<usize>() + e.uses(x)
+                } else {
+                    k
+                }
+            }
+            Self::List(e) | Self::Quote(e) | Self::Splice(e) => e.uses(x),
+            Self::ListRec(e_1, e_2, e_3) => e_1.uses(x) + e_2.uses(x) + e_3.uses(x),
+            Self::Pi(y, e_1, e_2) | Self::Sub(y, e_1, e_2) => {
+                e_1.uses(x) + if x != y { e_2.uses(x) } else { 0 }
+            }
+            Self::Var(y) => usize::from(x == y),
+            _ => 0,
+        }
+    }
+
+    // The term in a scope without `x`, which it must not mention.
+    pub fn strengthen(&self, x: &str) -> Result<Expr, Error> {
+        if self.occurs(x) {
+            return Err("variable escapes its scope".into());
+        }
+
+        Ok(self.to_owned())
+    }
+
+    pub fn check(&self, t: &Type, cx: &Context) -> Result<(), Error> {
+        self.check_with(t, cx, &Env::new(), &Guard::default())
+    }
+
+    // Types are checked against the definitions in `d`, which binder
+    // annotations are evaluated in.
+    pub fn check_with(&self, t: &Type, cx: &Context, d: &Env, g: &Guard) -> Result<(), Error> {
+        g.tick()?;
+
+        match (self, t.unfolded(g)?) {
+            (Self::Cons(e_1, e_2), Type::List(t_1)) => {
+                e_1.check_with(t_1, cx, d, g)?;
+                e_2.check_with(t, cx, d, g)
+            }
+            // Each side may live in a lower universe than the function type.
+            (Self::Fun(e_1, e_2) | Self::Inst(e_1, e_2), Type::U(_)) => {
+                e_1.check_with(t, cx, d, g)?;
+                e_2.check_with(t, cx, d, g)
+            }
+            (Self::Lam(x, a, e), Type::Fun(t_1, _) | Type::Inst(t_1, _) | Type::Pi(t_1, _)) => {
+                check_annotation(a.as_deref(), t_1, cx, d, g)?;
+                let (cx_, d_) = bind(x, t_1.as_ref().to_owned(), cx, d);
+                let t_2 = t.codomain(d_[x].to_owned(), g)?;
+                e.check_with(&t_2, &cx_, &d_, g)
+            }
+            (Self::Let(bs, e), _) => {
+                let (cx_, d_) = bind_block(bs, g.zeta(), cx, d, g)?;
+                e.check_with(t, &cx_, &d_, g)
+            }
+            (Self::LetRec(bs, e), _) => {
+                let (cx_, d_) = bind_rec_block(bs, g.zeta(), cx, d, g)?;
+                e.check_with(t, &cx_, &d_, g)
+            }
+            (Self::List(e), Type::U(_)) => e.check_with(t, cx, d, g),
+            (Self::Pi(x, e_1, e_2), Type::U(_)) => {
+                e_1.check_with(t, cx, d, g)?;
+                let (cx_, d_) = bind(x, e_1.eval_with(d, g)?, cx, d);
+                e_2.check_with(t, &cx_, &d_, g)
+            }
+            (Self::ListRec(e_1, e_2, e_3), _) => {
+                let t_1 = e_1.infer_with(cx, d, g)?;
+                e_2.check_with(t, cx, d, g)?;
+                e_3.check_with(&list_rec_step(t_1, t.to_owned(), g)?, cx, d, g)
+            }
+            (Self::Nil, Type::List(_)) => Ok(()),
+            (Self::Splice(e), _) => e.splice(cx, d, g)?.check_with(t, cx, d, g),
+            (Self::Sub(x, e_1, e_2), _) => {
+                let t_1 = e_1.infer_with(cx, d, g)?;
+                let v = g.zeta().then(|| e_1.eval_with(d, g)).transpose()?;
+                let (cx_, d_) = bind_let(x, t_1, v, cx, d);
+                e_2.check_with(t, &cx_, &d_, g)
+            }
+            (Self::U(i), Type::U(j)) if i < j => Ok(()),
+            (Self::Lam(_, _, _) | Self::Nil | Self::Cons(_, _), _) => Err(mismatch(self, t)),
+            _ => {
+                let t_ = self.infer_with(cx, d, g)?;
+
+                // Universes are cumulative.
+                if let (Value::U(i), Value::U(j)) = (t_.unfolded(g)?, t.unfolded(g)?) {
+                    return if i <= j {
+                        Ok(())
+                    } else {
+                        Err("universe inconsistency".into())
+                    };
+                }
+
+                if !convertible_lazily(&t_, t, cx, d, g)? {
+                    return Err(mismatch(self, t));
+                };
+
+                Ok(())
+            }
+        }
+    }
+
+    // Checks the term against a type like `check_with` does, keeping it
+    // as proof that it checked.
+    pub fn certify(self, t: &Type, cx: &Context, d: &Env, g: &Guard) -> Result<CheckedExpr, Error> {
+        self.check_with(t, cx, d, g)?;
+
+        Ok(CheckedExpr {
+            term: self,
+            ty: t.to_owned(),
+        })
+    }
+
+    // Infers the type of the term, keeping it as proof that it checked.
+    pub fn certify_infer(self, cx: &Context, d: &Env, g: &Guard) -> Result<CheckedExpr, Error> {
+        let ty = self.infer_with(cx, d, g)?;
+        Ok(CheckedExpr { term: self, ty })
+    }
+
+    // Checks that the term is a type and evaluates it, keeping the globals
+    // it mentions folded for conversion to compare first.
+    pub fn check_type(&self, cx: &Context, d: &Env, g: &Guard) -> Result<Type, Error> {
+        let Value::U(_) = self.infer_with(cx, d, g)?.unfolded(g)? else {
+            return Err("not a type".into());
+        };
+
+        self.eval_with(&d.gluing(), g)
+    }
+
+    // Evaluates a checked term, panicking where `eval_with` would fail.
+    pub fn eval(&self, d: &Env) -> Value {
+        unguarded(self.eval_with(d, &Guard::default()))
+    }
+
+    // Evaluates a term like `eval_with` does, except that a variable missing
+    // from `d` is an error unless it is declared in `cx`, in which case it
+    // stands for itself. `eval_with` takes every missing variable to stand
+    // for itself, which hides being given the wrong environment.
+    pub fn eval_open(&self, cx: &Context, d: &Env, g: &Guard) -> Result<Value, Error> {
+        if self
+            .free_vars()
+            .iter()
+            .any(|x| !d.contains_key(x) && !cx.contains_key(x))
+        {
+            return Err("unbound variable".into());
+        }
+
+        self.eval_with(d, g)
+    }
+
+    // Names are resolved to levels once, and the term evaluated by them.
+    pub fn eval_with(&self, d: &Env, g: &Guard) -> Result<Value, Error> {
+        self.resolve_levels(d).eval_with(d, g)
+    }
+
+    // The term a splice stands for, which has to be computed when it is
+    // checked.
+    fn splice(&self, cx: &Context, d: &Env, g: &Guard) -> Result<Expr, Error> {
+        self.check_with(&Value::Code, cx, d, g)?;
+
+        match self.eval_with(d, g)? {
+            Value::Quote(e) => Ok(*e),
+            _ => Err("cannot splice an unknown term".into()),
+        }
+    }
+
+    // The name of the constructor of a term, as `code_kind` gives it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::App(_, _) => "app",
+            Self::Code => "code",
+            Self::Cons(_, _) => "cons",
+            Self::Error => "error",
+            Self::Fun(_, _) => "fun",
+            Self::Hole(_) => "hole",
+            Self::Inst(_, _) => "inst",
+            Self::Int => "int",
+            Self::IntLit(_) => "int_lit",
+            Self::Lam(_, _, _) => "lam",
+            Self::Let(_, _) => "let_block",
+            Self::LetRec(_, _) => "let_rec",
+            Self::List(_) => "list",
+            Self::ListRec(_, _, _) => "list_rec",
+            Self::Nil => "nil",
+            Self::Pi(_, _, _) => "pi",
+            Self::Prim(_) => "prim",
+            Self::Quote(_) => "quote",
+            Self::Splice(_) => "splice",
+            Self::Str => "string",
+            Self::StrLit(_) => "str_lit",
+            Self::Sub(_, _, _) => "let",
+            Self::U(_) => "universe",
+            Self::Var(_) => "var",
+        }
+    }
+
+    // The name a term refers to or binds, as `code_name` gives it, or the
+    // empty string.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Hole(x)
+            | Self::Lam(x, _, _)
+            | Self::Pi(x, _, _)
+            | Self::Sub(x, _, _)
+            | Self::Var(x) => x,
+            Self::Prim(p) => p.name(),
+            _ => "",
+        }
+    }
+
+    pub fn infer(&self, cx: &Context) -> Result<Type, Error> {
+        self.infer_with(cx, &Env::new(), &Guard::default())
+    }
+
+    pub fn infer_with(&self, cx: &Context, d: &Env, g: &Guard) -> Result<Type, Error> {
+        g.tick()?;
+
+        match self {
+            Self::App(e_1, e_2) => match e_1.infer_with(cx, d, g)?.unfolded(g)? {
+                Value::Fun(v_1, v_2) | Value::Inst(v_1, v_2) => {
+                    e_2.check_with(v_1, cx, d, g)?;
+                    Ok(v_2.as_ref().to_owned())
+                }
+                // The codomain is instantiated with the argument.
+                Value::Pi(v_1, c) => {
+                    e_2.check_with(v_1, cx, d, g)?;
+                    c.apply_with(e_2.eval_with(d, g)?, g)
+                }
+                _ => Err("not a function".into()),
+            },
+            Self::Cons(e_1, e_2) => match e_1.infer_with(cx, d, g) {
+                Ok(t_1) => {
+                    let t = Value::List(Box::new(t_1));
+                    e_2.check_with(&t, cx, d, g)?;
+                    Ok(t)
+                }
+                Err(_) => {
+                    let t = e_2.infer_with(cx, d, g)?;
+
+                    let Value::List(t_1) = t.unfolded(g)? else {
+                        return Err("not a list".into());
+                    };
+
+                    e_1.check_with(t_1, cx, d, g)?;
+                    Ok(t)
+                }
+            },
+            Self::Fun(e_1, e_2) | Self::Inst(e_1, e_2) => {
+                let (t_1, t_2) = (e_1.infer_with(cx, d, g)?, e_2.infer_with(cx, d, g)?);
+
+                let (&Value::U(i), &Value::U(j)) = (t_1.unfolded(g)?, t_2.unfolded(g)?) else {
+                    return Err("not a type".into());
+                };
+
+                Ok(Value::U(i.max(j)))
+            }
+            Self::Code | Self::Int | Self::Str => Ok(Value::U(0)),
+            Self::IntLit(_) => Ok(Value::Int),
+            Self::Lam(x, Some(a), e) => {
+                let t_1 = a.check_type(cx, d, g)?;
+                let (cx_, d_) = bind(x, t_1.to_owned(), cx, d);
+                let t_2 = e.infer_with(&cx_, &d_, g)?;
+                let e_2 = t_2.quote_with(&taken(&cx_, &d_), g)?;
+                let Value::Neutral(Neutral::Var(x_)) = &d_[x] else {
+                    unreachable!()
+                };
+
+                // The type of the body is a normal form, so whatever it
+                // mentions besides the binder stays neutral.
+                if e_2.occurs(x_) {
+                    Ok(Value::Pi(
+                        Box::new(t_1),
+                        Closure::new(x_, &e_2, &Env::new()),
+                    ))
+                } else {
+                    Ok(Value::Fun(Box::new(t_1), Box::new(t_2)))
+                }
+            }
+            Self::List(e) => {
+                let &Value::U(i) = e.infer_with(cx, d, g)?.unfolded(g)? else {
+                    return Err("not a type".into());
+                };
+
+                Ok(Value::U(i))
+            }
+            Self::Let(bs, e) => {
+                let (cx_, d_) = bind_block(bs, true, cx, d, g)?;
+                e.infer_with(&cx_, &d_, g)
+            }
+            Self::LetRec(bs, e) => {
+                let (cx_, d_) = bind_rec_block(bs, true, cx, d, g)?;
+                e.infer_with(&cx_, &d_, g)
+            }
+            Self::ListRec(e_1, e_2, e_3) => {
+                let t_1 = e_1.infer_with(cx, d, g)?;
+                let t = e_2.infer_with(cx, d, g)?;
+                e_3.check_with(&list_rec_step(t_1, t.to_owned(), g)?, cx, d, g)?;
+                Ok(t)
+            }
+            Self::Pi(x, e_1, e_2) => {
+                let &Value::U(i) = e_1.infer_with(cx, d, g)?.unfolded(g)? else {
+                    return Err("not a type".into());
+                };
+
+                let (cx_, d_) = bind(x, e_1.eval_with(d, g)?, cx, d);
+
+                let &Value::U(j) = e_2.infer_with(&cx_, &d_, g)?.unfolded(g)? else {
+                    return Err("not a type".into());
+                };
+
+                Ok(Value::U(i.max(j)))
+            }
+            Self::Prim(p) if p.is_observational() && !g.observational() => {
+                Err("observational equality is not enabled".into())
+            }
+            Self::Prim(p) => Ok(p.ty()),
+            Self::Quote(_) => Ok(Value::Code),
+            Self::Splice(e) => e.splice(cx, d, g)?.infer_with(cx, d, g),
+            Self::StrLit(_) => Ok(Value::Str),
+            Self::Sub(x, e_1, e_2) => {
+                let t_1 = e_1.infer_with(cx, d, g)?;
+                let mut cx_ = cx.to_owned();
+                cx_.insert(x.to_owned(), t_1);
+                let mut d_ = d.to_owned();
+                d_.insert(x.to_owned(), e_1.eval_with(d, g)?);
+                e_2.infer_with(&cx_, &d_, g)
+            }
+            &Self::U(i) => i
+                .checked_add(1)
+                .map(Value::U)
+                .ok_or("universe level overflow".into()),
+            Self::Var(x) => cx.get(x).cloned().ok_or("unknown identifier".into()),
+            Self::Error => Err("syntax error".into()),
+            Self::Hole(_) => Err("unsolved hole".into()),
+            _ => Err("could not infer type".into()),
+        }
+    }
+}
+
+// The type a binder is annotated with, if any, has to be the one it is
+// checked against.
+pub(crate) fn check_annotation(
+    a: Option<&Expr>,
+    t: &Type,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<(), Error> {
+    let Some(a) = a else {
+        return Ok(());
+    };
+
+    let t_ = a.check_type(cx, d, g)?;
+
+    if !convertible_lazily(&t_, t, cx, d, g)? {
+        return Err("type mismatch".into());
+    }
+
+    Ok(())
+}
+
+// The error for a term that does not have the type `t`, telling what kind of
+// type its form calls for when it shows.
+pub(crate) fn mismatch(e: &Expr, t: &Type) -> Error {
+    match e {
+        Expr::Lam(_, _, _) => "lambda checked against a non-function type",
+        Expr::Cons(_, _) | Expr::Nil => "list checked against a non-list type",
+        Expr::IntLit(_) => "integer literal checked against a non-integer type",
+        Expr::StrLit(_) => "string literal checked against a non-string type",
+        Expr::Quote(_) => "quoted term checked against a type other than Code",
+        Expr::Code
+        | Expr::Fun(_, _)
+        | Expr::Inst(_, _)
+        | Expr::Int
+        | Expr::List(_)
+        | Expr::Pi(_, _, _)
+        | Expr::Str
+        | Expr::U(_)
+            if !matches!(t.unfolded(&Guard::default()), Ok(Value::U(_))) =>
+        {
+            "type checked against a non-universe type"
+        }
+        _ => "type mismatch",
+    }
+    .into()
+}
+
+// Extends the context with a local variable, which stands for itself and
+// shadows any definition of the same name.
+pub(crate) fn bind(x: &str, t: Type, cx: &Context, d: &Env) -> (Context, Env) {
+    // A binder shadowing a variable stands for a fresh one, so that types
+    // mentioning the shadowed variable keep referring to it. Only values
+    // know the fresh name: terms refer to the binder by its own.
+    let x_ = freshen(x.to_owned(), &taken(cx, d));
+    let mut cx_ = cx.to_owned();
+    cx_.insert(x.to_owned(), t);
+    let mut d_ = d.to_owned();
+    d_.insert(x.to_owned(), Value::Neutral(Neutral::Var(x_)));
+    (cx_, d_)
+}
+
+// The names a variable bound in the scope of `cx` and `d` must be apart
+// from.
+pub(crate) fn taken<'a>(cx: &'a Context, d: &'a Env) -> HashSet<&'a str> {
+    let mut xs = d.names();
+    xs.extend(cx.keys().map(String::as_str));
+    xs
+}
+
+// A type quoted in the scope of `cx` and `d`, the variables bound there
+// written with the names of their binders. Gives `None` if it mentions a
+// variable shadowed by a later binder, which no name refers to.
+pub(crate) fn quote_in(t: &Type, cx: &Context, d: &Env, g: &Guard) -> Result<Option<Expr>, Error> {
+    let e = t.quote_with(&taken(cx, d), g)?;
+    let mut s = HashMap::new();
+    let mut bound = HashSet::new();
+
+    for (x, v) in d.locals.iter().rev() {
+        let x = x.as_str();
+        let first = bound.insert(x);
+
+        let Value::Neutral(Neutral::Var(x_)) = v else {
+            continue;
+        };
+
+        if !first && e.occurs(x_) {
+            return Ok(None);
+        }
+
+        if first && x != x_ {
+            s.insert(x_.to_owned(), Expr::Var(x.to_owned()));
+        }
+    }
+
+    Ok(Some(e.instantiate(&s)))
+}
+
+// The context and environment under `let x := e`, where `e` has type `t`
+// and value `v`, or is opaque without one.
+pub(crate) fn bind_let(
+    x: &str,
+    t: Type,
+    v: Option<Value>,
+    cx: &Context,
+    d: &Env,
+) -> (Context, Env) {
+    let Some(v) = v else {
+        return bind(x, t, cx, d);
+    };
+
+    let mut cx_ = cx.to_owned();
+    cx_.insert(x.to_owned(), t);
+    let mut d_ = d.to_owned();
+    d_.insert(x.to_owned(), v);
+    (cx_, d_)
+}
+
+// The context and environment under a let block, whose bindings are
+// inferred and evaluated in the scope of the block. Without zeta, they are
+// opaque. A let whose type is inferred always unfolds, since its type may
+// mention what it binds.
+fn bind_block(
+    bs: &[(Identifier, Expr)],
+    zeta: bool,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<(Context, Env), Error> {
+    let (mut cx_, mut d_) = (cx.to_owned(), d.to_owned());
+
+    for (k, (x, e)) in bs.iter().enumerate() {
+        if bs[..k].iter().any(|(y, _)| x == y) {
+            return Err("duplicate definition".into());
+        }
+
+        let v = zeta.then(|| e.eval_with(d, g)).transpose()?;
+        (cx_, d_) = bind_let(x, e.infer_with(cx, d, g)?, v, &cx_, &d_);
+    }
+
+    Ok((cx_, d_))
+}
+
+// The context and environment under a recursive let block. The types are
+// checked in the scope of the block, and the bindings against them with the
+// whole block bound. Without zeta, the bindings are opaque.
+pub(crate) fn bind_rec_block(
+    bs: &[(Identifier, Expr, Expr)],
+    zeta: bool,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<(Context, Env), Error> {
+    let mut ts = vec![];
+
+    for (k, (x, a, _)) in bs.iter().enumerate() {
+        if bs[..k].iter().any(|(y, _, _)| x == y) {
+            return Err("duplicate definition".into());
+        }
+
+        ts.push(a.check_type(cx, d, g)?);
+    }
+
+    let (mut cx_1, mut d_1) = (cx.to_owned(), d.to_owned());
+
+    for ((x, _, _), t) in bs.iter().zip(&ts) {
+        (cx_1, d_1) = bind(x, t.to_owned(), &cx_1, &d_1);
+    }
+
+    for ((_, _, e), t) in bs.iter().zip(&ts) {
+        e.check_with(t, &cx_1, &d_1, g)?;
+    }
+
+    let vs = rec_values(bs, cx, d, g)?;
+    let (mut cx_, mut d_) = (cx.to_owned(), d.to_owned());
+
+    for (((x, _, _), t), v) in bs.iter().zip(ts).zip(vs) {
+        (cx_, d_) = bind_let(x, t, zeta.then_some(v), &cx_, &d_);
+    }
+
+    Ok((cx_, d_))
+}
+
+// The values of the bindings of a recursive let block, which must pass the
+// termination checker like a `mutual` block.
+pub(crate) fn rec_values(
+    bs: &[(Identifier, Expr, Expr)],
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<Vec<Value>, Error> {
+    let names = bs.iter().map(|(x, _, _)| x.to_owned()).collect::<Vec<_>>();
+    let refers = |k: usize, j: usize| bs[k].2.occurs(&bs[j].0);
+
+    fixpoints(&names, refers, &taken(cx, d), g, |k, vs| {
+        let mut d_ = d.to_owned();
+
+        for (x, v) in names.iter().zip(vs) {
+            d_.insert(x.to_owned(), v.to_owned());
+        }
+
+        bs[k].2.eval_with(&d_, g)
+    })
+}
+
+// The type A -> List A -> T -> T of the cons case of a fold over List A.
+pub(crate) fn list_rec_step(t_1: Type, t: Type, g: &Guard) -> Result<Type, Error> {
+    let Value::List(t_2) = t_1.unfolded(g)? else {
+        return Err("not a list".into());
+    };
+
+    let fun = |v_1, v_2| Value::Fun(Box::new(v_1), Box::new(v_2));
+    Ok(fun(t_2.as_ref().to_owned(), fun(t_1, fun(t.to_owned(), t))))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Prim {
+    CodeApp,
+    CodeArgs,
+    CodeInt,
+    CodeKind,
+    CodeName,
+    CodeVar,
+    IntAdd,
+    IntMul,
+    IntShow,
+    IntSub,
+    // obs_eq A x y, the type of proofs that x and y are equal, computed
+    // from the structure of A. Experimental, behind
+    // `CheckOptions::observational`.
+    ObsEq,
+    // obs_refl A x, a proof of obs_eq A x x.
+    ObsRefl,
+    StrAppend,
+    StrLength,
+}
+
+impl Prim {
+    pub const ALL: [Self; 14] = [
+        Self::CodeApp,
+        Self::CodeArgs,
+        Self::CodeInt,
+        Self::CodeKind,
+        Self::CodeName,
+        Self::CodeVar,
+        Self::IntAdd,
+        Self::IntMul,
+        Self::IntShow,
+        Self::IntSub,
+        Self::ObsEq,
+        Self::ObsRefl,
+        Self::StrAppend,
+        Self::StrLength,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CodeApp => "code_app",
+            Self::CodeArgs => "code_args",
+            Self::CodeInt => "code_int",
+            Self::CodeKind => "code_kind",
+            Self::CodeName => "code_name",
+            Self::CodeVar => "code_var",
+            Self::IntAdd => "int_add",
+            Self::IntMul => "int_mul",
+            Self::IntShow => "int_show",
+            Self::IntSub => "int_sub",
+            Self::ObsEq => "obs_eq",
+            Self::ObsRefl => "obs_refl",
+            Self::StrAppend => "str_append",
+            Self::StrLength => "str_length",
+        }
+    }
+
+    pub fn from_name(x: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == x)
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Self::CodeArgs
+            | Self::CodeInt
+            | Self::CodeKind
+            | Self::CodeName
+            | Self::CodeVar
+            | Self::IntShow
+            | Self::StrLength => 1,
+            Self::CodeApp
+            | Self::IntAdd
+            | Self::IntMul
+            | Self::IntSub
+            | Self::ObsRefl
+            | Self::StrAppend => 2,
+            Self::ObsEq => 3,
+        }
+    }
+
+    pub fn is_observational(self) -> bool {
+        matches!(self, Self::ObsEq | Self::ObsRefl)
+    }
+
+    pub fn ty(self) -> Type {
+        let fun = |v_1, v_2| Value::Fun(Box::new(v_1), Box::new(v_2));
+
+        match self {
+            Self::CodeApp => fun(Value::Code, fun(Value::Code, Value::Code)),
+            Self::CodeArgs => fun(Value::Code, Value::List(Box::new(Value::Code))),
+            Self::CodeInt => fun(Value::Int, Value::Code),
+            Self::CodeKind | Self::CodeName => fun(Value::Code, Value::Str),
+            Self::CodeVar => fun(Value::Str, Value::Code),
+            Self::IntAdd | Self::IntMul | Self::IntSub => {
+                fun(Value::Int, fun(Value::Int, Value::Int))
+            }
+            Self::IntShow => fun(Value::Int, Value::Str),
+            // Π (A : U 1) -> A -> A -> U 1
+            Self::ObsEq => pi(
+                "A",
+                Value::U(1),
+                Expr::Fun(
+                    var("A"),
+                    Box::new(Expr::Fun(var("A"), Box::new(Expr::U(1)))),
+                ),
+                Env::new(),
+            ),
+            // Π (A : U 1) (x : A) -> obs_eq A x x
+            Self::ObsRefl => pi(
+                "A",
+                Value::U(1),
+                Expr::Pi(
+                    "x".to_owned(),
+                    var("A"),
+                    Box::new(apps(Self::ObsEq, ["A", "x", "x"].map(|x| *var(x)))),
+                ),
+                Env::new(),
+            ),
+            Self::StrAppend => fun(Value::Str, fun(Value::Str, Value::Str)),
+            Self::StrLength => fun(Value::Str, Value::Int),
+        }
+    }
+
+    // Arguments are collected until the primitive is saturated; it then
+    // computes natively, or stays stuck if an argument is not a literal.
+    pub(crate) fn apply(self, vs: Vec<Value>) -> Value {
+        if vs.len() < self.arity() {
+            return Value::Prim(self, vs);
+        }
+
+        let quote = |e| Value::Quote(Box::new(e));
+
+        match (self, vs.as_slice()) {
+            (Self::CodeApp, [Value::Quote(e_1), Value::Quote(e_2)]) => {
+                quote(Expr::App(e_1.to_owned(), e_2.to_owned()))
+            }
+            // The subterms of a term, in order, without the names they bind.
+            (Self::CodeArgs, [Value::Quote(e)]) => {
+                children(e).into_iter().rev().fold(Value::Nil, |v, (e, _)| {
+                    Value::Cons(Box::new(quote(e.to_owned())), Box::new(v))
+                })
+            }
+            (Self::CodeInt, [Value::IntLit(n)]) => quote(Expr::IntLit(*n)),
+            (Self::CodeKind, [Value::Quote(e)]) => Value::StrLit(e.kind().to_owned()),
+            (Self::CodeName, [Value::Quote(e)]) => Value::StrLit(e.name().to_owned()),
+            (Self::CodeVar, [Value::StrLit(x)]) => {
+                quote(Self::from_name(x).map_or_else(|| Expr::Var(x.to_owned()), Expr::Prim))
+            }
+            (Self::IntAdd, [Value::IntLit(m), Value::IntLit(n)]) => {
+                Value::IntLit(m.wrapping_add(*n))
+            }
+            (Self::IntMul, [Value::IntLit(m), Value::IntLit(n)]) => {
+                Value::IntLit(m.wrapping_mul(*n))
+            }
+            (Self::IntShow, [Value::IntLit(n)]) => Value::StrLit(n.to_string()),
+            (Self::IntSub, [Value::IntLit(m), Value::IntLit(n)]) => {
+                Value::IntLit(m.wrapping_sub(*n))
+            }
+            (Self::StrAppend, [Value::StrLit(a), Value::StrLit(b)]) => {
+                Value::StrLit(format!("{a}{b}"))
+            }
+            (Self::StrLength, [Value::StrLit(a)]) => Value::IntLit(a.chars().count() as i64),
+            (Self::ObsEq, [t, v_1, v_2]) => obs_eq(t, v_1, v_2)
+                .unwrap_or_else(|| Value::Neutral(Neutral::Prim(self, vs.to_owned()))),
+            (Self::ObsRefl, [t, v]) => {
+                obs_refl(t, v).unwrap_or_else(|| Value::Neutral(Neutral::Prim(self, vs.to_owned())))
+            }
+            _ => Value::Neutral(Neutral::Prim(self, vs)),
+        }
+    }
+}
+
+fn var(x: &str) -> Box<Expr> {
+    Box::new(Expr::Var(x.to_owned()))
+}
+
+fn apps<const N: usize>(p: Prim, es: [Expr; N]) -> Expr {
+    es.into_iter().fold(Expr::Prim(p), |e_1, e_2| {
+        Expr::App(Box::new(e_1), Box::new(e_2))
+    })
+}
+
+fn pi(x: &str, t: Type, e: Expr, d: Env) -> Type {
+    Value::Pi(Box::new(t), Closure::new(x, &e, &d))
+}
+
+fn env<const N: usize>(vs: [(&str, &Value); N]) -> Env {
+    vs.into_iter()
+        .map(|(x, v)| (x.to_owned(), v.to_owned()))
+        .collect()
+}
+
+// Π (P : U 0) -> P -> P, the equality of equal literals.
+fn unit() -> Type {
+    pi("P", Value::U(0), Expr::Fun(var("P"), var("P")), Env::new())
+}
+
+// Π (P : U 0) -> P, the equality of different ones.
+fn empty() -> Type {
+    pi("P", Value::U(0), *var("P"), Env::new())
+}
+
+// The equality of two values of type `t`: functions are equal when they are
+// equal at every argument, lists when their heads and tails are, and
+// literals when they are the same. It is stuck on anything else.
+fn obs_eq(t: &Type, v_1: &Value, v_2: &Value) -> Option<Type> {
+    let at_x = |f: &str| Expr::App(var(f), var("x"));
+
+    Some(match (t, v_1, v_2) {
+        (Value::Fun(t_1, t_2), _, _) => pi(
+            "x",
+            t_1.as_ref().to_owned(),
+            apps(Prim::ObsEq, [*var("B"), at_x("f"), at_x("g")]),
+            env([("B", t_2), ("f", v_1), ("g", v_2)]),
+        ),
+        (Value::Pi(t_1, c), _, _) => pi(
+            "x",
+            t_1.as_ref().to_owned(),
+            apps(Prim::ObsEq, [at_x("C"), at_x("f"), at_x("g")]),
+            env([("C", &Value::Lam(c.to_owned())), ("f", v_1), ("g", v_2)]),
+        ),
+        (Value::Int, Value::IntLit(m), Value::IntLit(n)) if m == n => unit(),
+        (Value::Str, Value::StrLit(a), Value::StrLit(b)) if a == b => unit(),
+        (Value::Int, Value::IntLit(_), Value::IntLit(_))
+        | (Value::Str, Value::StrLit(_), Value::StrLit(_)) => empty(),
+        (Value::List(_), Value::Nil, Value::Nil) => unit(),
+        (Value::List(_), Value::Nil, Value::Cons(_, _))
+        | (Value::List(_), Value::Cons(_, _), Value::Nil) => empty(),
+        // Π (P : U 0) -> (obs_eq T h_1 h_2 -> obs_eq (List T) t_1 t_2 -> P) -> P
+        (Value::List(t_1), Value::Cons(h_1, t_2), Value::Cons(h_3, t_4)) => {
+            let eq = |t: Expr, x: &str, y: &str| apps(Prim::ObsEq, [t, *var(x), *var(y)]);
+            let heads = eq(*var("T"), "h_1", "h_2");
+            let tails = eq(Expr::List(var("T")), "t_1", "t_2");
+            let k = Expr::Fun(
+                Box::new(heads),
+                Box::new(Expr::Fun(Box::new(tails), var("P"))),
+            );
+
+            pi(
+                "P",
+                Value::U(0),
+                Expr::Fun(Box::new(k), var("P")),
+                env([
+                    ("T", t_1),
+                    ("h_1", h_1),
+                    ("h_2", h_3),
+                    ("t_1", t_2),
+                    ("t_2", t_4),
+                ]),
+            )
+        }
+        _ => return None,
+    })
+}
+
+// The proof of `obs_eq t v v`, by the same cases.
+fn obs_refl(t: &Type, v: &Value) -> Option<Value> {
+    let lam = |x: &str, e: Expr, d: Env| Value::Lam(Closure::new(x, &e, &d));
+    let at_x = |f: &str| Expr::App(var(f), var("x"));
+
+    Some(match (t, v) {
+        (Value::Fun(_, t_2), _) => lam(
+            "x",
+            apps(Prim::ObsRefl, [*var("B"), at_x("f")]),
+            env([("B", t_2), ("f", v)]),
+        ),
+        (Value::Pi(_, c), _) => lam(
+            "x",
+            apps(Prim::ObsRefl, [at_x("C"), at_x("f")]),
+            env([("C", &Value::Lam(c.to_owned())), ("f", v)]),
+        ),
+        (Value::Int, Value::IntLit(_))
+        | (Value::Str, Value::StrLit(_))
+        | (Value::List(_), Value::Nil) => {
+            lam("P", Expr::Lam("p".to_owned(), None, var("p")), Env::new())
+        }
+        // \P k. k (obs_refl T h) (obs_refl (List T) t)
+        (Value::List(t_1), Value::Cons(h, t_2)) => {
+            let k = Expr::App(
+                Box::new(Expr::App(
+                    var("k"),
+                    Box::new(apps(Prim::ObsRefl, [*var("T"), *var("h")])),
+                )),
+                Box::new(apps(Prim::ObsRefl, [Expr::List(var("T")), *var("t")])),
+            );
+
+            lam(
+                "P",
+                Expr::Lam("k".to_owned(), None, Box::new(k)),
+                env([("T", t_1), ("h", h), ("t", t_2)]),
+            )
+        }
+        _ => return None,
+    })
+}
+
+#[derive(Clone)]
+pub enum Neutral {
+    App(Box<Neutral>, Box<Value>),
+    // A recursive definition, within the block defining it.
+    Fix(Fix),
+    ListRec(Box<Neutral>, Box<Value>, Box<Value>),
+    Prim(Prim, Vec<Value>),
+    Var(Identifier),
+}
+
+// A definition of a recursive block as the bodies of the block refer to it.
+// Once the block passes the termination checker, it unfolds where it is
+// applied to a constructor at the parameter that shrinks at every recursive
+// call, which only happens finitely often. Anywhere else it stands for
+// itself, so that normal forms stay finite.
+#[derive(Clone)]
+pub struct Fix {
+    pub x: Identifier,
+    // The shrinking parameter and the definition, set once the block is
+    // known to terminate.
+    def: Arc<OnceLock<(usize, Value)>>,
+}
+
+impl Fix {
+    pub fn new(x: &str) -> Self {
+        Self {
+            x: x.to_owned(),
+            def: Arc::default(),
+        }
+    }
+
+    // Lets the definition unfold, at arguments of which the `k`th is a
+    // constructor.
+    pub fn define(&self, k: usize, v: Value) {
+        let _ = self.def.set((k, v));
+    }
+}
+
+impl Neutral {
+    fn occurs(&self, x: &str) -> bool {
+        match self {
+            Self::App(n, v) => n.occurs(x) || v.occurs(x),
+            Self::Fix(f) => x == f.x,
+            Self::ListRec(n, v_1, v_2) => n.occurs(x) || v_1.occurs(x) || v_2.occurs(x),
+            Self::Prim(_, vs) => vs.iter().any(|v| v.occurs(x)),
+            Self::Var(y) => x == y,
+        }
+    }
+
+    // What a recursive definition applied to arguments unfolds to, if they
+    // are enough to reach the shrinking parameter and it is a constructor.
+    fn unfold_fix(&self, g: &Guard) -> Result<Option<Value>, Error> {
+        let mut n = self;
+        let mut vs = vec![];
+
+        while let Self::App(n_, v) = n {
+            vs.push(v.as_ref());
+            n = n_;
+        }
+
+        let Self::Fix(f) = n else {
+            return Ok(None);
+        };
+
+        let Some((k, v)) = f.def.get() else {
+            return Ok(None);
+        };
+
+        vs.reverse();
+
+        if vs.len() != k + 1 || !matches!(vs[*k].unfolded(g)?, Value::Cons(_, _) | Value::Nil) {
+            return Ok(None);
+        }
+
+        vs.into_iter()
+            .try_fold(v.to_owned(), |v_1, v_2| v_1.apply_with(v_2.to_owned(), g))
+            .map(Some)
+    }
+
+    fn quote_as(
+        &self,
+        xs: &HashSet<&str>,
+        ns: &dyn NameSupply,
+        policy: &Unfold,
+        g: &Guard,
+    ) -> Result<Expr, Error> {
+        let q = |v: &Value| v.quote_as(xs, ns, policy, g).map(Box::new);
+
+        Ok(match self {
+            Self::App(n, v) => Expr::App(Box::new(n.quote_as(xs, ns, policy, g)?), q(v)?),
+            Self::Fix(f) => Expr::Var(f.x.to_owned()),
+            Self::ListRec(n, v_1, v_2) => {
+                Expr::ListRec(Box::new(n.quote_as(xs, ns, policy, g)?), q(v_1)?, q(v_2)?)
+            }
+            Self::Prim(p, vs) => quote_spine(Expr::Prim(*p), vs, xs, ns, policy, g)?,
+            Self::Var(x) => Expr::Var(x.to_owned()),
+        })
+    }
+}
+
+// A head applied to the quoted arguments.
+fn quote_spine(
+    e: Expr,
+    vs: &[Value],
+    xs: &HashSet<&str>,
+    ns: &dyn NameSupply,
+    policy: &Unfold,
+    g: &Guard,
+) -> Result<Expr, Error> {
+    vs.iter().try_fold(e, |e, v| {
+        Ok(Expr::App(
+            Box::new(e),
+            Box::new(v.quote_as(xs, ns, policy, g)?),
+        ))
+    })
+}
+
+// A term the kernel checked, with its type. Only the kernel makes one, so
+// the APIs taking one need not check it again, and the rest of the pipeline
+// cannot pass them a term it forgot to check.
+#[derive(Clone)]
+pub struct CheckedExpr {
+    term: Expr,
+    ty: Type,
+}
+
+impl CheckedExpr {
+    pub fn term(&self) -> &Expr {
+        &self.term
+    }
+
+    pub fn ty(&self) -> &Type {
+        &self.ty
+    }
+
+    pub fn into_term(self) -> Expr {
+        self.term
+    }
+
+    pub fn into_parts(self) -> (Expr, Type) {
+        (self.term, self.ty)
+    }
+}
+
+#[derive(Clone)]
+pub enum Value {
+    Code,
+    Cons(Box<Value>, Box<Value>),
+    Fun(Box<Value>, Box<Value>),
+    Inst(Box<Value>, Box<Value>),
+    Int,
+    IntLit(i64),
+    Lam(Closure),
+    List(Box<Value>),
+    // A global applied to arguments, kept folded where a type mentions it.
+    Glued(Box<Glued>),
+    Neutral(Neutral),
+    Nil,
+    Pi(Box<Value>, Closure),
+    Prim(Prim, Vec<Value>),
+    Quote(Box<Expr>),
+    Str,
+    StrLit(String),
+    U(Level),
+}
+
+// A global applied to arguments as it was written, alongside what it
+// unfolds to, so that conversion can compare types before unfolding their
+// definitions. The unfolding is only computed once something looks through
+// it, and then shared by the copies.
+#[derive(Clone)]
+pub struct Glued {
+    pub x: Identifier,
+    pub args: Vec<Value>,
+    def: Value,
+    unfolded: Arc<OnceLock<Value>>,
+}
+
+impl Glued {
+    fn new(x: &str, def: &Value) -> Self {
+        Self {
+            x: x.to_owned(),
+            args: vec![],
+            def: def.to_owned(),
+            unfolded: Arc::default(),
+        }
+    }
+
+    fn apply(&self, v: Value) -> Self {
+        let mut args = self.args.to_owned();
+        args.push(v);
+
+        Self {
+            x: self.x.to_owned(),
+            args,
+            def: self.def.to_owned(),
+            unfolded: Arc::default(),
+        }
+    }
+
+    // The definition of the global applied to the arguments.
+    pub fn unfolded(&self, g: &Guard) -> Result<&Value, Error> {
+        if let Some(v) = self.unfolded.get() {
+            return Ok(v);
+        }
+
+        let v = self.args.iter().try_fold(self.def.to_owned(), |v_1, v_2| {
+            v_1.apply_with(v_2.to_owned(), g)
+        })?;
+
+        Ok(self.unfolded.get_or_init(|| v))
+    }
+}
+
+// A term under a binder together with the environment it was evaluated in,
+// standing for a function from the value of the binder.
+#[derive(Clone)]
+pub struct Closure {
+    pub x: Symbol,
+    pub e: Arc<Term>,
+    pub d: Env,
+}
+
+impl Closure {
+    pub fn new(x: &str, e: &Expr, d: &Env) -> Self {
+        let x = Symbol::intern(x);
+        Self::resolved(x, &Arc::new(e.resolve_under(x, d)), d)
+    }
+
+    // A closure of a body already resolved under the binder, which it
+    // shares.
+    pub fn resolved(x: Symbol, e: &Arc<Term>, d: &Env) -> Self {
+        Self {
+            x,
+            e: Arc::clone(e),
+            d: d.to_owned(),
+        }
+    }
+
+    // Like `apply_with`, for closures of checked terms.
+    pub fn apply(&self, v: Value) -> Value {
+        unguarded(self.apply_with(v, &Guard::default()))
+    }
+
+    pub fn apply_with(&self, v: Value, g: &Guard) -> Result<Value, Error> {
+        let mut d = self.d.to_owned();
+        d.push(self.x, v);
+        self.e.eval_with(&d, g)
+    }
+
+    // Whether applying the closure to anything but `x` would mention it.
+    fn occurs(&self, x: &str) -> bool {
+        let (mut ks, mut ys) = (HashSet::new(), HashSet::new());
+        self.e.free(self.d.level(), &mut ks, &mut ys);
+
+        ks.iter().any(|&k| self.d.at(k).occurs(x))
+            || ys.iter().any(|y| match self.d.global(y) {
+                Some(v) => v.occurs(x),
+                None => x == y,
+            })
+    }
+
+    // Applies the closure to a fresh variable chosen by `ns`, returning its
+    // name along with the result.
+    fn open(
+        &self,
+        xs: &HashSet<&str>,
+        ns: &dyn NameSupply,
+        g: &Guard,
+    ) -> Result<(Identifier, Value), Error> {
+        // `_` is never referred to, so it need not be fresh.
+        let x_ = match self.x.as_str() {
+            "_" => "_".to_owned(),
+            x => ns.fresh(x, xs),
+        };
+        let v = self.apply_with(Value::Neutral(Neutral::Var(x_.clone())), g)?;
+        Ok((x_, v))
+    }
+}
+
+pub fn freshen(mut x: Identifier, xs: &HashSet<&str>) -> Identifier {
+    if xs.contains(x.as_str()) {
+        x.push('\'');
+        freshen(x, xs)
+    } else {
+        x
+    }
+}
+
+impl Value {
+    // The value with the global it is headed by unfolded, if it kept one
+    // folded. This is what anything matching on the form of a type looks
+    // at.
+    pub fn unfolded(&self, g: &Guard) -> Result<&Value, Error> {
+        match self {
+            Self::Glued(v) => v.unfolded(g)?.unfolded(g),
+            v => Ok(v),
+        }
+    }
+
+    // The codomain of a function type at an argument.
+    pub fn codomain(&self, v: Value, g: &Guard) -> Result<Type, Error> {
+        match self.unfolded(g)? {
+            Self::Fun(_, t) | Self::Inst(_, t) => Ok(t.as_ref().to_owned()),
+            Self::Pi(_, c) => c.apply_with(v, g),
+            _ => Err("not a function".into()),
+        }
+    }
+
+    // Like `apply_with`, for values of checked terms.
+    pub fn apply(self, v: Value) -> Value {
+        unguarded(self.apply_with(v, &Guard::default()))
+    }
+
+    pub fn apply_with(self, v: Value, g: &Guard) -> Result<Value, Error> {
+        match self {
+            Self::Lam(c) => {
+                g.unfold()?;
+                c.apply_with(v, g)
+            }
+            Self::Glued(v_1) => Ok(Self::Glued(Box::new(v_1.apply(v)))),
+            Self::Neutral(n) => {
+                let n = Neutral::App(Box::new(n), Box::new(v));
+
+                match n.unfold_fix(g)? {
+                    Some(v) => Ok(v),
+                    None => Ok(Self::Neutral(n)),
+                }
+            }
+            // Primitives compute on what their arguments unfold to.
+            Self::Prim(p, mut vs) => {
+                vs.push(v.unfolded(g)?.to_owned());
+                Ok(p.apply(vs))
+            }
+            _ => Err("not a function".into()),
+        }
+    }
+
+    // Whether the value mentions the variable `x`, as a neutral.
+    pub fn occurs(&self, x: &str) -> bool {
+        match self {
+            Self::Cons(v_1, v_2) | Self::Fun(v_1, v_2) | Self::Inst(v_1, v_2) => {
+                v_1.occurs(x) || v_2.occurs(x)
+            }
+            // Globals are closed, so only their arguments can mention `x`.
+            Self::Glued(v) => v.args.iter().any(|v| v.occurs(x)),
+            Self::Lam(c) => c.occurs(x),
+            Self::List(v) => v.occurs(x),
+            Self::Neutral(n) => n.occurs(x),
+            Self::Pi(v, c) => v.occurs(x) || c.occurs(x),
+            Self::Prim(_, vs) => vs.iter().any(|v| v.occurs(x)),
+            Self::Quote(e) => e.occurs(x),
+            _ => false,
+        }
+    }
+
+    // The value in the scope of `d` without the local bound at level `k`,
+    // which it must not mention. A local bound to a value rather than to a
+    // variable is never mentioned, having been evaluated away.
+    pub fn strengthen(&self, k: usize, d: &Env) -> Result<Value, Error> {
+        match d.at(k) {
+            Self::Neutral(Neutral::Var(x)) if self.occurs(x) => {
+                Err("variable escapes its scope".into())
+            }
+            _ => Ok(self.to_owned()),
+        }
+    }
+
+    // Like `list_rec_with`, for values of checked terms.
+    pub fn list_rec(self, v_1: Value, v_2: Value) -> Value {
+        unguarded(self.list_rec_with(v_1, v_2, &Guard::default()))
+    }
+
+    // ListRec nil f (cons h t) => f h t (ListRec nil f t)
+    pub fn list_rec_with(self, v_1: Value, v_2: Value, g: &Guard) -> Result<Value, Error> {
+        if let Self::Glued(v) = self {
+            return v.unfolded(g)?.to_owned().list_rec_with(v_1, v_2, g);
+        }
+
+        match self {
+            Self::Cons(h, t) => {
+                let v = t.as_ref().to_owned().list_rec_with(v_1, v_2.clone(), g)?;
+                v_2.apply_with(*h, g)?.apply_with(*t, g)?.apply_with(v, g)
+            }
+            Self::Neutral(n) => Ok(Self::Neutral(Neutral::ListRec(
+                Box::new(n),
+                Box::new(v_1),
+                Box::new(v_2),
+            ))),
+            Self::Nil => Ok(v_1),
+            _ => Err("not a list".into()),
+        }
+    }
+
+    // Like `quote_with`, for values of checked terms.
+    pub fn quote(&self, xs: &HashSet<&str>) -> Expr {
+        unguarded(self.quote_with(xs, &Guard::default()))
+    }
+
+    pub fn quote_with(&self, xs: &HashSet<&str>, g: &Guard) -> Result<Expr, Error> {
+        self.quote_named(xs, &Primes, g)
+    }
+
+    // Quotes with the names of bound variables chosen by `ns`, avoiding
+    // those in `xs`.
+    pub fn quote_named(
+        &self,
+        xs: &HashSet<&str>,
+        ns: &dyn NameSupply,
+        g: &Guard,
+    ) -> Result<Expr, Error> {
+        self.quote_as(xs, ns, &Unfold::All, g)
+    }
+
+    // Quotes with the globals kept folded left so unless `policy` allows
+    // unfolding them.
+    pub fn quote_unfolding(
+        &self,
+        xs: &HashSet<&str>,
+        policy: &Unfold,
+        g: &Guard,
+    ) -> Result<Expr, Error> {
+        self.quote_as(xs, &Primes, policy, g)
+    }
+
+    fn quote_as(
+        &self,
+        xs: &HashSet<&str>,
+        ns: &dyn NameSupply,
+        policy: &Unfold,
+        g: &Guard,
+    ) -> Result<Expr, Error> {
+        g.tick()?;
+        let q = |v: &Value| v.quote_as(xs, ns, policy, g).map(Box::new);
+
+        Ok(match self {
+            Self::Code => Expr::Code,
+            Self::Cons(v_1, v_2) => Expr::Cons(q(v_1)?, q(v_2)?),
+            Self::Fun(v_1, v_2) => Expr::Fun(q(v_1)?, q(v_2)?),
+            Self::Inst(v_1, v_2) => Expr::Inst(q(v_1)?, q(v_2)?),
+            Self::Lam(c) => {
+                let (x_, v) = c.open(xs, ns, g)?;
+                let mut xs_ = xs.to_owned();
+                xs_.insert(&x_);
+                let e_ = v.quote_as(&xs_, ns, policy, g)?;
+                Expr::Lam(x_, None, Box::new(e_))
+            }
+            Self::Int => Expr::Int,
+            &Self::IntLit(n) => Expr::IntLit(n),
+            Self::List(v) => Expr::List(q(v)?),
+            Self::Glued(v) if policy.allows(&v.x) => v.unfolded(g)?.quote_as(xs, ns, policy, g)?,
+            Self::Glued(v) => quote_spine(Expr::Var(v.x.to_owned()), &v.args, xs, ns, policy, g)?,
+            Self::Neutral(n) => n.quote_as(xs, ns, policy, g)?,
+            Self::Nil => Expr::Nil,
+            Self::Pi(v, c) => {
+                let e_1 = v.quote_as(xs, ns, policy, g)?;
+                let (x_, v) = c.open(xs, ns, g)?;
+                let mut xs_ = xs.to_owned();
+                xs_.insert(&x_);
+                let e_2 = v.quote_as(&xs_, ns, policy, g)?;
+
+                // A function type whose codomain does not depend on the
+                // argument is an arrow, however it was written.
+                if e_2.occurs(&x_) {
+                    Expr::Pi(x_, Box::new(e_1), Box::new(e_2))
+                } else {
+                    Expr::Fun(Box::new(e_1), Box::new(e_2))
+                }
+            }
+            Self::Prim(p, vs) => quote_spine(Expr::Prim(*p), vs, xs, ns, policy, g)?,
+            Self::Quote(e) => Expr::Quote(e.to_owned()),
+            Self::Str => Expr::Str,
+            Self::StrLit(a) => Expr::StrLit(a.to_owned()),
+            &Self::U(i) => Expr::U(i),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        notation::Notations,
+        options::{CheckOptions, NormStrategy},
+        parser::parse_expr,
+    };
+
+    #[test]
+    fn quotation_works() {
+        let e = Expr::App(
+            Box::new(Expr::Lam(
+                "x".to_string(),
+                None,
+                Box::new(Expr::Lam(
+                    "y".to_string(),
+                    None,
+                    Box::new(Expr::Var("x".to_string())),
+                )),
+            )),
+            Box::new(Expr::Var("y".to_string())),
+        );
+
+        let d = Env::new();
+        let v = e.eval(&d);
+        let mut xs = HashSet::new();
+        xs.insert("y");
+
+        assert_eq!(
+            v.quote(&xs),
+            Expr::Lam("y'".to_string(), None, Box::new(Expr::Var("y".to_string())))
+        );
+    }
+
+    #[test]
+    fn dependent_application_works() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let cx = Context::new();
+        let (d, xs) = (Env::new(), HashSet::new());
+
+        // (\A x. x) : forall (A : U 0) -> A -> A
+        let t = parse("forall (A : U 0) -> A -> A").eval(&d);
+        let id = parse("\\A x. x");
+        id.check(&t, &cx).unwrap();
+
+        let mut cx = Context::new();
+        cx.insert("id".to_string(), t.to_owned());
+        assert_eq!(
+            parse("id Int").infer(&cx).unwrap().quote(&xs),
+            parse("Int -> Int")
+        );
+        assert_eq!(parse("id Int 1").infer(&cx).unwrap().quote(&xs), Expr::Int);
+        assert!(parse("id Int \"1\"").infer(&cx).is_err());
+
+        let Value::Pi(_, c) = t else {
+            panic!();
+        };
+        assert_eq!(c.apply(Value::Str).quote(&xs), parse("String -> String"));
+    }
+
+    #[test]
+    fn function_types_bind_fresh_variables() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let cx = Context::new();
+        let d = Env::new();
+
+        // Domains and codomains may live in lower universes.
+        parse("Int -> U 0").check(&Value::U(1), &cx).unwrap();
+        parse("forall (A : U 0) -> A -> A")
+            .check(&Value::U(2), &cx)
+            .unwrap();
+        parse("List Int").check(&Value::U(1), &cx).unwrap();
+        let e = parse("U 0 -> Int").check(&Value::U(0), &cx).unwrap_err();
+        assert_eq!(e, "universe inconsistency");
+
+        // The inner A shadows the outer one, which x still has the type of.
+        let t = parse("forall (A : U 0) -> A -> (forall (B : U 0) -> B -> A)").eval(&d);
+        parse("\\A x A y. x").check(&t, &cx).unwrap();
+        assert!(parse("\\A x A y. y").check(&t, &cx).is_err());
+
+        // The fresh variable the inner A stands for has no name in the term.
+        let t = parse("forall (A : U 0) -> A -> (forall (B : U 0) -> B -> U 0)").eval(&d);
+        let e = parse("\\A x A y. A'").check(&t, &cx).unwrap_err();
+        assert_eq!(e, "unknown identifier");
+    }
+
+    #[test]
+    fn primitives_work() {
+        let app = |e_1, e_2| Expr::App(Box::new(e_1), Box::new(e_2));
+
+        // int_show (int_add 2 3) => "5"
+        let e = app(
+            Expr::Prim(Prim::IntShow),
+            app(
+                app(Expr::Prim(Prim::IntAdd), Expr::IntLit(2)),
+                Expr::IntLit(3),
+            ),
+        );
+
+        let cx = Context::new();
+        e.check(&Value::Str, &cx).unwrap();
+        let xs = HashSet::new();
+        assert_eq!(
+            e.eval(&Env::new()).quote(&xs),
+            Expr::StrLit("5".to_string())
+        );
+
+        // int_add n 1 is stuck on the neutral n
+        let e = app(
+            app(Expr::Prim(Prim::IntAdd), Expr::Var("n".to_string())),
+            Expr::IntLit(1),
+        );
+
+        let mut cx = Context::new();
+        cx.insert("n".to_string(), Value::Int);
+        assert!(e.check(&Value::Int, &cx).is_ok());
+        assert_eq!(e.eval(&Env::new()).quote(&xs), e);
+    }
+
+    #[test]
+    fn list_fold_works() {
+        let app = |e_1, e_2| Expr::App(Box::new(e_1), Box::new(e_2));
+        let var = |x: &str| Expr::Var(x.to_string());
+        let lam = |x: &str, e| Expr::Lam(x.to_string(), None, Box::new(e));
+
+        // ListRec (cons 1 (cons 2 nil)) 0 (\h. \t. \r. int_add h r) => 3
+        let e = Expr::ListRec(
+            Box::new(Expr::Cons(
+                Box::new(Expr::IntLit(1)),
+                Box::new(Expr::Cons(Box::new(Expr::IntLit(2)), Box::new(Expr::Nil))),
+            )),
+            Box::new(Expr::IntLit(0)),
+            Box::new(lam(
+                "h",
+                lam(
+                    "t",
+                    lam("r", app(app(Expr::Prim(Prim::IntAdd), var("h")), var("r"))),
+                ),
+            )),
+        );
+
+        let cx = Context::new();
+        assert!(e.check(&Value::Int, &cx).is_ok());
+        let xs = HashSet::new();
+        assert_eq!(e.eval(&Env::new()).quote(&xs), Expr::IntLit(3));
+    }
+
+    #[test]
+    fn code_is_spliced() {
+        let src = "def double : Code -> Code := \\c. code_app (code_app (code_var \"int_add\") c) c
+def four : Int := splice (double (code_int 2))
+def kind : String := code_kind (quote (f x))
+def args : List Code := code_args (quote (\\x. f x))";
+
+        let mut driver = crate::driver::Driver::new();
+        driver.run(src).unwrap();
+
+        let xs = HashSet::new();
+        let show = |x: &str| driver.d[x].quote(&xs).to_string();
+        assert_eq!(show("four"), "4");
+        assert_eq!(show("kind"), "\"app\"");
+        assert_eq!(show("args"), "cons (quote (f x)) nil");
+
+        let fails = |a: &str| crate::driver::Driver::new().run(a).unwrap_err().message;
+        assert_eq!(
+            fails("def a : Int := splice (quote \"a\")"),
+            "type mismatch"
+        );
+        assert_eq!(
+            fails("def f : Code -> Int := \\c. splice c"),
+            "cannot splice an unknown term"
+        );
+    }
+
+    #[test]
+    fn open_terms_declare_their_free_variables() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let e = parse("\\y. int_add x y");
+        let g = Guard::default();
+        let cx = Context::from([("x".to_owned(), Value::Int)]);
+
+        assert!(e.eval_open(&cx, &Env::new(), &g).is_ok());
+        assert!(matches!(
+            parse("f x").eval_open(&cx, &Env::new(), &g),
+            Err(e) if e == "unbound variable"
+        ));
+
+        let d = Env::from([("f".to_owned(), parse("\\x. x").eval(&Env::new()))]);
+        let v = parse("f x").eval_open(&cx, &d, &g).unwrap();
+        assert_eq!(v.quote(&HashSet::from(["x"])), parse("x"));
+    }
+
+    #[test]
+    fn unmentioned_variables_are_strengthened_away() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let e = parse("\\x. int_add x y");
+        assert_eq!(e.strengthen("x").unwrap().to_string(), e.to_string());
+        assert_eq!(e.strengthen("y").unwrap_err(), "variable escapes its scope");
+
+        let var = |x: &str| Value::Neutral(Neutral::Var(x.to_owned()));
+        let d = Env::from([
+            ("x".to_owned(), var("x")),
+            ("y".to_owned(), var("y")),
+            ("n".to_owned(), Value::IntLit(1)),
+        ]);
+        let v = parse("\\z. int_add z (int_add n y)").eval(&d);
+        assert!(v.strengthen(0, &d).is_ok());
+        assert!(v.strengthen(1, &d).is_err());
+        assert!(v.strengthen(2, &d).is_ok());
+        assert!(!parse("\\y. y").eval(&d).occurs("y"));
+    }
+
+    #[test]
+    fn let_bindings_are_shared_unless_by_name() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let e = parse("let x := int_add 1 2 in \\y. int_mul x (int_add x y)");
+        let normalize = |strategy| {
+            let g = Guard::new(CheckOptions {
+                strategy,
+                ..CheckOptions::default()
+            });
+            let v = e.eval_with(&Env::new(), &g).unwrap();
+            (v.quote(&HashSet::new()).to_string(), g.shared())
+        };
+
+        let (by_need, shared) = normalize(NormStrategy::ByNeed);
+        assert_eq!(by_need, "\\y. int_mul 3 (int_add 3 y)");
+        assert_eq!(shared, 1);
+        assert_eq!(normalize(NormStrategy::ByName), (by_need, 0));
+    }
+
+    #[test]
+    fn certified_terms_keep_their_type() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let (cx, d, g) = (Context::new(), Env::new(), Guard::default());
+        let t = parse("Int -> Int").eval(&d);
+
+        let e = parse("\\x. int_add x 1").certify(&t, &cx, &d, &g).unwrap();
+        assert_eq!(*e.term(), parse("\\y. int_add y 1"));
+        assert!(matches!(e.ty(), Value::Fun(_, _)));
+
+        let e = parse("int_show 1").certify_infer(&cx, &d, &g).unwrap();
+        assert!(matches!(e.ty(), Value::Str));
+        assert!(parse("int_show 1").certify(&t, &cx, &d, &g).is_err());
+    }
+
+    #[test]
+    fn mismatches_name_the_kind_of_term() {
+        let fails = |a: &str| crate::driver::Driver::new().run(a).unwrap_err().message;
+        assert_eq!(
+            fails("def A : U 1 := \\x. x"),
+            "lambda checked against a non-function type"
+        );
+        assert_eq!(
+            fails("def f : Int -> Int := \\x. \\y. x"),
+            "lambda checked against a non-function type"
+        );
+        assert_eq!(
+            fails("def n : Int := nil"),
+            "list checked against a non-list type"
+        );
+        assert_eq!(
+            fails("def n : Int := List Int"),
+            "type checked against a non-universe type"
+        );
+        assert_eq!(
+            fails("def n : Int := quote 1"),
+            "quoted term checked against a type other than Code"
+        );
+        assert_eq!(fails("def n : Int := int_show 1"), "type mismatch");
+    }
+
+    #[test]
+    fn environments_share_their_globals() {
+        let mut d = Env::new();
+        d.define("one".to_owned(), Value::IntLit(1));
+
+        let mut d_1 = d.to_owned();
+        d_1.insert("x".to_owned(), Value::IntLit(2));
+        d_1.insert("x".to_owned(), Value::IntLit(3));
+        assert!(Arc::ptr_eq(&d.globals, &d_1.globals));
+        assert_eq!(d_1.level(), 2);
+        assert!(matches!(d_1.at(0), Value::IntLit(2)));
+        assert!(matches!(d_1["x"], Value::IntLit(3)));
+        assert!(matches!(d_1["one"], Value::IntLit(1)));
+
+        d.define("one".to_owned(), Value::IntLit(4));
+        assert!(matches!(d_1["one"], Value::IntLit(1)));
+        assert!(!d.contains_key("x"));
+    }
+
+    #[test]
+    fn observational_equality_computes_on_types() {
+        let src = "def funext : forall (A : U 1) (B : U 1) (f g : A -> B) -> (forall (x : A) -> obs_eq B (f x) (g x)) -> obs_eq (A -> B) f g := \\A B f g h. h
+def one : obs_eq Int 1 1 := obs_refl Int 1
+def lists : obs_eq (List Int) (cons 1 nil) (cons 1 nil) := obs_refl (List Int) (cons 1 nil)
+def absurd : forall (P : U 0) -> obs_eq Int 1 2 -> P := \\P e. e P";
+
+        let mut driver = crate::driver::Driver::new();
+        driver.options.observational = true;
+        driver.run(src).unwrap();
+
+        let e = crate::driver::Driver::new().run(src).unwrap_err();
+        assert_eq!(
+            (e.pos.line, e.message),
+            (1, "observational equality is not enabled".into())
+        );
+    }
+
+    #[test]
+    fn lets_unfold_unless_opaque() {
+        let src = "def f : Int -> Int := let A := Int in \\(y : A). y
+def g : Int := let a := 1; b := 2 in int_add a b";
+
+        let mut driver = crate::driver::Driver::new();
+        driver.run(src).unwrap();
+
+        let mut driver = crate::driver::Driver::new();
+        driver.options.opaque_lets = true;
+        let e = driver.run(src).unwrap_err();
+        assert_eq!((e.pos.line, e.message), (1, "type mismatch".into()));
+        assert!(driver
+            .run("def g : Int := let a := 1; b := 2 in int_add a b")
+            .is_ok());
+    }
+
+    #[test]
+    fn let_blocks_bind_in_parallel() {
+        let src = "def x : Int := 1
+def swap : Int := let x := 2; y := x in int_sub x y
+#print swap";
+
+        let mut driver = crate::driver::Driver::new();
+        driver.run(src).unwrap();
+
+        let xs = HashSet::new();
+        let show = |x: &str| driver.d[x].quote(&xs).to_string();
+        assert_eq!(show("swap"), "1");
+        assert_eq!(
+            driver.out[0].1,
+            "def swap : Int := let x := 2; y := x in int_sub x y"
+        );
+
+        let fails = |a: &str| crate::driver::Driver::new().run(a).unwrap_err().message;
+        assert_eq!(fails("def pair : List Int := let a := 3; b := int_add a 1; c := a in cons b (cons c nil)"), "unknown identifier");
+        assert_eq!(
+            fails("def a : Int := let b := 1; b := 2 in b"),
+            "duplicate definition"
+        );
+    }
+
+    #[test]
+    fn recursive_let_blocks_compute() {
+        let src = "def parity : List Int -> Int :=
+  let rec even : List Int -> Int := \\xs. ListRec xs 1 (\\h t r. odd t);
+          odd : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. even t)
+  in even
+#eval parity (cons 1 (cons 2 (cons 3 nil)))
+#eval parity (cons 1 (cons 2 nil))
+#eval \\(xs : List Int). parity (cons 1 xs)";
+
+        let mut driver = crate::driver::Driver::new();
+        driver.run(src).unwrap();
+
+        let out = driver
+            .out
+            .iter()
+            .map(|(_, a)| a.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(out, ["0", "1", "\\xs. odd xs"]);
+
+        let fails = |a: &str| crate::driver::Driver::new().run(a).unwrap_err().message;
+        assert_eq!(
+            fails("def f : Int := let rec g : List Int -> Int := \\xs. g xs in g nil"),
+            "cannot show that recursion terminates"
+        );
+        assert_eq!(
+            fails("def f : Int := let rec g : Int := 1; g : Int := 2 in g"),
+            "duplicate definition"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    Arrow,
+    Backslash,
+    Colon,
+    ColonEq,
+    Command(String),
+    // -- text, kept for the formatter.
+    Comment(String),
+    Doc(String),
+    Dot,
+    Hole(String),
+    Ident(String),
+    Int(String),
+    LBracket,
+    LParen,
+    Op(String),
+    Pi,
+    RBracket,
+    RParen,
+    Semicolon,
+    Str(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LexError {
+    pub pos: Pos,
+    pub message: &'static str,
+}
+
+// Symbols with a meaning of their own, not available to identifiers or
+// operators.
+const RESERVED: &str = "λΠ∀→";
+
+fn is_op(c: char) -> bool {
+    "+-*/<>=&|^%!?~@$:".contains(c)
+        || !(c.is_ascii() || c.is_alphanumeric() || c.is_whitespace() || RESERVED.contains(c))
+}
+
+pub fn is_ident_start(c: char) -> bool {
+    (c.is_alphabetic() || c == '_') && !RESERVED.contains(c)
+}
+
+pub fn is_ident(c: char) -> bool {
+    (c.is_alphanumeric() || c == '_' || c == '\'') && !RESERVED.contains(c)
+}
+
+pub fn tokenize(src: &str) -> Result<Vec<(Token, Pos)>, LexError> {
+    let mut ts = vec![];
+    let mut cs = src.chars().peekable();
+    let mut pos = Pos { line: 1, column: 1 };
+
+    macro_rules! bump {
+        () => {{
+            let c = cs.next();
+
+            if c == Some('\n') {
+                pos.line += 1;
+                pos.column = 1;
+            } else if c.is_some() {
+                pos.column += 1;
+            }
+
+            c
+        }};
+    }
+
+    while let Some(&c) = cs.peek() {
+        let start = pos;
+
+        let t = match c {
+            _ if c.is_whitespace() => {
+                bump!();
+                continue;
+            }
+            '[' => {
+                bump!();
+                Token::LBracket
+            }
+            ']' => {
+                bump!();
+                Token::RBracket
+            }
+            '(' => {
+                bump!();
+                Token::LParen
+            }
+            ')' => {
+                bump!();
+                Token::RParen
+            }
+            '\\' | 'λ' => {
+                bump!();
+                Token::Backslash
+            }
+            '→' => {
+                bump!();
+                Token::Arrow
+            }
+            'Π' | '∀' => {
+                bump!();
+                Token::Pi
+            }
+            '.' => {
+                bump!();
+                Token::Dot
+            }
+            ';' => {
+                bump!();
+                Token::Semicolon
+            }
+            '#' => {
+                bump!();
+                let mut x = String::new();
+
+                while let Some(&c) = cs.peek().filter(|&&c| is_ident(c)) {
+                    x.push(c);
+                    bump!();
+                }
+
+                Token::Command(x)
+            }
+            '?' if cs.clone().nth(1).is_some_and(is_ident_start) => {
+                bump!();
+                let mut x = String::new();
+
+                while let Some(&c) = cs.peek().filter(|&&c| is_ident(c)) {
+                    x.push(c);
+                    bump!();
+                }
+
+                Token::Hole(x)
+            }
+            '"' => {
+                bump!();
+                let mut a = String::new();
+
+                loop {
+                    match bump!() {
+                        Some('"') => break,
+                        Some('\\') => a.push(match bump!() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(c @ ('"' | '\\')) => c,
+                            _ => {
+                                return Err(LexError {
+                                    pos,
+                                    message: "invalid escape sequence",
+                                })
+                            }
+                        }),
+                        Some(c) => a.push(c),
+                        None => {
+                            return Err(LexError {
+                                pos: start,
+                                message: "unterminated string literal",
+                            })
+                        }
+                    }
+                }
+
+                Token::Str(a)
+            }
+            _ if c.is_ascii_digit() => {
+                let mut n = String::new();
+
+                while let Some(&c) = cs.peek().filter(|c| c.is_ascii_digit()) {
+                    n.push(c);
+                    bump!();
+                }
+
+                Token::Int(n)
+            }
+            // Capitalized segments followed by a dot qualify the name after
+            // them, as in `Data.Nat.add`, so `\x.x` still lexes as a lambda.
+            _ if is_ident_start(c) => {
+                let mut x = String::new();
+                let mut k = 0;
+
+                loop {
+                    while let Some(&c) = cs.peek().filter(|&&c| is_ident(c)) {
+                        x.push(c);
+                        bump!();
+                    }
+
+                    let mut ahead = cs.clone();
+
+                    if !x[k..].starts_with(char::is_uppercase)
+                        || ahead.next() != Some('.')
+                        || !ahead.next().is_some_and(is_ident_start)
+                    {
+                        break;
+                    }
+
+                    bump!();
+                    x.push('.');
+                    k = x.len();
+                }
+
+                Token::Ident(x)
+            }
+            _ if is_op(c) => {
+                let mut a = String::new();
+
+                while let Some(&c) = cs.peek().filter(|&&c| is_op(c)) {
+                    a.push(c);
+                    bump!();
+                }
+
+                if let Some(b) = a.strip_prefix("--") {
+                    let mut b = b.to_owned();
+
+                    while let Some(c) = bump!().filter(|&c| c != '\n') {
+                        b.push(c);
+                    }
+
+                    match b.strip_prefix('|') {
+                        Some(b) => Token::Doc(b.trim().to_owned()),
+                        None => Token::Comment(b.trim_end().to_owned()),
+                    }
+                } else {
+                    match a.as_str() {
+                        "->" => Token::Arrow,
+                        ":" => Token::Colon,
+                        ":=" => Token::ColonEq,
+                        _ => Token::Op(a),
+                    }
+                }
+            }
+            _ => {
+                return Err(LexError {
+                    pos,
+                    message: "unexpected character",
+                })
+            }
+        };
+
+        ts.push((t, start));
+    }
+
+    Ok(ts)
+}
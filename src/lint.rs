@@ -0,0 +1,217 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{decl::Def, diff::children, lexer::Pos, Context, Error, Expr, Identifier};
+
+// A kind of warning a definition may draw.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    // The definition refers to an axiom.
+    Axiom,
+    // A binder has the name of a variable in scope.
+    Shadowing,
+    // A binder is never referred to. Those whose names start with `_` are
+    // meant to be.
+    UnusedBinder,
+}
+
+impl Lint {
+    pub const ALL: [Self; 3] = [Self::Axiom, Self::Shadowing, Self::UnusedBinder];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Axiom => "axiom",
+            Self::Shadowing => "shadowing",
+            Self::UnusedBinder => "unused-binder",
+        }
+    }
+
+    pub fn from_name(a: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|l| l.name() == a)
+    }
+
+    // The error a denied warning becomes.
+    pub fn error(self) -> Error {
+        match self {
+            Self::Axiom => "definition uses an axiom".into(),
+            Self::Shadowing => "binder shadows a variable".into(),
+            Self::UnusedBinder => "unused binder".into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Allow,
+    Warn,
+    Deny,
+}
+
+// The severity of every lint, which is a warning unless configured
+// otherwise.
+#[derive(Clone, Debug, Default)]
+pub struct Lints(HashMap<Lint, Severity>);
+
+impl Lints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, l: Lint) -> Severity {
+        self.0.get(&l).copied().unwrap_or(Severity::Warn)
+    }
+
+    pub fn set(&mut self, l: Lint, s: Severity) {
+        self.0.insert(l, s);
+    }
+
+    // Turns every warning into an error, leaving the allowed lints alone.
+    pub fn deny_warnings(&mut self) {
+        for l in Lint::ALL {
+            if self.get(l) == Severity::Warn {
+                self.set(l, Severity::Deny);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub pos: Pos,
+    pub lint: Lint,
+    pub message: String,
+}
+
+// The lints a definition draws, in order, given the globals in scope and the
+// axioms among them.
+pub fn lint_def(def: &Def, cx: &Context, axioms: &BTreeSet<Identifier>) -> Vec<(Lint, String)> {
+    let mut ws = vec![];
+
+    for e in [&def.ty, &def.body] {
+        lint_binders(e, cx, &mut vec![], &mut ws);
+    }
+
+    let mut xs = (&def.ty.free_vars() | &def.body.free_vars())
+        .into_iter()
+        .filter(|x| axioms.contains(x))
+        .collect::<Vec<_>>();
+
+    xs.sort();
+
+    for x in xs {
+        ws.push((Lint::Axiom, format!("{} uses the axiom {x}", def.name)));
+    }
+
+    ws
+}
+
+fn lint_binders<'a>(
+    e: &'a Expr,
+    cx: &Context,
+    bs: &mut Vec<&'a str>,
+    ws: &mut Vec<(Lint, String)>,
+) {
+    if let Expr::Let(bs_1, e) = e {
+        for (_, e_1) in bs_1 {
+            lint_binders(e_1, cx, bs, ws);
+        }
+
+        for (x, _) in bs_1 {
+            lint_binder(x, e.occurs(x), cx, bs, ws);
+        }
+
+        bs.extend(bs_1.iter().map(|(x, _)| x.as_str()));
+        lint_binders(e, cx, bs, ws);
+        bs.truncate(bs.len() - bs_1.len());
+        return;
+    }
+
+    // A recursive binding used only by itself is never used.
+    if let Expr::LetRec(bs_1, e) = e {
+        for (_, a, _) in bs_1 {
+            lint_binders(a, cx, bs, ws);
+        }
+
+        for (k, (x, _, _)) in bs_1.iter().enumerate() {
+            let used = e.occurs(x)
+                || bs_1
+                    .iter()
+                    .enumerate()
+                    .any(|(j, (_, _, e_1))| j != k && e_1.occurs(x));
+            lint_binder(x, used, cx, bs, ws);
+        }
+
+        bs.extend(bs_1.iter().map(|(x, _, _)| x.as_str()));
+
+        for (_, _, e_1) in bs_1 {
+            lint_binders(e_1, cx, bs, ws);
+        }
+
+        lint_binders(e, cx, bs, ws);
+        bs.truncate(bs.len() - bs_1.len());
+        return;
+    }
+
+    for (e, x) in children(e) {
+        let Some(x) = x else {
+            lint_binders(e, cx, bs, ws);
+            continue;
+        };
+
+        lint_binder(x, e.occurs(x), cx, bs, ws);
+        bs.push(x);
+        lint_binders(e, cx, bs, ws);
+        bs.pop();
+    }
+}
+
+// The lints a binder of `x` draws, given whether what it scopes over uses it.
+fn lint_binder(x: &str, used: bool, cx: &Context, bs: &[&str], ws: &mut Vec<(Lint, String)>) {
+    if bs.contains(&x) || cx.contains_key(x) {
+        ws.push((Lint::Shadowing, format!("{x} shadows a variable in scope")));
+    }
+
+    if !x.starts_with('_') && !used {
+        ws.push((Lint::UnusedBinder, format!("{x} is never used")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::Driver;
+
+    #[test]
+    fn lints_follow_their_severity() {
+        let src = "axiom lem : forall (P : U 0) -> P
+def one : Int := 1
+def k : Int -> Int -> Int := \\x one. x
+def f : Int -> Int := \\_x. lem Int";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+        assert_eq!(
+            driver
+                .warnings
+                .iter()
+                .map(|w| (w.pos.line, w.lint.name(), w.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (3, "shadowing", "one shadows a variable in scope"),
+                (3, "unused-binder", "one is never used"),
+                (4, "axiom", "f uses the axiom lem"),
+            ]
+        );
+
+        let mut driver = Driver::new();
+        driver
+            .options
+            .lints
+            .set(Lint::UnusedBinder, Severity::Allow);
+        driver.options.lints.deny_warnings();
+        let e = driver.run(src).unwrap_err();
+        assert_eq!(
+            (e.pos.line, e.message),
+            (3, "binder shadows a variable".into())
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{freshen, Error, Expr, Identifier};
+
+// macro twice f x := f (f x)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Macro {
+    pub name: Identifier,
+    pub params: Vec<Identifier>,
+    pub body: Expr,
+}
+
+pub type Macros = HashMap<Identifier, Macro>;
+
+impl Expr {
+    // Replaces every application of a macro to as many arguments as it has
+    // parameters by its body, with the parameters standing for the
+    // arguments. Expansion is hygienic: the binders of a body are renamed
+    // away from the variables of the arguments, and a body cannot refer to
+    // a name bound where the macro is used.
+    pub fn expand(&self, ms: &Macros) -> Result<Expr, Error> {
+        self.expand_in(ms, &mut vec![])
+    }
+
+    fn expand_in<'a>(&'a self, ms: &Macros, bs: &mut Vec<&'a str>) -> Result<Expr, Error> {
+        let mut head = self;
+        let mut args = vec![];
+
+        while let Self::App(e_1, e_2) = head {
+            args.push(&**e_2);
+            head = e_1;
+        }
+
+        args.reverse();
+
+        if let Self::Var(x) = head {
+            if let Some(m) = ms.get(x).filter(|_| !bs.contains(&x.as_str())) {
+                if args.len() < m.params.len() {
+                    return Err("macro applied to too few arguments".into());
+                }
+
+                if m.body
+                    .free_vars()
+                    .iter()
+                    .any(|y| !m.params.contains(y) && bs.contains(&y.as_str()))
+                {
+                    return Err("macro refers to a name bound where it is used".into());
+                }
+
+                let (args_1, args_2) = args.split_at(m.params.len());
+                let mut s = HashMap::new();
+
+                for (y, e) in m.params.iter().zip(args_1) {
+                    s.insert(y.as_str(), e.expand_in(ms, bs)?);
+                }
+
+                return args_2.iter().try_fold(m.body.substitute(&s), |e_1, e_2| {
+                    Ok(Self::App(Box::new(e_1), Box::new(e_2.expand_in(ms, bs)?)))
+                });
+            }
+        }
+
+        let go = |e: &'a Expr, bs: &mut Vec<&'a str>| e.expand_in(ms, bs).map(Box::new);
+
+        Ok(match self {
+            Self::App(e_1, e_2) => Self::App(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Cons(e_1, e_2) => Self::Cons(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Fun(e_1, e_2) => Self::Fun(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Inst(e_1, e_2) => Self::Inst(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Lam(x, a, e) => {
+                let a_ = a.as_deref().map(|a| go(a, bs)).transpose()?;
+                bs.push(x);
+                let e_ = go(e, bs)?;
+                bs.pop();
+                Self::Lam(x.to_owned(), a_, e_)
+            }
+            Self::Let(bs_, e) => {
+                let bs_1 = bs_
+                    .iter()
+                    .map(|(x, e)| Ok((x.to_owned(), *go(e, bs)?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                bs.extend(bs_.iter().map(|(x, _)| x.as_str()));
+                let e_ = go(e, bs)?;
+                bs.truncate(bs.len() - bs_.len());
+                Self::Let(bs_1, e_)
+            }
+            Self::LetRec(bs_, e) => {
+                let ts = bs_
+                    .iter()
+                    .map(|(_, a, _)| go(a, bs))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                bs.extend(bs_.iter().map(|(x, _, _)| x.as_str()));
+                let bs_1 = bs_
+                    .iter()
+                    .zip(ts)
+                    .map(|((x, _, e), a)| Ok((x.to_owned(), *a, *go(e, bs)?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let e_ = go(e, bs)?;
+                bs.truncate(bs.len() - bs_.len());
+                Self::LetRec(bs_1, e_)
+            }
+            Self::List(e) => Self::List(go(e, bs)?),
+            Self::ListRec(e_1, e_2, e_3) => Self::ListRec(go(e_1, bs)?, go(e_2, bs)?, go(e_3, bs)?),
+            Self::Pi(x, e_1, e_2) => {
+                let e_1_ = go(e_1, bs)?;
+                bs.push(x);
+                let e_2_ = go(e_2, bs)?;
+                bs.pop();
+                Self::Pi(x.to_owned(), e_1_, e_2_)
+            }
+            Self::Quote(e) => Self::Quote(go(e, bs)?),
+            Self::Splice(e) => Self::Splice(go(e, bs)?),
+            Self::Sub(x, e_1, e_2) => {
+                let e_1_ = go(e_1, bs)?;
+                bs.push(x);
+                let e_2_ = go(e_2, bs)?;
+                bs.pop();
+                Self::Sub(x.to_owned(), e_1_, e_2_)
+            }
+            e => e.to_owned(),
+        })
+    }
+
+    // Replaces several free variables by terms at once, renaming the
+    // binders that would capture a variable of one of them.
+    pub fn instantiate(&self, s: &HashMap<Identifier, Expr>) -> Expr {
+        let s = s.iter().map(|(x, e)| (x.as_str(), e.to_owned())).collect();
+        self.substitute(&s)
+    }
+
+    fn substitute(&self, s: &HashMap<&str, Expr>) -> Expr {
+        let b = Box::new;
+        let go = |e: &Expr| b(e.substitute(s));
+
+        // A binder and the substitution for the term under it.
+        let under = |x: &str, e: &Expr| {
+            let mut s_ = s.to_owned();
+            s_.remove(x);
+            let ys = s_
+                .values()
+                .flat_map(Expr::free_vars)
+                .collect::<HashSet<_>>();
+
+            if !ys.contains(x) {
+                return (x.to_owned(), e.substitute(&s_));
+            }
+
+            let mut xs = ys.iter().map(String::as_str).collect::<HashSet<_>>();
+            let zs = e.free_vars();
+            xs.extend(zs.iter().map(String::as_str));
+            let x_ = freshen(x.to_owned(), &xs);
+            let e = e.substitute(&HashMap::from([(x, Self::Var(x_.to_owned()))]));
+            (x_, e.substitute(&s_))
+        };
+
+        match self {
+            Self::App(e_1, e_2) => Self::App(go(e_1), go(e_2)),
+            Self::Cons(e_1, e_2) => Self::Cons(go(e_1), go(e_2)),
+            Self::Fun(e_1, e_2) => Self::Fun(go(e_1), go(e_2)),
+            Self::Inst(e_1, e_2) => Self::Inst(go(e_1), go(e_2)),
+            Self::Lam(x, a, e) => {
+                let (x_, e_) = under(x, e);
+                Self::Lam(x_, a.as_deref().map(go), b(e_))
+            }
+            Self::Let(bs, e) => {
+                let mut s_ = s.to_owned();
+
+                for (x, _) in bs {
+                    s_.remove(x.as_str());
+                }
+
+                let ys = s_
+                    .values()
+                    .flat_map(Expr::free_vars)
+                    .collect::<HashSet<_>>();
+                let mut taken = &ys | &e.free_vars();
+                taken.extend(bs.iter().map(|(x, _)| x.to_owned()));
+                let mut r = HashMap::new();
+                let mut bs_ = vec![];
+
+                for (x, e_1) in bs {
+                    let mut x_ = x.to_owned();
+
+                    if ys.contains(x) {
+                        x_ = freshen(x_, &taken.iter().map(String::as_str).collect());
+                        taken.insert(x_.to_owned());
+                        r.insert(x.as_str(), Self::Var(x_.to_owned()));
+                    }
+
+                    bs_.push((x_, e_1.substitute(s)));
+                }
+
+                Self::Let(bs_, b(e.substitute(&r).substitute(&s_)))
+            }
+            // The names of the block are renamed in its bindings too.
+            Self::LetRec(bs, e) => {
+                let mut s_ = s.to_owned();
+
+                for (x, _, _) in bs {
+                    s_.remove(x.as_str());
+                }
+
+                let ys = s_
+                    .values()
+                    .flat_map(Expr::free_vars)
+                    .collect::<HashSet<_>>();
+                let mut taken = &ys | &e.free_vars();
+                taken.extend(bs.iter().flat_map(|(_, _, e)| e.free_vars()));
+                taken.extend(bs.iter().map(|(x, _, _)| x.to_owned()));
+                let mut r = HashMap::new();
+
+                for (x, _, _) in bs {
+                    if ys.contains(x) {
+                        let x_ = freshen(x.to_owned(), &taken.iter().map(String::as_str).collect());
+                        taken.insert(x_.to_owned());
+                        r.insert(x.as_str(), Self::Var(x_));
+                    }
+                }
+
+                let rename = |x: &Identifier| match r.get(x.as_str()) {
+                    Some(Self::Var(x_)) => x_.to_owned(),
+                    _ => x.to_owned(),
+                };
+
+                let bs_ = bs
+                    .iter()
+                    .map(|(x, a, e_1)| {
+                        let e_1 = e_1.substitute(&r).substitute(&s_);
+                        (rename(x), a.substitute(s), e_1)
+                    })
+                    .collect();
+
+                Self::LetRec(bs_, b(e.substitute(&r).substitute(&s_)))
+            }
+            Self::List(e) => Self::List(go(e)),
+            Self::ListRec(e_1, e_2, e_3) => Self::ListRec(go(e_1), go(e_2), go(e_3)),
+            Self::Pi(x, e_1, e_2) => {
+                let (x_, e_2_) = under(x, e_2);
+                Self::Pi(x_, go(e_1), b(e_2_))
+            }
+            Self::Quote(e) => Self::Quote(go(e)),
+            Self::Splice(e) => Self::Splice(go(e)),
+            Self::Sub(x, e_1, e_2) => {
+                let (x_, e_2_) = under(x, e_2);
+                Self::Sub(x_, go(e_1), b(e_2_))
+            }
+            Self::Var(x) => s
+                .get(x.as_str())
+                .cloned()
+                .unwrap_or_else(|| self.to_owned()),
+            e => e.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{driver::Driver, notation::Notations, parser::parse_expr};
+
+    #[test]
+    fn instantiation_avoids_capture() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let instantiate = |a: &str, s: &[(&str, &str)]| {
+            let s = s.iter().map(|(x, b)| (x.to_string(), parse(b))).collect();
+            parse(a).instantiate(&s)
+        };
+
+        // Simultaneously, so a swap does not substitute twice.
+        let e = instantiate("f x y", &[("x", "y"), ("y", "x")]);
+        assert_eq!(e.to_string(), "f y x");
+
+        let e = instantiate("\\y. x y", &[("x", "y")]);
+        assert_eq!(e, parse("\\z. y z"));
+        assert_eq!(e.to_string(), "\\y'. y y'");
+
+        let e = instantiate("\\x. x", &[("x", "1")]);
+        assert_eq!(e.to_string(), "\\x. x");
+
+        let e = instantiate(
+            "forall (A : U 0) -> B -> A",
+            &[("B", "List A"), ("A", "Int")],
+        );
+        assert_eq!(e, parse("forall (C : U 0) -> List A -> C"));
+
+        // The bindings of a block see the outer variables, its body the
+        // bound ones.
+        let e = instantiate("let y := x; z := y in f y z", &[("x", "y"), ("y", "2")]);
+        assert_eq!(e, parse("let a := y; z := 2 in f a z"));
+    }
+
+    #[test]
+    fn macros_are_expanded_hygienically() {
+        let src = "macro twice f x := f (f x)
+macro const x := \\y. x
+def succ : Int -> Int := \\n. int_add n 1
+def four : Int := twice succ 2
+def k : Int -> Int -> Int := \\y. const y
+#print k";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+
+        let xs = std::collections::HashSet::new();
+        assert_eq!(driver.d["four"].quote(&xs).to_string(), "4");
+        assert_eq!(
+            driver.out[0].1,
+            "def k : Int -> Int -> Int := \\(y : Int) (y' : Int). y"
+        );
+
+        let fails = |a: &str| Driver::new().run(a).unwrap_err().message;
+        assert_eq!(
+            fails("macro m x := f x\ndef g : Int -> Int := \\f. m 1"),
+            "macro refers to a name bound where it is used"
+        );
+        assert_eq!(
+            fails("macro m x y := x\ndef g : Int := m 1"),
+            "macro applied to too few arguments"
+        );
+    }
+}
@@ -1,23 +1,422 @@
-use std::collections::{HashMap, HashSet};
-
-use saida::Expr;
-
-fn main() {
-    // (\x. \y. x)(y) => \y'. y
-    let e = Expr::App(
-        Box::new(Expr::Lam(
-            "x".to_string(),
-            Box::new(Expr::Lam(
-                "y".to_string(),
-                Box::new(Expr::Var("y".to_string())),
-            )),
-        )),
-        Box::new(Expr::Var("y".to_string())),
-    );
-
-    let d = HashMap::new();
-    let v = e.eval(&d);
-    let mut xs = HashSet::new();
-    xs.insert("y");
-    println!("{:?}", v.quote(&xs));
+use std::{
+    cmp::Reverse,
+    env, fs,
+    io::{self, IsTerminal},
+    mem,
+    path::Path,
+    process::ExitCode,
+    sync::Arc,
+};
+
+use saida::{
+    doc::render_html,
+    driver::{Diagnostic, Driver, Loader, Profile},
+    explain,
+    format::{format_source, WIDTH},
+    golden::{check_determinism, run_dir, Outcome},
+    lint::{Lint, Lints, Severity},
+    parser::{parse_header, Header},
+    repl::{History, Repl},
+    serve::Server,
+};
+
+const USAGE: &str =
+    "usage: saida check [--profile] [--prelude <file>] [--allow|--warn|--deny <lint>]... <file>...
+       saida build [--profile] [--allow|--warn|--deny <lint>]... <file>...
+       saida doc <file>...
+       saida explain <code>
+       saida fmt <file>...
+       saida test [--bless|--determinism] <dir>
+       saida repl
+       saida serve";
+
+// A driver resolving imports to the `.sdi` files in the directory of a
+// source file.
+fn driver(path: &str, src: &str) -> Driver {
+    let mut driver = Driver::new();
+    driver.loader = Some(loader(path, src));
+    driver
+}
+
+fn loader(path: &str, src: &str) -> Loader {
+    // `Data.Nat` lives in `Data/Nat.sd`, so imports resolve against the
+    // directory as many levels up as the module name has segments.
+    let mut root = Path::new(path).parent().unwrap_or(Path::new("")).to_owned();
+
+    if let Ok(Header {
+        module: Some(m), ..
+    }) = parse_header(src)
+    {
+        for _ in 1..m.split('.').count() {
+            root.pop();
+        }
+    }
+
+    Arc::new(move |x| fs::read_to_string(root.join(x.replace('.', "/")).with_extension("sdi")).ok())
+}
+
+#[derive(Default)]
+struct Flags {
+    lints: Lints,
+    profile: bool,
+    prelude: Option<String>,
+}
+
+// Severities given as `--deny shadowing`, in order, `warnings` standing for
+// every lint that would warn, whether to profile and the file checked
+// before the others, their definitions in scope in each. The files given
+// are what remains.
+fn flags(args: &[String]) -> Option<(Flags, &[String])> {
+    let mut flags = Flags::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.as_slice().first().filter(|a| a.starts_with("--")) {
+        args.next();
+
+        let s = match arg.as_str() {
+            "--profile" => {
+                flags.profile = true;
+                continue;
+            }
+            "--prelude" => {
+                flags.prelude = Some(args.next()?.to_owned());
+                continue;
+            }
+            "--allow" => Severity::Allow,
+            "--warn" => Severity::Warn,
+            "--deny" => Severity::Deny,
+            _ => return None,
+        };
+
+        match (args.next()?.as_str(), s) {
+            ("warnings", Severity::Deny) => flags.lints.deny_warnings(),
+            (a, s) => flags.lints.set(Lint::from_name(a)?, s),
+        }
+    }
+
+    Some((flags, args.as_slice())).filter(|(_, paths)| !paths.is_empty())
+}
+
+// The declarations that took longest to check first.
+fn print_profile(path: &str, mut ps: Vec<Profile>) {
+    ps.sort_by_key(|p| Reverse(p.time));
+    eprintln!("      time  conversions  hits  misses  unfoldings  shared  peak size  declaration");
+
+    for p in ps {
+        eprintln!(
+            "{:>8.3}ms  {:>11}  {:>4}  {:>6}  {:>10}  {:>6}  {:>9}  {path}:{}:{}: {}",
+            p.time.as_secs_f64() * 1000.0,
+            p.conversions,
+            p.cache_hits,
+            p.cache_misses,
+            p.unfoldings,
+            p.shared,
+            p.peak_size,
+            p.pos.line,
+            p.pos.column,
+            p.names.join(", ")
+        );
+    }
+}
+
+// Prints what checking a file gave, returning whether it checked.
+fn report(path: &str, driver: &mut Driver, es: &[Diagnostic], profile: bool) -> bool {
+    if profile {
+        print_profile(path, mem::take(&mut driver.profile));
+    }
+
+    for (pos, text) in &driver.out {
+        println!("{path}:{}:{}: {text}", pos.line, pos.column);
+    }
+
+    let color = env::var_os("NO_COLOR").is_none();
+    let (error, warning) = if color && io::stderr().is_terminal() {
+        ("\x1b[1;31merror\x1b[0m", "\x1b[1;33mwarning\x1b[0m")
+    } else {
+        ("error", "warning")
+    };
+
+    for w in &driver.warnings {
+        eprintln!(
+            "{path}:{}:{}: {warning}: {} [{}]",
+            w.pos.line,
+            w.pos.column,
+            w.message,
+            w.lint.name()
+        );
+    }
+
+    for e in es {
+        let code = e.message.code().map_or(String::new(), |c| format!("[{c}]"));
+        eprintln!(
+            "{path}:{}:{}: {error}{code}: {}",
+            e.pos.line, e.pos.column, e.message
+        );
+    }
+
+    es.is_empty()
+}
+
+fn read(path: &str) -> Option<String> {
+    fs::read_to_string(path)
+        .map_err(|e| eprintln!("{path}: {e}"))
+        .ok()
+}
+
+// Checks files, and when building writes the interface of each next to it.
+// The prelude is checked first, once, and the files are then checked at the
+// same time, each with its definitions in scope.
+fn check(paths: &[String], flags: Flags, build: bool) -> ExitCode {
+    let Some(srcs) = paths
+        .iter()
+        .map(|path| read(path))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return ExitCode::FAILURE;
+    };
+
+    let color = env::var_os("NO_COLOR").is_none();
+    let mut prelude = Driver::new();
+    prelude.ansi = color && io::stdout().is_terminal();
+    prelude.options.lints = flags.lints;
+
+    if let Some(path) = &flags.prelude {
+        let Some(src) = read(path) else {
+            return ExitCode::FAILURE;
+        };
+
+        prelude.loader = Some(loader(path, &src));
+        let es = prelude.check(&src);
+
+        if !report(path, &mut prelude, &es, flags.profile) {
+            return ExitCode::FAILURE;
+        }
+
+        prelude.out.clear();
+        prelude.warnings.clear();
+        prelude.profile.clear();
+    }
+
+    let mut jobs = paths
+        .iter()
+        .zip(&srcs)
+        .map(|(path, src)| {
+            let mut driver = prelude.to_owned();
+            driver.loader = Some(loader(path, src));
+            (driver, src.as_str())
+        })
+        .collect::<Vec<_>>();
+
+    let es = Driver::check_all(&mut jobs);
+    let mut code = ExitCode::SUCCESS;
+
+    for ((path, (driver, _)), es) in paths.iter().zip(&mut jobs).zip(es) {
+        if !report(path, driver, &es, flags.profile) {
+            code = ExitCode::FAILURE;
+            continue;
+        }
+
+        if !build {
+            continue;
+        }
+
+        let sdi = Path::new(path).with_extension("sdi");
+
+        if let Err(e) = fs::write(&sdi, driver.interface.to_string()) {
+            eprintln!("{}: {e}", sdi.display());
+            code = ExitCode::FAILURE;
+        }
+    }
+
+    code
+}
+
+// Formats files in place, with the notations of the modules they import.
+fn fmt(paths: &[String]) -> ExitCode {
+    let mut code = ExitCode::SUCCESS;
+
+    for path in paths {
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                code = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        let mut driver = driver(path, &src);
+
+        let result = driver
+            .load_imports(&src)
+            .and_then(|()| Ok(format_source(&src, &mut driver.ns, WIDTH)?));
+
+        match result {
+            Ok(a) if a == src => {}
+            Ok(a) => {
+                if let Err(e) = fs::write(path, a) {
+                    eprintln!("{path}: {e}");
+                    code = ExitCode::FAILURE;
+                }
+            }
+            Err(e) => {
+                eprintln!(
+                    "{path}:{}:{}: error: {}",
+                    e.pos.line, e.pos.column, e.message
+                );
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+
+    code
+}
+
+fn doc(paths: &[String]) -> ExitCode {
+    let mut sources = vec![];
+
+    for path in paths {
+        match fs::read_to_string(path) {
+            Ok(src) => {
+                let name = Path::new(path).file_stem().unwrap_or_default();
+                sources.push((name.to_string_lossy().into_owned(), src));
+            }
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    match render_html(&sources) {
+        Ok(html) => {
+            print!("{html}");
+            ExitCode::SUCCESS
+        }
+        Err((module, e)) => {
+            eprintln!(
+                "{module}:{}:{}: error: {}",
+                e.pos.line, e.pos.column, e.message
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn test(dir: &str, flag: Option<&str>) -> ExitCode {
+    let outcomes = match flag {
+        Some("--determinism") => check_determinism(Path::new(dir)),
+        _ => run_dir(Path::new(dir), flag == Some("--bless")),
+    };
+
+    let outcomes = match outcomes {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            eprintln!("{dir}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut failed = 0;
+
+    for (path, outcome) in &outcomes {
+        let path = path.display();
+
+        match outcome {
+            Outcome::Pass => println!("PASS {path}"),
+            Outcome::Blessed => println!("BLESS {path}"),
+            Outcome::Missing => {
+                failed += 1;
+                println!("MISSING {path} (no .expected file)");
+            }
+            Outcome::Fail(diff) => {
+                failed += 1;
+                println!("FAIL {path}\n{diff}");
+            }
+            Outcome::Unstable(diff) => {
+                failed += 1;
+                println!("UNSTABLE {path}\n{diff}");
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed", outcomes.len() - failed);
+
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn explain(code: &str) -> ExitCode {
+    match explain::explain(code) {
+        Some(e) => {
+            print!("{e}");
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("{code}: no such error code");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// The history is kept in `~/.saida_history` across sessions.
+fn repl() -> ExitCode {
+    let history = match env::var_os("HOME") {
+        Some(home) => History::open(Path::new(&home).join(".saida_history")),
+        None => History::default(),
+    };
+
+    match Repl::new(history).run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("repl: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Answers JSON-RPC requests on standard input, one per line.
+fn serve() -> ExitCode {
+    match Server::new().run(io::stdin().lock(), io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("serve: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    match args.as_slice() {
+        [c, args @ ..] if c == "check" || c == "build" => match flags(args) {
+            // A prelude would end up in the interfaces built.
+            Some((flags, _)) if c == "build" && flags.prelude.is_some() => {
+                eprintln!("{USAGE}");
+                ExitCode::FAILURE
+            }
+            Some((flags, paths)) => check(paths, flags, c == "build"),
+            None => {
+                eprintln!("{USAGE}");
+                ExitCode::FAILURE
+            }
+        },
+        [c, paths @ ..] if c == "doc" && !paths.is_empty() => doc(paths),
+        [c, code] if c == "explain" => explain(code),
+        [c, paths @ ..] if c == "fmt" && !paths.is_empty() => fmt(paths),
+        [c, dir] if c == "test" => test(dir, None),
+        [c, flag, dir] if c == "test" && ["--bless", "--determinism"].contains(&flag.as_str()) => {
+            test(dir, Some(flag))
+        }
+        [c] if c == "repl" => repl(),
+        [c] if c == "serve" => serve(),
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
 }
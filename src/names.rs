@@ -0,0 +1,188 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{freshen, Expr, Identifier};
+
+// Chooses the name of a bound variable when quoting, given the name it had
+// and the names it must not capture.
+pub trait NameSupply {
+    fn fresh(&self, x: &str, xs: &HashSet<&str>) -> Identifier;
+}
+
+// Keeps source names, priming them where they clash.
+pub struct Primes;
+
+impl NameSupply for Primes {
+    fn fresh(&self, x: &str, xs: &HashSet<&str>) -> Identifier {
+        freshen(x.to_owned(), xs)
+    }
+}
+
+// Keeps source names, numbering them `x₁`, `x₂`, … where they clash.
+pub struct Subscripts;
+
+impl NameSupply for Subscripts {
+    fn fresh(&self, x: &str, xs: &HashSet<&str>) -> Identifier {
+        if !xs.contains(x) {
+            return x.to_owned();
+        }
+
+        (1..)
+            .map(|k: usize| {
+                let mut y = x.to_owned();
+
+                for c in k.to_string().chars() {
+                    y.extend(char::from_u32('₀' as u32 + c.to_digit(10).unwrap()));
+                }
+
+                y
+            })
+            .find(|y| !xs.contains(y.as_str()))
+            .unwrap()
+    }
+}
+
+// Names every variable after the number of names in scope where it is
+// bound, that is its de Bruijn level, ignoring source names.
+pub struct Levels;
+
+impl NameSupply for Levels {
+    fn fresh(&self, _: &str, xs: &HashSet<&str>) -> Identifier {
+        freshen(format!("_{}", xs.len()), xs)
+    }
+}
+
+impl Expr {
+    // Renames every bound variable to the name `ns` chooses for it, away
+    // from the free variables and the binders around it. With `Levels`,
+    // alpha-equivalent terms become the same, which is what serializing,
+    // diffing or hashing them wants.
+    pub fn rename_binders(&self, ns: &dyn NameSupply) -> Expr {
+        let fvs = self.free_vars();
+        let xs = fvs.iter().map(String::as_str).collect();
+        self.rename_in(&xs, &HashMap::new(), ns)
+    }
+
+    fn rename_in(&self, xs: &HashSet<&str>, r: &HashMap<&str, &str>, ns: &dyn NameSupply) -> Expr {
+        let b = Box::new;
+        let go = |e: &Expr| b(e.rename_in(xs, r, ns));
+
+        // A binder of `x` over `e`, with its new name.
+        let under = |x: &str, e: &Expr| {
+            let y = ns.fresh(x, xs);
+            let mut xs = xs.to_owned();
+            xs.insert(&y);
+            let mut r = r.to_owned();
+            r.insert(x, &y);
+            let e = e.rename_in(&xs, &r, ns);
+            (y, b(e))
+        };
+
+        match self {
+            Self::App(e_1, e_2) => Self::App(go(e_1), go(e_2)),
+            Self::Cons(e_1, e_2) => Self::Cons(go(e_1), go(e_2)),
+            Self::Fun(e_1, e_2) => Self::Fun(go(e_1), go(e_2)),
+            Self::Inst(e_1, e_2) => Self::Inst(go(e_1), go(e_2)),
+            Self::Lam(x, a, e) => {
+                let (y, e) = under(x, e);
+                Self::Lam(y, a.as_deref().map(go), e)
+            }
+            Self::Let(bs, e) => {
+                let mut ys = vec![];
+
+                for (x, _) in bs {
+                    let mut xs = xs.to_owned();
+                    xs.extend(ys.iter().map(String::as_str));
+                    ys.push(ns.fresh(x, &xs));
+                }
+
+                let mut xs = xs.to_owned();
+                let mut r = r.to_owned();
+
+                for ((x, _), y) in bs.iter().zip(&ys) {
+                    xs.insert(y);
+                    r.insert(x, y);
+                }
+
+                let e = e.rename_in(&xs, &r, ns);
+                let bs = bs.iter().zip(&ys).map(|((_, e), y)| (y.to_owned(), *go(e)));
+                Self::Let(bs.collect(), b(e))
+            }
+            Self::LetRec(bs, e) => {
+                let mut ys = vec![];
+
+                for (x, _, _) in bs {
+                    let mut xs = xs.to_owned();
+                    xs.extend(ys.iter().map(String::as_str));
+                    ys.push(ns.fresh(x, &xs));
+                }
+
+                let mut xs_ = xs.to_owned();
+                let mut r_ = r.to_owned();
+
+                for ((x, _, _), y) in bs.iter().zip(&ys) {
+                    xs_.insert(y);
+                    r_.insert(x, y);
+                }
+
+                let e = e.rename_in(&xs_, &r_, ns);
+                let bs = bs
+                    .iter()
+                    .zip(&ys)
+                    .map(|((_, a, e), y)| (y.to_owned(), *go(a), e.rename_in(&xs_, &r_, ns)));
+                Self::LetRec(bs.collect(), b(e))
+            }
+            Self::List(e) => Self::List(go(e)),
+            Self::ListRec(e_1, e_2, e_3) => Self::ListRec(go(e_1), go(e_2), go(e_3)),
+            Self::Pi(x, e_1, e_2) => {
+                let (y, e_2) = under(x, e_2);
+                Self::Pi(y, go(e_1), e_2)
+            }
+            Self::Quote(e) => Self::Quote(go(e)),
+            Self::Splice(e) => Self::Splice(go(e)),
+            Self::Sub(x, e_1, e_2) => {
+                let (y, e_2) = under(x, e_2);
+                Self::Sub(y, go(e_1), e_2)
+            }
+            Self::Var(x) => Self::Var(r.get(x.as_str()).map_or(x.as_str(), |y| y).to_owned()),
+            e => e.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::Guard, Expr};
+
+    #[test]
+    fn names_are_supplied() {
+        let lam = |x: &str, e: Expr| Expr::Lam(x.to_string(), None, Box::new(e));
+        let var = |x: &str| Box::new(Expr::Var(x.to_string()));
+        let e = lam("x", lam("x", Expr::App(var("x"), var("y"))));
+        let v = e.eval(&Default::default());
+        let xs = HashSet::from(["y"]);
+        let g = Guard::default();
+
+        let quote = |ns: &dyn NameSupply| v.quote_named(&xs, ns, &g).unwrap().to_string();
+        assert_eq!(quote(&Primes), "\\x x'. x' y");
+        assert_eq!(quote(&Subscripts), "\\x x₁. x₁ y");
+        assert_eq!(quote(&Levels), "\\_1 _2. _2 y");
+    }
+
+    #[test]
+    fn binders_are_renamed_canonically() {
+        let parse =
+            |a: &str| crate::parser::parse_expr(a, &mut crate::notation::Notations::new()).unwrap();
+        let e_1 = parse("\\x. let a := x; b := y in forall (z : U 0) -> a b z");
+        let e_2 = parse("\\w. let c := w; d := y in forall (a : U 0) -> c d a");
+
+        let rename = |e: &Expr, ns: &dyn NameSupply| e.rename_binders(ns).to_string();
+        assert_ne!(e_1.to_string(), e_2.to_string());
+        assert_eq!(rename(&e_1, &Levels), rename(&e_2, &Levels));
+        assert_eq!(
+            rename(&e_1, &Levels),
+            "\\_1. let _2 := _1; _3 := y in forall (_4 : U 0) -> _2 _3 _4"
+        );
+        assert_eq!(rename(&parse("\\x x. x y"), &Primes), "\\x x'. x' y");
+    }
+}
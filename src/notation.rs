@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use crate::{Identifier, Level};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fixity {
+    Infix,
+    Infixl,
+    Infixr,
+}
+
+impl Fixity {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Self::Infix => "infix",
+            Self::Infixl => "infixl",
+            Self::Infixr => "infixr",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notation {
+    pub fixity: Fixity,
+    pub precedence: u8,
+    pub symbol: String,
+    pub name: Identifier,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Notations {
+    by_symbol: HashMap<String, Notation>,
+    by_name: HashMap<Identifier, String>,
+    // The universe `U` and `Type` stand for without a level.
+    universe: Level,
+}
+
+impl Notations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, n: Notation) {
+        self.by_name.insert(n.name.to_owned(), n.symbol.to_owned());
+        self.by_symbol.insert(n.symbol.to_owned(), n);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Notation> {
+        self.by_symbol.get(symbol)
+    }
+
+    pub fn for_name(&self, name: &str) -> Option<&Notation> {
+        self.by_name.get(name).and_then(|a| self.by_symbol.get(a))
+    }
+
+    pub fn universe(&self) -> Level {
+        self.universe
+    }
+
+    pub fn set_universe(&mut self, i: Level) {
+        self.universe = i;
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{lint::Lints, store::Hash, Error, Expr};
+
+// Shared with whoever may abort a check from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// How the binding of a let is evaluated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormStrategy {
+    // Once, with its value shared by every use of the variable.
+    #[default]
+    ByNeed,
+    // Again at every use, as if it were substituted for the variable.
+    ByName,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CheckOptions {
+    pub deadline: Option<Instant>,
+    pub cancel: Option<CancelToken>,
+    // How deeply evaluation may nest.
+    pub max_depth: Option<usize>,
+    // How many nodes a normal form may have.
+    pub max_size: Option<usize>,
+    // How many closures may be applied. Definitions are evaluated ahead of
+    // time, so this is what unfolding one amounts to.
+    pub max_unfoldings: Option<usize>,
+    // How severe each kind of warning is.
+    pub lints: Lints,
+    // Whether the variables a let binds stay opaque where it is checked
+    // against a type, rather than unfolding to their values (zeta).
+    pub opaque_lets: bool,
+    // Experimental: whether `obs_eq` and `obs_refl` are available, an
+    // equality computing on the structure of types, under which function
+    // extensionality holds by definition.
+    pub observational: bool,
+    pub strategy: NormStrategy,
+}
+
+type Compared = (Expr, Expr, bool);
+
+// Polled at every step of evaluation, quotation and checking. The clock is
+// only read every few hundred steps.
+#[derive(Debug, Default)]
+pub struct Guard {
+    options: CheckOptions,
+    steps: Cell<u32>,
+    depth: Cell<usize>,
+    unfoldings: Cell<usize>,
+    conversions: Cell<usize>,
+    // Types found different with their globals folded, by the hashes of
+    // both forms, with the forms and whether unfolding made them equal. It
+    // is kept for a declaration.
+    compared: RefCell<HashMap<(Hash, Hash), Compared>>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+    shared: Cell<usize>,
+    peak_size: Cell<usize>,
+}
+
+const POLL_INTERVAL: u32 = 256;
+
+impl Guard {
+    pub fn new(options: CheckOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    pub fn tick(&self) -> Result<(), Error> {
+        let k = self.steps.get().wrapping_add(1);
+        self.steps.set(k);
+
+        if !k.is_multiple_of(POLL_INTERVAL) {
+            return Ok(());
+        }
+
+        if self
+            .options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+        {
+            return Err(Error::Cancelled);
+        }
+
+        if self
+            .options
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(Error::DeadlineExceeded);
+        }
+
+        Ok(())
+    }
+
+    pub fn enter(&self) -> Result<(), Error> {
+        self.tick()?;
+        let k = self.depth.get() + 1;
+
+        if self.options.max_depth.is_some_and(|n| k > n) {
+            return Err(Error::DepthLimit);
+        }
+
+        self.depth.set(k);
+        Ok(())
+    }
+
+    pub fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    pub fn unfold(&self) -> Result<(), Error> {
+        let k = self.unfoldings.get() + 1;
+
+        if self.options.max_unfoldings.is_some_and(|n| k > n) {
+            return Err(Error::UnfoldLimit);
+        }
+
+        self.unfoldings.set(k);
+        Ok(())
+    }
+
+    // How many closures have been applied so far.
+    pub fn unfoldings(&self) -> usize {
+        self.unfoldings.get()
+    }
+
+    // Whether let bindings unfold where a let is checked.
+    pub fn zeta(&self) -> bool {
+        !self.options.opaque_lets
+    }
+
+    pub fn observational(&self) -> bool {
+        self.options.observational
+    }
+
+    pub fn strategy(&self) -> NormStrategy {
+        self.options.strategy
+    }
+
+    // Counts the evaluations of a let binding saved by sharing its value
+    // between `n` uses of the variable.
+    pub fn share(&self, n: usize) {
+        self.shared.set(self.shared.get() + n.saturating_sub(1));
+    }
+
+    // How many evaluations of let bindings sharing has saved so far.
+    pub fn shared(&self) -> usize {
+        self.shared.get()
+    }
+
+    // Counts a comparison of two types.
+    pub fn convert(&self) {
+        self.conversions.set(self.conversions.get() + 1);
+    }
+
+    // How many types have been compared so far.
+    pub fn conversions(&self) -> usize {
+        self.conversions.get()
+    }
+
+    // Whether two folded forms were found equal once unfolded, if they were
+    // compared before. A hit is only taken once the forms stored with it are
+    // found to be these, since different forms may have the same hashes.
+    pub fn compared(&self, e_1: &Expr, e_2: &Expr) -> Option<bool> {
+        let key = (e_1.hash_with(&|_| None), e_2.hash_with(&|_| None));
+        let equal = match self.compared.borrow().get(&key) {
+            Some((e_3, e_4, equal)) if e_1 == e_3 && e_2 == e_4 => Some(*equal),
+            _ => None,
+        };
+
+        let k = if equal.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        k.set(k.get() + 1);
+        equal
+    }
+
+    pub fn remember(&self, e_1: Expr, e_2: Expr, equal: bool) {
+        let key = (e_1.hash_with(&|_| None), e_2.hash_with(&|_| None));
+        self.compared.borrow_mut().insert(key, (e_1, e_2, equal));
+    }
+
+    // How many comparisons the cache has answered, and how many it has not,
+    // so far.
+    pub fn cache_hits(&self) -> usize {
+        self.hits.get()
+    }
+
+    pub fn cache_misses(&self) -> usize {
+        self.misses.get()
+    }
+
+    // Forgets the comparisons made, once a declaration is checked.
+    pub fn clear_cache(&self) {
+        self.compared.borrow_mut().clear();
+    }
+
+    // The size of the largest term checked against the size limit since the
+    // last call.
+    pub fn take_peak_size(&self) -> usize {
+        self.peak_size.take()
+    }
+
+    // Runs `f` with at most `n` more unfoldings, which count against this
+    // guard as well. Gives `None` if it is this budget that runs out rather
+    // than a limit of the guard.
+    pub fn within<T>(
+        &self,
+        n: usize,
+        f: impl FnOnce(&Guard) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        let k = self.unfoldings.get();
+        let left = self.options.max_unfoldings.map(|m| m.saturating_sub(k));
+
+        let g = Guard::new(CheckOptions {
+            max_unfoldings: Some(left.map_or(n, |m| m.min(n))),
+            ..self.options.to_owned()
+        });
+
+        g.steps.set(self.steps.get());
+        g.depth.set(self.depth.get());
+        let result = f(&g);
+        self.steps.set(g.steps.get());
+        self.unfoldings.set(k + g.unfoldings());
+        self.conversions
+            .set(self.conversions.get() + g.conversions());
+        self.hits.set(self.hits.get() + g.cache_hits());
+        self.misses.set(self.misses.get() + g.cache_misses());
+        self.shared.set(self.shared.get() + g.shared());
+        self.peak_size
+            .set(self.peak_size.get().max(g.take_peak_size()));
+
+        match result {
+            Err(Error::UnfoldLimit) if left.is_none_or(|m| m > n) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    pub fn check_size(&self, e: &Expr) -> Result<(), Error> {
+        let k = e.size();
+        self.peak_size.set(self.peak_size.get().max(k));
+
+        match self.options.max_size {
+            Some(n) if k > n => Err(Error::SizeLimit),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The result of a computation run without options set, which nothing can
+// interrupt. It can still fail on a term that was never checked, one with
+// holes, syntax errors or a splice of an unknown term, or one applying
+// something that is not a function, and then this panics: the unguarded
+// entry points are only for checked terms.
+pub(crate) fn unguarded<T>(result: Result<T, Error>) -> T {
+    result.unwrap_or_else(|e| panic!("evaluating an unchecked term: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_comparisons_are_confirmed() {
+        let g = Guard::default();
+        let (e_1, e_2) = (Expr::Var("T".to_owned()), Expr::Int);
+        assert_eq!(g.compared(&e_1, &e_2), None);
+        g.remember(e_1.to_owned(), e_2.to_owned(), true);
+        assert_eq!(g.compared(&e_1, &e_2), Some(true));
+
+        // Forms stored under the same hashes, as if they collided.
+        let key = (e_1.hash_with(&|_| None), e_2.hash_with(&|_| None));
+        let stored = (Expr::Var("S".to_owned()), Expr::Int, true);
+        g.compared.borrow_mut().insert(key, stored);
+        assert_eq!(g.compared(&e_1, &e_2), None);
+        assert_eq!((g.cache_hits(), g.cache_misses()), (1, 2));
+    }
+}
@@ -0,0 +1,1114 @@
+use std::collections::HashMap;
+
+use crate::{
+    decl::{Decl, Def, Section},
+    diff::children,
+    interface::{Interface, Signature},
+    lexer::{tokenize, LexError, Pos, Token},
+    macros::Macro,
+    notation::{Fixity, Notation, Notations},
+    telescope::Telescope,
+    Expr, Identifier, Prim,
+};
+
+pub const KEYWORDS: [&str; 31] = [
+    "Code", "Int", "List", "ListRec", "String", "Type", "U", "as", "axiom", "coercion", "cons",
+    "def", "end", "forall", "import", "in", "infix", "infixl", "infixr", "instance", "let",
+    "macro", "module", "mutual", "nil", "opaque", "open", "private", "quote", "section", "splice",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub pos: Pos,
+    pub message: &'static str,
+}
+
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        Self {
+            pos: e.pos,
+            message: e.message,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Check(Expr),
+    Eval(Expr),
+    Print(Identifier),
+}
+
+// import Data.Nat as N
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Import {
+    pub module: Identifier,
+    pub alias: Option<Identifier>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Item {
+    // axiom x : T, a definition without a body.
+    Axiom(Identifier, Expr),
+    Coercion(Identifier),
+    Command(Command),
+    // A plain comment between items.
+    Comment(String),
+    Decl(Decl),
+    Import(Import),
+    Instance(Identifier),
+    Macro(Macro),
+    Module(Identifier),
+    Notation(Notation),
+    Open(Identifier),
+    Section(Section),
+}
+
+impl Item {
+    // Module declarations come first, then imports, then everything else.
+    // Comments may go anywhere.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Comment(_) | Self::Module(_) => 0,
+            Self::Import(_) => 1,
+            _ => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Header {
+    pub module: Option<Identifier>,
+    pub imports: Vec<(Pos, Import)>,
+}
+
+struct Parser<'a> {
+    ts: Vec<(Token, Pos)>,
+    i: usize,
+    ns: &'a mut Notations,
+    // Doc comments, keyed by the index of the token they precede.
+    docs: HashMap<usize, String>,
+    // Plain comments with their positions, keyed the same way.
+    comments: HashMap<usize, Vec<(Pos, String)>>,
+    // Whether syntax errors are recorded in `errors` and skipped over
+    // instead of ending the parse.
+    recover: bool,
+    errors: Vec<ParseError>,
+}
+
+pub fn parse_expr(src: &str, ns: &mut Notations) -> Result<Expr, ParseError> {
+    let mut p = Parser::new(src, ns)?;
+    let e = p.expr()?;
+    p.eof()?;
+    Ok(e)
+}
+
+pub fn parse_program(src: &str, ns: &mut Notations) -> Result<Vec<(Pos, Item)>, ParseError> {
+    Parser::new(src, ns)?.program()
+}
+
+// Parses a source file like `parse_program`, but recovers from syntax errors
+// at the next declaration, and at the closing parenthesis of the group they
+// occur in, so that every one of them is reported. What did not parse of a
+// declaration or command is left as `Expr::Error`, and items that did not
+// parse at all are dropped.
+pub fn parse_program_recovering(
+    src: &str,
+    ns: &mut Notations,
+) -> (Vec<(Pos, Item)>, Vec<ParseError>) {
+    let mut p = match Parser::new(src, ns) {
+        Ok(p) => p,
+        Err(e) => return (vec![], vec![e]),
+    };
+
+    p.recover = true;
+
+    match p.program() {
+        Ok(items) => (items, p.errors),
+        Err(e) => (vec![], vec![e]),
+    }
+}
+
+// The module declaration and imports a source file starts with, which have
+// to be loaded before the rest of it is parsed with the notations they
+// declare.
+pub fn parse_header(src: &str) -> Result<Header, ParseError> {
+    let mut ns = Notations::new();
+    let mut p = Parser::new(src, &mut ns)?;
+    let mut h = Header::default();
+
+    if p.keyword("module") {
+        h.module = Some(p.module_name()?);
+    }
+
+    while p.is_keyword("import") {
+        let pos = p.pos();
+        h.imports.push((pos, p.import()?));
+    }
+
+    Ok(h)
+}
+
+pub fn parse_interface(src: &str) -> Result<Interface, ParseError> {
+    let mut ns = Notations::new();
+    let mut p = Parser::new(src, &mut ns)?;
+    let mut i = Interface::default();
+
+    if p.keyword("module") {
+        i.module = Some(p.module_name()?);
+    }
+
+    while p.peek().is_some() {
+        if p.keyword("import") {
+            i.imports.push(p.module_name()?);
+        } else if let Some(fixity) = p.fixity() {
+            i.notations.push(p.notation(fixity)?);
+        } else if p.keyword("coercion") {
+            i.coercions.push(p.qualified()?);
+        } else if p.keyword("instance") {
+            i.instances.push(p.qualified()?);
+        } else if p.keyword("axiom") {
+            i.axioms.push(p.qualified()?);
+        } else {
+            i.sigs.push(p.signature()?);
+        }
+    }
+
+    Ok(i)
+}
+
+impl Expr {
+    // Whether error recovery left out any part of the term.
+    pub fn has_errors(&self) -> bool {
+        matches!(self, Self::Error) || children(self).into_iter().any(|(e, _)| e.has_errors())
+    }
+}
+
+fn name_expr(x: &str) -> Expr {
+    Prim::from_name(x).map_or_else(|| Expr::Var(x.to_owned()), Expr::Prim)
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &str, ns: &'a mut Notations) -> Result<Self, ParseError> {
+        let mut ts = vec![];
+        let mut docs = HashMap::<usize, String>::new();
+        let mut comments = HashMap::<_, Vec<_>>::new();
+
+        for (t, pos) in tokenize(src)? {
+            match t {
+                Token::Doc(a) => {
+                    let b = docs.entry(ts.len()).or_default();
+
+                    if !b.is_empty() {
+                        b.push('\n');
+                    }
+
+                    b.push_str(&a);
+                }
+                Token::Comment(a) => comments.entry(ts.len()).or_default().push((pos, a)),
+                t => ts.push((t, pos)),
+            }
+        }
+
+        Ok(Self {
+            ts,
+            i: 0,
+            ns,
+            docs,
+            comments,
+            recover: false,
+            errors: vec![],
+        })
+    }
+
+    fn program(&mut self) -> Result<Vec<(Pos, Item)>, ParseError> {
+        let mut items = vec![];
+
+        loop {
+            for (pos, a) in self.comments.remove(&self.i).unwrap_or_default() {
+                items.push((pos, Item::Comment(a)));
+            }
+
+            if self.peek().is_none() {
+                break;
+            }
+
+            let pos = self.pos();
+            let i = self.i;
+
+            let item = match self.item() {
+                Ok(item) => item,
+                Err(e) if self.recover => {
+                    self.errors.push(e);
+                    self.synchronize(i);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            if items
+                .iter()
+                .any(|(_, item_): &(_, Item)| item_.rank() > item.rank())
+                && !matches!(item, Item::Comment(_))
+            {
+                let e = ParseError {
+                    pos,
+                    message: match item {
+                        Item::Module(_) => "module declaration must come first",
+                        _ => "imports must come first",
+                    },
+                };
+
+                if !self.recover {
+                    return Err(e);
+                }
+
+                self.errors.push(e);
+                continue;
+            }
+
+            items.push((pos, item));
+        }
+
+        Ok(items)
+    }
+
+    // Skips to the next token that can only start a declaration, past the
+    // token at `i` where the failed one started.
+    fn synchronize(&mut self, i: usize) {
+        self.i = self.i.max(i + 1);
+
+        while self.peek().is_some() && !self.starts_item() {
+            self.i += 1;
+        }
+    }
+
+    fn starts_item(&self) -> bool {
+        matches!(self.peek(), Some(Token::Command(_)))
+            || [
+                "axiom", "coercion", "def", "end", "import", "infix", "infixl", "infixr",
+                "instance", "macro", "module", "mutual", "opaque", "open", "private", "section",
+            ]
+            .iter()
+            .any(|kw| self.is_keyword(kw))
+    }
+
+    // Parses a term with `f`. When recovering, a syntax error is recorded
+    // instead, and the tokens from `i` up to `stop` outside of any brackets,
+    // or up to the next declaration, are skipped and stand for an error.
+    fn or_skip(
+        &mut self,
+        stop: Option<&Token>,
+        f: impl FnOnce(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let i = self.i;
+
+        let e = match f(self) {
+            Err(e) if self.recover => e,
+            result => return result,
+        };
+
+        self.errors.push(e);
+        self.i = i;
+        let mut depth = 0usize;
+
+        while let Some(t) = self.peek() {
+            if depth == 0 && (Some(t) == stop || self.starts_item()) {
+                break;
+            }
+
+            match t {
+                Token::LBracket | Token::LParen => depth += 1,
+                Token::RBracket | Token::RParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+
+            self.i += 1;
+        }
+
+        Ok(Expr::Error)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.ts.get(self.i).map(|(t, _)| t)
+    }
+
+    fn peek_at(&self, k: usize) -> Option<&Token> {
+        self.ts.get(self.i + k).map(|(t, _)| t)
+    }
+
+    fn pos(&self) -> Pos {
+        match self.ts.get(self.i).or(self.ts.last()) {
+            Some((_, pos)) => *pos,
+            None => Pos { line: 1, column: 1 },
+        }
+    }
+
+    fn error<T>(&self, message: &'static str) -> Result<T, ParseError> {
+        Err(ParseError {
+            pos: self.pos(),
+            message,
+        })
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.ts.get(self.i).map(|(t, _)| t.to_owned());
+        self.i += 1;
+        t
+    }
+
+    fn eat(&mut self, t: &Token) -> bool {
+        if self.peek() == Some(t) {
+            self.i += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, t: &Token, message: &'static str) -> Result<(), ParseError> {
+        if self.eat(t) {
+            Ok(())
+        } else {
+            self.error(message)
+        }
+    }
+
+    fn is_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(x)) if x == kw)
+    }
+
+    fn keyword(&mut self, kw: &str) -> bool {
+        let b = self.is_keyword(kw);
+
+        if b {
+            self.i += 1;
+        }
+
+        b
+    }
+
+    fn eof(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => self.error("unexpected token"),
+        }
+    }
+
+    fn ident(&mut self) -> Result<Identifier, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(x)) if !x.contains('.') => self.qualified(),
+            _ => self.error("expected identifier"),
+        }
+    }
+
+    fn qualified(&mut self) -> Result<Identifier, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(x)) if !KEYWORDS.contains(&x.as_str()) => {
+                let x = x.to_owned();
+                self.i += 1;
+                Ok(x)
+            }
+            _ => self.error("expected identifier"),
+        }
+    }
+
+    // Every segment of a module name is capitalized, so that the names it
+    // qualifies lex as one identifier.
+    fn module_name(&mut self) -> Result<Identifier, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(x)) if x.split('.').all(|a| a.starts_with(char::is_uppercase)) => {
+                self.qualified()
+            }
+            _ => self.error("expected module name"),
+        }
+    }
+
+    fn import(&mut self) -> Result<Import, ParseError> {
+        self.keyword("import");
+        let module = self.module_name()?;
+
+        let alias = if self.keyword("as") {
+            Some(self.module_name()?)
+        } else {
+            None
+        };
+
+        Ok(Import { module, alias })
+    }
+
+    fn item(&mut self) -> Result<Item, ParseError> {
+        if self.starts_def() {
+            Ok(Item::Decl(Decl::Def(self.def()?)))
+        } else if self.is_keyword("import") {
+            Ok(Item::Import(self.import()?))
+        } else if self.keyword("module") {
+            Ok(Item::Module(self.module_name()?))
+        } else if self.keyword("open") {
+            Ok(Item::Open(self.module_name()?))
+        } else if self.keyword("axiom") {
+            let x = self.ident()?;
+            self.expect(&Token::Colon, "expected `:`")?;
+            Ok(Item::Axiom(x, self.or_skip(None, Self::expr)?))
+        } else if self.keyword("coercion") {
+            Ok(Item::Coercion(self.qualified()?))
+        } else if self.keyword("instance") {
+            Ok(Item::Instance(self.qualified()?))
+        } else if self.keyword("macro") {
+            Ok(Item::Macro(self.macro_()?))
+        } else if self.keyword("mutual") {
+            let mut defs = vec![];
+
+            while !self.keyword("end") {
+                if !self.starts_def() {
+                    return self.error("expected `def` or `end`");
+                }
+
+                let i = self.i;
+
+                match self.def() {
+                    Ok(def) => defs.push(def),
+                    Err(e) if self.recover => {
+                        self.errors.push(e);
+                        self.synchronize(i);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok(Item::Decl(Decl::Mutual(defs)))
+        } else if self.keyword("section") {
+            let mut vars = Telescope::new();
+
+            while self.eat(&Token::LParen) {
+                let mut xs = vec![self.ident()?];
+
+                while !self.eat(&Token::Colon) {
+                    xs.push(self.ident()?);
+                }
+
+                let t = self.expr()?;
+                self.expect(&Token::RParen, "expected `)`")?;
+                for x in xs {
+                    vars.push(x, t.to_owned());
+                }
+            }
+
+            let mut decls = vec![];
+
+            while !self.keyword("end") {
+                if !self.starts_def() && !self.is_keyword("mutual") {
+                    return self.error("expected `def`, `mutual` or `end`");
+                }
+
+                let (pos, i) = (self.pos(), self.i);
+
+                match self.item() {
+                    Ok(Item::Decl(decl)) => decls.push((pos, decl)),
+                    Ok(_) => unreachable!(),
+                    Err(e) if self.recover => {
+                        self.errors.push(e);
+                        self.synchronize(i);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok(Item::Section(Section { vars, decls }))
+        } else if let Some(Token::Command(x)) = self.peek() {
+            let c = match x.as_str() {
+                "check" => Command::Check,
+                "eval" => Command::Eval,
+                "print" => {
+                    self.i += 1;
+                    return Ok(Item::Command(Command::Print(self.qualified()?)));
+                }
+                _ => return self.error("unknown command"),
+            };
+
+            self.i += 1;
+            Ok(Item::Command(c(self.or_skip(None, Self::expr)?)))
+        } else if let Some(fixity) = self.fixity() {
+            let n = self.notation(fixity)?;
+            self.ns.insert(n.to_owned());
+            Ok(Item::Notation(n))
+        } else {
+            self.error("expected declaration")
+        }
+    }
+
+    fn def(&mut self) -> Result<Def, ParseError> {
+        let start = self.i;
+        let doc = self.docs.remove(&self.i);
+        let private = self.keyword("private");
+        let opaque = self.keyword("opaque");
+
+        if !self.keyword("def") {
+            return self.error("expected `def`");
+        }
+
+        let name = self.ident()?;
+        self.expect(&Token::Colon, "expected `:`")?;
+        let ty = self.or_skip(Some(&Token::ColonEq), Self::expr)?;
+
+        let body = if self.eat(&Token::ColonEq) {
+            self.or_skip(None, Self::expr)?
+        } else if ty == Expr::Error {
+            // The error in the type already stands for the missing body.
+            Expr::Error
+        } else {
+            return self.error("expected `:=`");
+        };
+
+        let comments = (start..self.i)
+            .flat_map(|k| self.comments.remove(&k).unwrap_or_default())
+            .map(|(_, a)| a)
+            .collect();
+
+        Ok(Def {
+            name,
+            ty,
+            body,
+            doc,
+            comments,
+            opaque,
+            private,
+        })
+    }
+
+    fn starts_def(&self) -> bool {
+        ["def", "opaque", "private"]
+            .iter()
+            .any(|kw| self.is_keyword(kw))
+    }
+
+    // def f : T := e, or opaque def f : T without the body.
+    fn signature(&mut self) -> Result<Signature, ParseError> {
+        let opaque = self.keyword("opaque");
+
+        if !self.keyword("def") {
+            return self.error("expected `def`");
+        }
+
+        let name = self.qualified()?;
+        self.expect(&Token::Colon, "expected `:`")?;
+        let ty = self.expr()?;
+
+        let body = if opaque {
+            None
+        } else {
+            self.expect(&Token::ColonEq, "expected `:=`")?;
+            Some(self.expr()?)
+        };
+
+        Ok(Signature { name, ty, body })
+    }
+
+    // macro twice f x := f (f x)
+    fn macro_(&mut self) -> Result<Macro, ParseError> {
+        let name = self.ident()?;
+        let mut params = vec![];
+
+        while !self.eat(&Token::ColonEq) {
+            params.push(self.ident()?);
+        }
+
+        Ok(Macro {
+            name,
+            params,
+            body: self.expr()?,
+        })
+    }
+
+    fn fixity(&mut self) -> Option<Fixity> {
+        let fixity = match self.peek() {
+            Some(Token::Ident(x)) if x == "infix" => Fixity::Infix,
+            Some(Token::Ident(x)) if x == "infixl" => Fixity::Infixl,
+            Some(Token::Ident(x)) if x == "infixr" => Fixity::Infixr,
+            _ => return None,
+        };
+
+        self.i += 1;
+        Some(fixity)
+    }
+
+    // infixl 6 _+_ := int_add
+    fn notation(&mut self, fixity: Fixity) -> Result<Notation, ParseError> {
+        let precedence = match self.next() {
+            Some(Token::Int(n)) => match n.parse::<u8>() {
+                Ok(k) if k <= 9 => k,
+                _ => return self.error("precedence must be between 0 and 9"),
+            },
+            _ => return self.error("expected precedence"),
+        };
+
+        let underscore = Token::Ident("_".to_owned());
+        self.expect(&underscore, "expected `_`")?;
+
+        let Some(Token::Op(symbol)) = self.next() else {
+            return self.error("expected operator");
+        };
+
+        self.expect(&underscore, "expected `_`")?;
+        self.expect(&Token::ColonEq, "expected `:=`")?;
+        let name = self.qualified()?;
+
+        Ok(Notation {
+            fixity,
+            precedence,
+            symbol,
+            name,
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        if self.eat(&Token::Backslash) {
+            let mut bs = vec![];
+
+            loop {
+                self.lam_binders(&mut bs)?;
+
+                if self.eat(&Token::Dot) {
+                    break;
+                }
+            }
+
+            let e = self.expr()?;
+
+            Ok(bs
+                .into_iter()
+                .rev()
+                .fold(e, |e, (x, a)| Expr::Lam(x, a.map(Box::new), Box::new(e))))
+        } else if self.eat(&Token::Pi) || self.keyword("forall") {
+            let mut bs = vec![];
+
+            while self.eat(&Token::LParen) {
+                let mut xs = vec![self.ident()?];
+
+                while !self.eat(&Token::Colon) {
+                    xs.push(self.ident()?);
+                }
+
+                let t = self.expr()?;
+                self.expect(&Token::RParen, "expected `)`")?;
+                bs.extend(xs.into_iter().map(|x| (x, t.to_owned())));
+            }
+
+            if bs.is_empty() {
+                return self.error("expected `(`");
+            }
+
+            self.expect(&Token::Arrow, "expected `->`")?;
+            let mut e = self.expr()?;
+
+            // Binders the codomain does not mention make plain function
+            // types.
+            for (x, t) in bs.into_iter().rev() {
+                e = if e.occurs(&x) {
+                    Expr::Pi(x, Box::new(t), Box::new(e))
+                } else {
+                    Expr::Fun(Box::new(t), Box::new(e))
+                };
+            }
+
+            Ok(e)
+        } else if self.keyword("let") {
+            // `rec` is only a keyword here, so that it stays a name elsewhere.
+            if self.is_keyword("rec")
+                && !matches!(self.ts.get(self.i + 1), Some((Token::ColonEq, _)))
+            {
+                self.i += 1;
+                return self.let_rec();
+            }
+
+            let mut bs = vec![];
+
+            loop {
+                let x = self.ident()?;
+                self.expect(&Token::ColonEq, "expected `:=`")?;
+                bs.push((x, self.expr()?));
+
+                if !self.eat(&Token::Semicolon) {
+                    break;
+                }
+            }
+
+            if !self.keyword("in") {
+                return self.error("expected `in`");
+            }
+
+            let e = self.expr()?;
+
+            // A single binding is a plain substitution.
+            Ok(match <[_; 1]>::try_from(bs) {
+                Ok([(x, e_1)]) => Expr::Sub(x, Box::new(e_1), Box::new(e)),
+                Err(bs) => Expr::Let(bs, Box::new(e)),
+            })
+        } else {
+            self.arrow()
+        }
+    }
+
+    // The bindings and body of let rec x_1 : A_1 := e_1; ...; x_n : A_n := e_n
+    // in e.
+    fn let_rec(&mut self) -> Result<Expr, ParseError> {
+        let mut bs = vec![];
+
+        loop {
+            let x = self.ident()?;
+            self.expect(&Token::Colon, "expected `:`")?;
+            let a = self.expr()?;
+            self.expect(&Token::ColonEq, "expected `:=`")?;
+            bs.push((x, a, self.expr()?));
+
+            if !self.eat(&Token::Semicolon) {
+                break;
+            }
+        }
+
+        if !self.keyword("in") {
+            return self.error("expected `in`");
+        }
+
+        Ok(Expr::LetRec(bs, Box::new(self.expr()?)))
+    }
+
+    // x, or (x y : A) giving the binders a type.
+    fn lam_binders(&mut self, bs: &mut Vec<(Identifier, Option<Expr>)>) -> Result<(), ParseError> {
+        if !self.eat(&Token::LParen) {
+            bs.push((self.ident()?, None));
+            return Ok(());
+        }
+
+        let mut xs = vec![self.ident()?];
+
+        while !self.eat(&Token::Colon) {
+            xs.push(self.ident()?);
+        }
+
+        let t = self.expr()?;
+        self.expect(&Token::RParen, "expected `)`")?;
+        bs.extend(xs.into_iter().map(|x| (x, Some(t.to_owned()))));
+        Ok(())
+    }
+
+    fn arrow(&mut self) -> Result<Expr, ParseError> {
+        if self.eat(&Token::LBracket) {
+            let e_1 = self.expr()?;
+            self.expect(&Token::RBracket, "expected `]`")?;
+            self.expect(&Token::Arrow, "expected `->`")?;
+            let e_2 = self.arrow()?;
+            return Ok(Expr::Inst(Box::new(e_1), Box::new(e_2)));
+        }
+
+        let e_1 = self.ops(0)?;
+
+        if self.eat(&Token::Arrow) {
+            let e_2 = self.arrow()?;
+            Ok(Expr::Fun(Box::new(e_1), Box::new(e_2)))
+        } else {
+            Ok(e_1)
+        }
+    }
+
+    // Precedence climbing over the declared operators.
+    fn ops(&mut self, min: u8) -> Result<Expr, ParseError> {
+        let mut e = self.app()?;
+
+        while let Some(Token::Op(a)) = self.peek() {
+            let Some(n) = self.ns.get(a).cloned() else {
+                return self.error("unknown operator");
+            };
+
+            if n.precedence < min {
+                break;
+            }
+
+            self.i += 1;
+
+            let e_2 = self.ops(match n.fixity {
+                Fixity::Infixr => n.precedence,
+                Fixity::Infix | Fixity::Infixl => n.precedence + 1,
+            })?;
+
+            e = Expr::App(
+                Box::new(Expr::App(Box::new(name_expr(&n.name)), Box::new(e))),
+                Box::new(e_2),
+            );
+
+            if n.fixity == Fixity::Infix {
+                if let Some(Token::Op(b)) = self.peek() {
+                    if self.ns.get(b).is_some_and(|m| m.precedence == n.precedence) {
+                        return self.error("non-associative operator");
+                    }
+                }
+            }
+        }
+
+        Ok(e)
+    }
+
+    fn app(&mut self) -> Result<Expr, ParseError> {
+        let mut e = if self.keyword("List") {
+            Expr::List(Box::new(self.atom()?))
+        } else if self.keyword("cons") {
+            let e_1 = self.atom()?;
+            let e_2 = self.atom()?;
+            Expr::Cons(Box::new(e_1), Box::new(e_2))
+        } else if self.keyword("quote") {
+            Expr::Quote(Box::new(self.atom()?))
+        } else if self.keyword("splice") {
+            Expr::Splice(Box::new(self.atom()?))
+        } else if self.keyword("ListRec") {
+            let e_1 = self.atom()?;
+            let e_2 = self.atom()?;
+            let e_3 = self.atom()?;
+            Expr::ListRec(Box::new(e_1), Box::new(e_2), Box::new(e_3))
+        } else if self.keyword("U") || self.keyword("Type") {
+            match self.peek() {
+                Some(Token::Int(n)) => match n.parse() {
+                    Ok(i) => {
+                        self.i += 1;
+                        Expr::U(i)
+                    }
+                    Err(_) => return self.error("universe level out of range"),
+                },
+                _ => Expr::U(self.ns.universe()),
+            }
+        } else {
+            self.atom()?
+        };
+
+        while self.starts_atom() {
+            e = Expr::App(Box::new(e), Box::new(self.atom()?));
+        }
+
+        Ok(e)
+    }
+
+    fn starts_atom(&self) -> bool {
+        match self.peek() {
+            Some(Token::Ident(x)) => {
+                !KEYWORDS.contains(&x.as_str())
+                    || ["Code", "Int", "String", "nil"].contains(&x.as_str())
+            }
+            Some(Token::Hole(_) | Token::Int(_) | Token::LParen | Token::Str(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Ident(x)) if x == "Code" => {
+                self.i += 1;
+                Ok(Expr::Code)
+            }
+            Some(Token::Ident(x)) if x == "Int" => {
+                self.i += 1;
+                Ok(Expr::Int)
+            }
+            Some(Token::Ident(x)) if x == "String" => {
+                self.i += 1;
+                Ok(Expr::Str)
+            }
+            Some(Token::Ident(x)) if x == "nil" => {
+                self.i += 1;
+                Ok(Expr::Nil)
+            }
+            // `_` binds nothing, so there is nothing for it to refer to.
+            Some(Token::Ident(x)) if x == "_" => self.error("`_` cannot be referred to"),
+            Some(Token::Ident(_)) => Ok(name_expr(&self.qualified()?)),
+            Some(Token::Int(n)) => {
+                self.i += 1;
+                self.int(&n)
+            }
+            Some(Token::Op(a)) if a == "-" && matches!(self.peek_at(1), Some(Token::Int(_))) => {
+                self.i += 1;
+                let Some(Token::Int(n)) = self.next() else {
+                    unreachable!()
+                };
+                self.int(&format!("-{n}"))
+            }
+            Some(Token::Str(a)) => {
+                self.i += 1;
+                Ok(Expr::StrLit(a))
+            }
+            Some(Token::Hole(h)) => {
+                self.i += 1;
+                Ok(Expr::Hole(h))
+            }
+            Some(Token::LParen) => {
+                self.i += 1;
+
+                let e = self.or_skip(Some(&Token::RParen), |p| {
+                    let e = p.expr()?;
+
+                    if p.peek() != Some(&Token::RParen) {
+                        return p.error("expected `)`");
+                    }
+
+                    Ok(e)
+                })?;
+
+                // The parenthesis is only missing if the group did not parse,
+                // which is already reported.
+                self.eat(&Token::RParen);
+                Ok(e)
+            }
+            _ => self.error("expected expression"),
+        }
+    }
+
+    fn int(&self, n: &str) -> Result<Expr, ParseError> {
+        match n.parse() {
+            Ok(n) => Ok(Expr::IntLit(n)),
+            Err(_) => self.error("integer literal out of range"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notation_parsing_works() {
+        let mut ns = Notations::new();
+
+        let items = parse_program(
+            "infixl 6 _+_ := int_add\ninfixl 7 _*_ := int_mul\ninfixr 5 _++_ := str_append",
+            &mut ns,
+        )
+        .unwrap();
+
+        assert_eq!(items.len(), 3);
+
+        let app = |e_1, e_2| Expr::App(Box::new(e_1), Box::new(e_2));
+        let op = |p, e_1, e_2| app(app(Expr::Prim(p), e_1), e_2);
+        let var = |x: &str| Expr::Var(x.to_string());
+
+        assert_eq!(
+            parse_expr("a + b * c + -1", &mut ns).unwrap(),
+            op(
+                Prim::IntAdd,
+                op(Prim::IntAdd, var("a"), op(Prim::IntMul, var("b"), var("c"))),
+                Expr::IntLit(-1)
+            )
+        );
+
+        assert_eq!(
+            parse_expr("a ++ b ++ c", &mut ns).unwrap(),
+            op(
+                Prim::StrAppend,
+                var("a"),
+                op(Prim::StrAppend, var("b"), var("c"))
+            )
+        );
+
+        assert_eq!(
+            parse_expr("a - b", &mut ns).unwrap_err().message,
+            "unknown operator"
+        );
+    }
+
+    #[test]
+    fn errors_are_recovered_from() {
+        let src = "def f : Int := (1 +) 2
+def g : Int -> := 3
+#check )
+mutual
+def h : Int := 1
+def k : Int :=
+end
+#eval f";
+
+        let (items, es) = parse_program_recovering(src, &mut Notations::new());
+
+        assert_eq!(
+            es.iter()
+                .map(|e| (e.pos.line, e.message))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, "unknown operator"),
+                (2, "expected expression"),
+                (3, "expected expression"),
+                (7, "expected identifier"),
+            ]
+        );
+
+        let Item::Decl(Decl::Def(f)) = &items[0].1 else {
+            panic!()
+        };
+
+        assert_eq!(
+            f.body,
+            Expr::App(Box::new(Expr::Error), Box::new(Expr::IntLit(2)))
+        );
+
+        let Item::Decl(Decl::Def(g)) = &items[1].1 else {
+            panic!()
+        };
+
+        assert_eq!((&g.ty, &g.body), (&Expr::Error, &Expr::IntLit(3)));
+
+        let Item::Decl(Decl::Mutual(defs)) = &items[3].1 else {
+            panic!()
+        };
+
+        assert_eq!(defs[1].body, Expr::Error);
+        assert!(matches!(items[4].1, Item::Command(Command::Eval(_))));
+        assert!(parse_program(src, &mut Notations::new()).is_err());
+    }
+
+    #[test]
+    fn universes_are_spelled_several_ways() {
+        let mut ns = Notations::new();
+        let parse = |a, ns: &mut Notations| parse_expr(a, ns).map(|e| e.to_string());
+
+        assert_eq!(
+            parse("U -> Type 1 -> U 2", &mut ns).unwrap(),
+            "U 0 -> U 1 -> U 2"
+        );
+        ns.set_universe(1);
+        assert_eq!(parse("Type -> U", &mut ns).unwrap(), "U 1 -> U 1");
+        assert_eq!(
+            parse("Type 256", &mut ns).unwrap_err().message,
+            "universe level out of range"
+        );
+
+        let e = parse_expr("Type -> Type 0 -> Type 2", &mut ns).unwrap();
+        assert_eq!(
+            e.pretty(&Notations::new()).types(true).to_string(),
+            "Type 1 -> Type -> Type 2"
+        );
+        assert!(matches!(
+            Expr::U(255).infer(&Default::default()),
+            Err(e) if e == "universe level overflow"
+        ));
+    }
+
+    #[test]
+    fn unicode_parsing_works() {
+        let mut ns = Notations::new();
+        parse_program("infixr 9 _∘_ := compose", &mut ns).unwrap();
+
+        assert_eq!(
+            parse_expr("λf g. f ∘ g", &mut ns).unwrap(),
+            parse_expr("\\f g. compose f g", &mut ns).unwrap()
+        );
+
+        assert_eq!(
+            parse_expr("Π (x y : Int) → ∀ (α : U 0) → Int", &mut ns).unwrap(),
+            parse_expr("Int -> Int -> U 0 -> Int", &mut ns).unwrap()
+        );
+
+        assert_eq!(
+            parse_expr("∀ (α : U 0) → α", &mut ns).unwrap(),
+            Expr::Pi(
+                "α".to_string(),
+                Box::new(Expr::U(0)),
+                Box::new(Expr::Var("α".to_string()))
+            )
+        );
+    }
+}
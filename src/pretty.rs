@@ -0,0 +1,528 @@
+use std::fmt;
+
+use crate::{
+    notation::{Fixity, Notation, Notations},
+    Expr,
+};
+
+pub(crate) const LAM: u8 = 0;
+pub(crate) const ARROW: u8 = 1;
+pub(crate) const OP: u8 = 10;
+pub(crate) const APP: u8 = 30;
+pub(crate) const ATOM: u8 = 31;
+
+pub type Links<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    Binder,
+    Keyword,
+    Literal,
+    Operator,
+    Type,
+    Universe,
+}
+
+impl Class {
+    // The CSS class of the spans emitted for HTML.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Binder => "binder",
+            Self::Keyword => "keyword",
+            Self::Literal => "literal",
+            Self::Operator => "operator",
+            Self::Type => "type",
+            Self::Universe => "universe",
+        }
+    }
+
+    // The SGR parameters of the escape sequence emitted for terminals.
+    pub fn ansi(self) -> &'static str {
+        match self {
+            Self::Binder => "35",
+            Self::Keyword => "1;34",
+            Self::Literal => "32",
+            Self::Operator => "33",
+            Self::Type => "36",
+            Self::Universe => "1;36",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Markup {
+    Plain,
+    Ansi,
+    Html,
+}
+
+pub struct Pretty<'a> {
+    e: &'a Expr,
+    ns: &'a Notations,
+    unicode: bool,
+    types: bool,
+    markup: Markup,
+    links: Option<Links<'a>>,
+}
+
+impl Expr {
+    pub fn pretty<'a>(&'a self, ns: &'a Notations) -> Pretty<'a> {
+        Pretty {
+            e: self,
+            ns,
+            unicode: false,
+            types: false,
+            markup: Markup::Plain,
+            links: None,
+        }
+    }
+}
+
+impl<'a> Pretty<'a> {
+    pub fn unicode(mut self, unicode: bool) -> Self {
+        self.unicode = unicode;
+        self
+    }
+
+    // Writes universes as `Type`, leaving out the level of the lowest one,
+    // for sources whose bare universe is the lowest.
+    pub fn types(mut self, types: bool) -> Self {
+        self.types = types;
+        self
+    }
+
+    // Colors binders, keywords, literals, operators, types and universes
+    // with ANSI escape sequences.
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        if ansi {
+            self.markup = Markup::Ansi;
+        } else if self.markup == Markup::Ansi {
+            self.markup = Markup::Plain;
+        }
+
+        self
+    }
+
+    // Renders HTML instead of plain text, wrapping highlighted tokens in
+    // spans of their class and linking every free variable for which `links`
+    // returns an anchor.
+    pub fn html(mut self, links: Links<'a>) -> Self {
+        self.markup = Markup::Html;
+        self.links = Some(links);
+        self
+    }
+}
+
+pub fn escape_html(a: &str) -> String {
+    let mut b = String::new();
+
+    for c in a.chars() {
+        match c {
+            '&' => b.push_str("&amp;"),
+            '<' => b.push_str("&lt;"),
+            '>' => b.push_str("&gt;"),
+            '"' => b.push_str("&quot;"),
+            c => b.push(c),
+        }
+    }
+
+    b
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pretty(&Notations::new()))
+    }
+}
+
+impl fmt::Display for Pretty<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_expr(self.e, LAM, &[], f)
+    }
+}
+
+pub(crate) fn operator<'a>(
+    e: &'a Expr,
+    ns: &'a Notations,
+) -> Option<(&'a Notation, &'a Expr, &'a Expr)> {
+    let Expr::App(e_1, e_3) = e else {
+        return None;
+    };
+
+    let Expr::App(e, e_2) = e_1.as_ref() else {
+        return None;
+    };
+
+    let n = match e.as_ref() {
+        Expr::Var(x) => ns.for_name(x)?,
+        Expr::Prim(p) => ns.for_name(p.name())?,
+        _ => return None,
+    };
+
+    Some((n, e_2, e_3))
+}
+
+pub(crate) fn precedence(e: &Expr, ns: &Notations) -> u8 {
+    if let Some((n, _, _)) = operator(e, ns) {
+        return OP + n.precedence;
+    }
+
+    match e {
+        Expr::Pi(x, _, e) if !e.occurs(x) => ARROW,
+        Expr::Lam(_, _, _)
+        | Expr::Let(_, _)
+        | Expr::LetRec(_, _)
+        | Expr::Pi(_, _, _)
+        | Expr::Sub(_, _, _) => LAM,
+        Expr::Fun(_, _) | Expr::Inst(_, _) => ARROW,
+        Expr::App(_, _)
+        | Expr::Cons(_, _)
+        | Expr::List(_)
+        | Expr::ListRec(_, _, _)
+        | Expr::Quote(_)
+        | Expr::Splice(_)
+        | Expr::U(_) => APP,
+        &Expr::IntLit(n) if n < 0 => APP,
+        _ => ATOM,
+    }
+}
+
+pub(crate) fn quote_str(a: &str) -> String {
+    let mut b = String::from('"');
+
+    for c in a.chars() {
+        match c {
+            '"' => b.push_str("\\\""),
+            '\\' => b.push_str("\\\\"),
+            '\n' => b.push_str("\\n"),
+            '\t' => b.push_str("\\t"),
+            c => b.push(c),
+        }
+    }
+
+    b.push('"');
+    b
+}
+
+impl Pretty<'_> {
+    fn text(&self, a: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.markup {
+            Markup::Html => write!(f, "{}", escape_html(a)),
+            _ => write!(f, "{a}"),
+        }
+    }
+
+    fn token(&self, c: Class, a: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.markup {
+            Markup::Plain => write!(f, "{a}"),
+            Markup::Ansi => write!(f, "\x1b[{}m{a}\x1b[0m", c.ansi()),
+            Markup::Html => write!(f, "<span class=\"{}\">{}</span>", c.name(), escape_html(a)),
+        }
+    }
+
+    fn var(&self, x: &str, bs: &[&str], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self
+            .links
+            .filter(|_| !bs.contains(&x))
+            .and_then(|links| links(x))
+        {
+            Some(href) => write!(
+                f,
+                "<a href=\"#{}\">{}</a>",
+                escape_html(&href),
+                escape_html(x)
+            ),
+            None => self.text(x, f),
+        }
+    }
+
+    // x, or (x : A) if the binder is annotated.
+    fn write_binder(
+        &self,
+        x: &str,
+        a: Option<&Expr>,
+        bs: &[&str],
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        let Some(a) = a else {
+            return self.token(Class::Binder, x, f);
+        };
+
+        write!(f, "(")?;
+        self.token(Class::Binder, x, f)?;
+        write!(f, " : ")?;
+        self.write_expr(a, LAM, bs, f)?;
+        write!(f, ")")
+    }
+
+    fn write_arrow(
+        &self,
+        e_1: &Expr,
+        e_2: &Expr,
+        bs: &[&str],
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        self.write_expr(e_1, ARROW + 1, bs, f)?;
+        write!(f, " ")?;
+        self.token(Class::Type, if self.unicode { "→" } else { "->" }, f)?;
+        write!(f, " ")?;
+        self.write_expr(e_2, ARROW, bs, f)
+    }
+
+    fn write_expr(&self, e: &Expr, p: u8, bs: &[&str], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let q = precedence(e, self.ns);
+
+        if q < p {
+            write!(f, "(")?;
+            self.write_expr(e, LAM, bs, f)?;
+            return write!(f, ")");
+        }
+
+        if let Some((n, e_1, e_2)) = operator(e, self.ns) {
+            let (p_1, p_2) = match n.fixity {
+                Fixity::Infix => (q + 1, q + 1),
+                Fixity::Infixl => (q, q + 1),
+                Fixity::Infixr => (q + 1, q),
+            };
+
+            self.write_expr(e_1, p_1, bs, f)?;
+            write!(f, " ")?;
+            self.token(Class::Operator, &n.symbol, f)?;
+            write!(f, " ")?;
+            return self.write_expr(e_2, p_2, bs, f);
+        }
+
+        match e {
+            Expr::App(e_1, e_2) => {
+                self.write_expr(e_1, APP, bs, f)?;
+                write!(f, " ")?;
+                self.write_expr(e_2, ATOM, bs, f)
+            }
+            Expr::Code => self.token(Class::Type, "Code", f),
+            Expr::Cons(e_1, e_2) => {
+                self.token(Class::Keyword, "cons", f)?;
+                write!(f, " ")?;
+                self.write_expr(e_1, ATOM, bs, f)?;
+                write!(f, " ")?;
+                self.write_expr(e_2, ATOM, bs, f)
+            }
+            Expr::Fun(e_1, e_2) => self.write_arrow(e_1, e_2, bs, f),
+            Expr::Inst(e_1, e_2) => {
+                write!(f, "[")?;
+                self.write_expr(e_1, LAM, bs, f)?;
+                write!(f, "] ")?;
+                self.token(Class::Type, if self.unicode { "→" } else { "->" }, f)?;
+                write!(f, " ")?;
+                self.write_expr(e_2, ARROW, bs, f)
+            }
+            Expr::Error => write!(f, "<error>"),
+            Expr::Hole(h) => write!(f, "?{h}"),
+            Expr::Int => self.token(Class::Type, "Int", f),
+            Expr::IntLit(n) => self.token(Class::Literal, &n.to_string(), f),
+            Expr::Lam(x, a, e) => {
+                self.token(Class::Keyword, if self.unicode { "λ" } else { "\\" }, f)?;
+                let mut bs_ = bs.to_owned();
+                self.write_binder(x, a.as_deref(), &bs_, f)?;
+                bs_.push(x);
+                let mut e = e.as_ref();
+
+                while let Expr::Lam(y, a, e_) = e {
+                    write!(f, " ")?;
+                    self.write_binder(y, a.as_deref(), &bs_, f)?;
+                    bs_.push(y);
+                    e = e_;
+                }
+
+                write!(f, ". ")?;
+                self.write_expr(e, LAM, &bs_, f)
+            }
+            Expr::List(e) => {
+                self.token(Class::Type, "List", f)?;
+                write!(f, " ")?;
+                self.write_expr(e, ATOM, bs, f)
+            }
+            Expr::Quote(e) => {
+                self.token(Class::Keyword, "quote", f)?;
+                write!(f, " ")?;
+                self.write_expr(e, ATOM, bs, f)
+            }
+            Expr::Splice(e) => {
+                self.token(Class::Keyword, "splice", f)?;
+                write!(f, " ")?;
+                self.write_expr(e, ATOM, bs, f)
+            }
+            Expr::ListRec(e_1, e_2, e_3) => {
+                self.token(Class::Keyword, "ListRec", f)?;
+                write!(f, " ")?;
+                self.write_expr(e_1, ATOM, bs, f)?;
+                write!(f, " ")?;
+                self.write_expr(e_2, ATOM, bs, f)?;
+                write!(f, " ")?;
+                self.write_expr(e_3, ATOM, bs, f)
+            }
+            Expr::Nil => self.token(Class::Keyword, "nil", f),
+            // A binder the codomain does not mention is left out.
+            Expr::Pi(_, e_1, e_2) if q == ARROW => self.write_arrow(e_1, e_2, bs, f),
+            Expr::Pi(x, e_1, e_2) => {
+                self.token(Class::Type, if self.unicode { "Π" } else { "forall" }, f)?;
+                let mut bs_ = bs.to_owned();
+                write!(f, " ")?;
+                self.write_binder(x, Some(e_1), &bs_, f)?;
+                bs_.push(x);
+                let mut e = e_2.as_ref();
+
+                while let Expr::Pi(y, e_1, e_2) = e {
+                    if !e_2.occurs(y) {
+                        break;
+                    }
+
+                    write!(f, " ")?;
+                    self.write_binder(y, Some(e_1), &bs_, f)?;
+                    bs_.push(y);
+                    e = e_2;
+                }
+
+                write!(f, " ")?;
+                self.token(Class::Type, if self.unicode { "→" } else { "->" }, f)?;
+                write!(f, " ")?;
+                self.write_expr(e, LAM, &bs_, f)
+            }
+            Expr::Prim(p) => write!(f, "{}", p.name()),
+            Expr::Str => self.token(Class::Type, "String", f),
+            Expr::StrLit(a) => self.token(Class::Literal, &quote_str(a), f),
+            Expr::Let(bs_1, e) => {
+                self.token(Class::Keyword, "let", f)?;
+                let mut bs_ = bs.to_owned();
+
+                for (k, (x, e_1)) in bs_1.iter().enumerate() {
+                    write!(f, "{}", if k == 0 { " " } else { "; " })?;
+                    self.token(Class::Binder, x, f)?;
+                    write!(f, " := ")?;
+                    self.write_expr(e_1, LAM, bs, f)?;
+                    bs_.push(x);
+                }
+
+                write!(f, " ")?;
+                self.token(Class::Keyword, "in", f)?;
+                write!(f, " ")?;
+                self.write_expr(e, LAM, &bs_, f)
+            }
+            Expr::LetRec(bs_1, e) => {
+                self.token(Class::Keyword, "let", f)?;
+                write!(f, " ")?;
+                self.token(Class::Keyword, "rec", f)?;
+                let mut bs_ = bs.to_owned();
+                bs_.extend(bs_1.iter().map(|(x, _, _)| x.as_str()));
+
+                for (k, (x, a, e_1)) in bs_1.iter().enumerate() {
+                    write!(f, "{}", if k == 0 { " " } else { "; " })?;
+                    self.token(Class::Binder, x, f)?;
+                    write!(f, " : ")?;
+                    self.write_expr(a, LAM, bs, f)?;
+                    write!(f, " := ")?;
+                    self.write_expr(e_1, LAM, &bs_, f)?;
+                }
+
+                write!(f, " ")?;
+                self.token(Class::Keyword, "in", f)?;
+                write!(f, " ")?;
+                self.write_expr(e, LAM, &bs_, f)
+            }
+            Expr::Sub(x, e_1, e_2) => {
+                self.token(Class::Keyword, "let", f)?;
+                write!(f, " ")?;
+                self.token(Class::Binder, x, f)?;
+                write!(f, " := ")?;
+                self.write_expr(e_1, LAM, bs, f)?;
+                write!(f, " ")?;
+                self.token(Class::Keyword, "in", f)?;
+                write!(f, " ")?;
+                let mut bs_ = bs.to_owned();
+                bs_.push(x);
+                self.write_expr(e_2, LAM, &bs_, f)
+            }
+            Expr::U(0) if self.types => self.token(Class::Universe, "Type", f),
+            Expr::U(i) if self.types => self.token(Class::Universe, &format!("Type {i}"), f),
+            Expr::U(i) => self.token(Class::Universe, &format!("U {i}"), f),
+            Expr::Var(x) => self.var(x, bs, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_expr, parse_program};
+
+    use super::*;
+
+    #[test]
+    fn printing_round_trips() {
+        let mut ns = Notations::new();
+        parse_program("infixl 6 _+_ := int_add\ninfixl 7 _*_ := int_mul", &mut ns).unwrap();
+
+        for src in [
+            "\\x y. (x + y) * 2 + -1",
+            "\\(x : Int) y (f : Int -> Int). f (x + y)",
+            "(Int -> Int) -> List Int -> String",
+            "forall (A : U 0) (B : U 0) -> A -> B -> List A",
+            "[Int -> String] -> Int -> String",
+            "let f := \\x. x in f (cons 1 nil)",
+            "ListRec xs 0 (\\h t r. h + r) * str_length \"a\\\"b\"",
+        ] {
+            let e = parse_expr(src, &mut ns).unwrap();
+            assert_eq!(e.pretty(&ns).to_string(), src);
+        }
+
+        let e = parse_expr("\\f x. f x -> x", &mut ns).unwrap();
+        assert_eq!(e.pretty(&ns).unicode(true).to_string(), "λf x. f x → x");
+    }
+
+    #[test]
+    fn unused_binders_are_left_out() {
+        let parse = |a| parse_expr(a, &mut Notations::new());
+        let e = parse("\\_ _. forall (A : U 0) (_ : A) (x : A) -> A").unwrap();
+        assert_eq!(e.to_string(), "\\_ _. forall (A : U 0) -> A -> A -> A");
+        assert_eq!(
+            parse("\\_. _").unwrap_err().message,
+            "`_` cannot be referred to"
+        );
+
+        let pi = Expr::Pi("x".into(), Box::new(Expr::Int), Box::new(Expr::Int));
+        assert_eq!(pi.to_string(), "Int -> Int");
+        let e = Expr::App(Box::new(Expr::Var("f".into())), Box::new(pi.to_owned()));
+        assert_eq!(e.to_string(), "f (Int -> Int)");
+
+        let v = pi.eval(&crate::Env::new());
+        assert!(matches!(v.quote(&Default::default()), Expr::Fun(_, _)));
+
+        let goals = crate::session::Checker::new()
+            .goals("\\_ x. ?h", "Int -> Int -> Int")
+            .unwrap();
+        assert_eq!(goals[0].to_string(), "x : Int |- ?h : Int");
+    }
+
+    #[test]
+    fn highlighting_works() {
+        let mut ns = Notations::new();
+        let e = parse_expr("\\x. cons x (f \"<\")", &mut ns).unwrap();
+
+        assert_eq!(
+            e.pretty(&ns).ansi(true).to_string(),
+            "\x1b[1;34m\\\x1b[0m\x1b[35mx\x1b[0m. \x1b[1;34mcons\x1b[0m x (f \x1b[32m\"<\"\x1b[0m)"
+        );
+
+        let links = |x: &str| (x == "f").then(|| "f".to_string());
+
+        assert_eq!(
+            e.pretty(&ns).html(&links).to_string(),
+            "<span class=\"keyword\">\\</span><span class=\"binder\">x</span>. <span class=\"keyword\">cons</span> x (<a href=\"#f\">f</a> <span class=\"literal\">&quot;&lt;&quot;</span>)"
+        );
+
+        let e = parse_expr("List Int -> U 1", &mut ns).unwrap();
+
+        assert_eq!(
+            e.pretty(&ns).ansi(true).to_string(),
+            "\x1b[36mList\x1b[0m \x1b[36mInt\x1b[0m \x1b[36m->\x1b[0m \x1b[1;36mU 1\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,705 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{Command as Process, Stdio},
+};
+
+use crate::{
+    conv::{unify, why_equal},
+    decl::{Decl, Def},
+    driver::{Diagnostic, Driver},
+    holes::{goals, holes, refine, split},
+    lexer::{is_ident, tokenize, Pos, Token},
+    options::Guard,
+    parser::{parse_expr, parse_program, Command, Item},
+    search::find_by_type,
+    Error, Expr,
+};
+
+// Lines entered in earlier sessions and this one, oldest first, kept in a
+// file with one line each.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    pub lines: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    // Starts from the lines saved in `path`, if any.
+    pub fn open(path: PathBuf) -> Self {
+        let lines = fs::read_to_string(&path)
+            .map(|a| a.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        Self {
+            lines,
+            path: Some(path),
+        }
+    }
+
+    // Blank lines and repetitions of the previous line are not recorded.
+    pub fn push(&mut self, line: &str) {
+        if !line.trim().is_empty() && self.lines.last().map(String::as_str) != Some(line) {
+            self.lines.push(line.to_owned());
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => fs::write(path, self.lines.join("\n") + "\n"),
+            None => Ok(()),
+        }
+    }
+}
+
+// Whether an input can be checked as it is, as opposed to ending inside a
+// string literal, with brackets left open or with a token that cannot end a
+// term, when it goes on on the next line.
+pub fn is_complete(src: &str) -> bool {
+    let mut ts = match tokenize(src) {
+        Ok(ts) => ts,
+        Err(e) => return e.message != "unterminated string literal",
+    };
+
+    ts.retain(|(t, _)| !matches!(t, Token::Comment(_)));
+
+    if let Some((
+        Token::Arrow
+        | Token::Backslash
+        | Token::Colon
+        | Token::ColonEq
+        | Token::Dot
+        | Token::Op(_)
+        | Token::Pi
+        | Token::Semicolon,
+        _,
+    )) = ts.last()
+    {
+        return false;
+    }
+
+    let mut depth = 0;
+
+    for (t, _) in ts {
+        match t {
+            Token::LBracket | Token::LParen => depth += 1,
+            Token::RBracket | Token::RParen => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth <= 0
+}
+
+// The identifiers in scope, local or global, that `prefix` may be completed
+// to, in order.
+pub fn completions(driver: &Driver, prefix: &str) -> Vec<String> {
+    let mut xs = driver
+        .cx
+        .keys()
+        .chain(driver.defs.keys())
+        .filter(|x| x.starts_with(prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    xs.sort();
+    xs.dedup();
+    xs
+}
+
+pub struct Repl {
+    pub driver: Driver,
+    pub history: History,
+    // The last definition entered with holes, kept aside to be completed.
+    pub pending: Option<Def>,
+    // The inputs whose declarations were checked, in order, which `:dump`
+    // saves as they were written.
+    pub session: Vec<String>,
+}
+
+impl Repl {
+    pub fn new(history: History) -> Self {
+        Self {
+            driver: Driver::new(),
+            history,
+            pending: None,
+            session: vec![],
+        }
+    }
+
+    // Checks the declarations and runs the commands of an input, returning
+    // their output. A bare expression is evaluated, and a definition with
+    // holes is not checked but kept pending, its goals being returned.
+    pub fn eval(&mut self, src: &str) -> Result<Vec<String>, Diagnostic> {
+        if let Some(c) = src.trim().strip_prefix(':') {
+            let at = |message| Diagnostic {
+                pos: Pos { line: 1, column: 1 },
+                message,
+            };
+
+            return self.command(c).map_err(at);
+        }
+
+        if let Ok(e) = parse_expr(src, &mut self.driver.ns.to_owned()) {
+            let g = Guard::new(self.driver.options.to_owned());
+
+            return self
+                .driver
+                .command(&Command::Eval(e), &g)
+                .map(|a| vec![a])
+                .map_err(|message| Diagnostic {
+                    pos: Pos { line: 1, column: 1 },
+                    message,
+                });
+        }
+
+        if let Ok(items) = parse_program(src, &mut self.driver.ns.to_owned()) {
+            if let [(pos, Item::Decl(Decl::Def(def)))] = items.as_slice() {
+                if !holes(&def.body).is_empty() {
+                    let at = |message| Diagnostic { pos: *pos, message };
+                    let def = Def {
+                        ty: def.ty.resolve(&self.driver.scope).map_err(at)?,
+                        body: def.body.resolve(&self.driver.scope).map_err(at)?,
+                        ..def.to_owned()
+                    };
+
+                    return self.pend(def).map_err(at);
+                }
+            }
+        }
+
+        let k = self.driver.out.len();
+        let result = self.driver.run(src);
+        let out = self.driver.out.drain(k..).map(|(_, a)| a).collect();
+        result?;
+        self.session.push(src.to_owned());
+        Ok(out)
+    }
+
+    // The goals of a definition with holes, which becomes the pending one.
+    fn pend(&mut self, def: Def) -> Result<Vec<String>, Error> {
+        let driver = &self.driver;
+        let g = Guard::new(driver.options.to_owned());
+        let t = def.ty.check_type(&driver.cx, &driver.d, &g)?;
+        let gs = goals(&def.body, &t, &driver.cx, &driver.d, &g)?;
+        self.pending = Some(def);
+        Ok(gs.iter().map(ToString::to_string).collect())
+    }
+
+    // `:dump file` saves the declarations of the session to a source file,
+    // a `.sd` one rather than an interface, as they were entered, and
+    // `:load file` checks those of a saved one again, as if they were
+    // entered here.
+    // `:browse` lists the global definitions with their types, and
+    // `:search t` those whose type matches `t` as `find_by_type` finds them,
+    // the names in `t` that are not in scope standing for any term.
+    // `:split h x` cases on `x` in the hole `h` of the pending definition,
+    // and `:refine h` introduces the arguments the hole is a function of.
+    // `:why-equal e_1, e_2` shows how conversion compares two terms, and
+    // `:unify e_1, e_2` solves the names not in scope in them so that they
+    // become equal, or tells why they cannot be.
+    fn command(&mut self, c: &str) -> Result<Vec<String>, Error> {
+        let (c, arg) = c.split_once(char::is_whitespace).unwrap_or((c, ""));
+
+        if c == "dump" {
+            let a = self.session.join("\n");
+            fs::write(arg.trim(), a).map_err(|_| "cannot write file")?;
+            return Ok(vec![]);
+        }
+
+        if c == "load" {
+            let src = fs::read_to_string(arg.trim()).map_err(|_| "cannot read file")?;
+            let k = self.driver.out.len();
+            let result = self.driver.run(&src);
+            let out = self.driver.out.drain(k..).map(|(_, a)| a).collect();
+            result.map_err(|e| e.message)?;
+            self.session.push(src);
+            return Ok(out);
+        }
+
+        if c == "why-equal" || c == "unify" {
+            let driver = &self.driver;
+            let g = Guard::new(driver.options.to_owned());
+            let (a, b) = arg.split_once(',').ok_or("expected two terms")?;
+
+            let parse = |a: &str| -> Result<Expr, Error> {
+                let e = parse_expr(a, &mut driver.ns.to_owned()).map_err(|e| e.message)?;
+                e.resolve(&driver.scope)
+            };
+
+            let (e_1, e_2) = (parse(a)?, parse(b)?);
+
+            if c == "why-equal" {
+                let trace = why_equal(&e_1, &e_2, &driver.d, &g)?;
+                return Ok(trace.to_string().lines().map(str::to_owned).collect());
+            }
+
+            let metas = &e_1.free_vars() | &e_2.free_vars();
+            let metas = metas.into_iter().filter(|x| !driver.cx.contains_key(x));
+
+            return match unify(&e_1, &e_2, &metas.collect(), &driver.d, &g)? {
+                Ok(sol) => {
+                    let mut out = sol
+                        .into_iter()
+                        .map(|(x, e)| format!("{x} := {}", e.pretty(&driver.ns)))
+                        .collect::<Vec<_>>();
+                    out.sort();
+                    Ok(out)
+                }
+                Err(m) => Ok(vec![m.to_string()]),
+            };
+        }
+
+        if c == "split" || c == "refine" {
+            let args = arg.split_whitespace().collect::<Vec<_>>();
+            let def = self.pending.take().ok_or("no definition with holes")?;
+            let driver = &self.driver;
+            let g = Guard::new(driver.options.to_owned());
+            let (cx, d) = (&driver.cx, &driver.d);
+            let hole = |h: &'_ str| h.strip_prefix('?').unwrap_or(h).to_owned();
+
+            let body = def
+                .ty
+                .check_type(cx, d, &g)
+                .and_then(|t| match (c, &args[..]) {
+                    ("split", &[h, x]) => split(&def.body, &t, &hole(h), x, cx, d, &g),
+                    ("split", _) => Err("expected a hole and a variable".into()),
+                    (_, &[h]) => refine(&def.body, &t, &hole(h), cx, d, &g),
+                    _ => Err("expected a hole".into()),
+                });
+
+            let body = match body {
+                Ok(body) => body,
+                Err(e) => {
+                    self.pending = Some(def);
+                    return Err(e);
+                }
+            };
+
+            let def = Def { body, ..def };
+            let a = format!("def {} : {} := {}", def.name, def.ty, def.body);
+            let mut out = self.pend(def)?;
+            out.insert(0, a);
+            return Ok(out);
+        }
+
+        let driver = &self.driver;
+        let g = Guard::new(driver.options.to_owned());
+        let xs = HashSet::new();
+
+        let names: Vec<_> = match c {
+            "browse" if arg.trim().is_empty() => driver.cx.keys().cloned().collect(),
+            "search" => {
+                let mut ns = driver.ns.to_owned();
+                let e = parse_expr(arg, &mut ns).map_err(|e| e.message)?;
+                let e = e.resolve(&driver.scope)?;
+                let metas = e.free_vars();
+                let metas = metas.into_iter().filter(|x| !driver.cx.contains_key(x));
+                let ms = find_by_type(&e, &metas.collect(), &driver.cx, &driver.d, &g)?;
+                ms.into_iter().map(|m| m.name).collect()
+            }
+            _ => return Err("unknown command".into()),
+        };
+
+        let mut out = vec![];
+
+        for x in names {
+            // Types are shown as declared, when the definition is known.
+            let t = match driver.defs.get(&x) {
+                Some(def) => def.ty.to_owned(),
+                None => driver.cx[&x].quote_with(&xs, &g)?,
+            };
+
+            out.push(format!("{x} : {}", t.pretty(&driver.ns).ansi(driver.ansi)));
+        }
+
+        Ok(out)
+    }
+
+    // Reads inputs until the end of the input or `:quit`, from a line editor
+    // when standard input is a terminal.
+    pub fn run(&mut self) -> io::Result<()> {
+        let tty = io::stdin().is_terminal();
+        let _raw = if tty { Some(RawMode::enable()?) } else { None };
+
+        loop {
+            let mut src = String::new();
+
+            loop {
+                let prompt = if src.is_empty() { "saida> " } else { "  ...> " };
+
+                let line = if tty {
+                    self.edit(prompt)?
+                } else {
+                    let mut line = String::new();
+
+                    if io::stdin().read_line(&mut line)? == 0 {
+                        None
+                    } else {
+                        Some(line.trim_end_matches('\n').to_owned())
+                    }
+                };
+
+                let Some(line) = line else {
+                    return self.history.save();
+                };
+
+                self.history.push(&line);
+                src.push_str(&line);
+                src.push('\n');
+
+                if is_complete(&src) {
+                    break;
+                }
+            }
+
+            let mut out = io::stdout();
+
+            match src.trim() {
+                "" => continue,
+                ":q" | ":quit" => return self.history.save(),
+                _ => match self.eval(&src) {
+                    Ok(lines) => {
+                        for a in lines {
+                            writeln!(out, "{a}")?;
+                        }
+                    }
+                    Err(e) => {
+                        writeln!(out, "{}:{}: error: {}", e.pos.line, e.pos.column, e.message)?
+                    }
+                },
+            }
+        }
+    }
+
+    // Reads a line from the terminal in raw mode, with the arrow keys moving
+    // through it and the history and tab completing identifiers. Returns
+    // `None` when ctrl-d is pressed on an empty line.
+    fn edit(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let mut out = io::stdout();
+        let mut keys = io::stdin().lock().bytes();
+        let mut next = || keys.next().transpose();
+        let mut line = Vec::<char>::new();
+        let mut cursor = 0;
+        let mut k = self.history.lines.len();
+        let mut utf8 = vec![];
+
+        loop {
+            let rest = line.len() - cursor;
+            let line_ = line.iter().collect::<String>();
+            write!(out, "\r\x1b[K{prompt}{line_}")?;
+
+            if rest > 0 {
+                write!(out, "\x1b[{rest}D")?;
+            }
+
+            out.flush()?;
+
+            let Some(c) = next()? else {
+                return Ok(None);
+            };
+
+            match c {
+                b'\r' | b'\n' => {
+                    writeln!(out, "\r")?;
+                    return Ok(Some(line_));
+                }
+                4 if line.is_empty() => {
+                    writeln!(out, "\r")?;
+                    return Ok(None);
+                }
+                3 => {
+                    writeln!(out, "\r")?;
+                    return Ok(Some(String::new()));
+                }
+                8 | 127 if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                b'\t' => {
+                    let start = line[..cursor]
+                        .iter()
+                        .rposition(|&c| !(is_ident(c) || c == '.'))
+                        .map_or(0, |i| i + 1);
+
+                    let prefix = line[start..cursor].iter().collect::<String>();
+                    let xs = completions(&self.driver, &prefix);
+
+                    // Completes as far as the candidates agree, listing them
+                    // when that does not extend the prefix.
+                    let common = xs.iter().fold(None::<&str>, |a, x| match a {
+                        None => Some(x),
+                        Some(a) => {
+                            let n = a.chars().zip(x.chars()).take_while(|(c, d)| c == d);
+                            Some(&a[..n.map(|(c, _)| c.len_utf8()).sum()])
+                        }
+                    });
+
+                    match common {
+                        Some(a) if a.len() > prefix.len() => {
+                            for c in a[prefix.len()..].chars() {
+                                line.insert(cursor, c);
+                                cursor += 1;
+                            }
+                        }
+                        Some(_) if xs.len() > 1 => writeln!(out, "\r\n{}\r", xs.join("  "))?,
+                        _ => {}
+                    }
+                }
+                0x1b => {
+                    if next()? != Some(b'[') {
+                        continue;
+                    }
+
+                    match next()? {
+                        Some(b'A') if k > 0 => {
+                            k -= 1;
+                            line = self.history.lines[k].chars().collect();
+                            cursor = line.len();
+                        }
+                        Some(b'B') if k < self.history.lines.len() => {
+                            k += 1;
+                            line = self
+                                .history
+                                .lines
+                                .get(k)
+                                .map_or(vec![], |a| a.chars().collect());
+                            cursor = line.len();
+                        }
+                        Some(b'C') if cursor < line.len() => cursor += 1,
+                        Some(b'D') if cursor > 0 => cursor -= 1,
+                        _ => {}
+                    }
+                }
+                c if c >= 0x20 => {
+                    utf8.push(c);
+
+                    if let Ok(a) = std::str::from_utf8(&utf8) {
+                        for c in a.chars() {
+                            line.insert(cursor, c);
+                            cursor += 1;
+                        }
+
+                        utf8.clear();
+                    } else if utf8.len() >= 4 {
+                        utf8.clear();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Puts the terminal in raw mode with `stty`, restoring its settings when
+// dropped.
+struct RawMode(String);
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Ok(Self(saved.trim().to_owned()))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.0]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let out = Process::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty")?)
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !out.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_continue_until_complete() {
+        assert!(is_complete("def one : Int := 1"));
+        assert!(!is_complete("def one : Int :="));
+        assert!(!is_complete("def f : Int -> Int := \\x. int_add (x"));
+        assert!(is_complete("def f : Int -> Int := \\x. int_add (x\n  1)"));
+        assert!(!is_complete("#eval \"a (\n"));
+        assert!(is_complete("#eval \"a (\nb\""));
+
+        let mut repl = Repl::new(History::default());
+        repl.eval("def double : Int -> Int := \\x. int_mul x 2")
+            .unwrap();
+        assert_eq!(repl.eval("double 21").unwrap(), vec!["42".to_string()]);
+        assert_eq!(
+            repl.eval("#check double").unwrap(),
+            vec!["double : Int -> Int".to_string()]
+        );
+        assert_eq!(repl.eval("dub").unwrap_err().message, "unknown identifier");
+        assert_eq!(completions(&repl.driver, "dou"), vec!["double".to_string()]);
+
+        let path = std::env::temp_dir().join(format!("saida-history-{}", std::process::id()));
+        let mut history = History::open(path.to_owned());
+        history.push("double 1");
+        history.push("double 1");
+        history.push(" ");
+        history.save().unwrap();
+        assert_eq!(
+            History::open(path.to_owned()).lines,
+            vec!["double 1".to_string()]
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sessions_are_dumped_and_loaded() {
+        let mut repl = Repl::new(History::default());
+        let src = "infixl 6 _+_ := int_add
+private def one : Int := 1
+def double : Int -> Int := \\x. x + x
+opaque def secret : Int := 7
+def show : Int -> String := int_show
+coercion show";
+        repl.eval(src).unwrap();
+        repl.eval("def seven : Int := secret").unwrap();
+
+        let path = std::env::temp_dir().join(format!("saida-dump-{}.sd", std::process::id()));
+        let path_ = path.display();
+        repl.eval(&format!(":dump {path_}")).unwrap();
+
+        let mut repl = Repl::new(History::default());
+        repl.eval(&format!(":load {path_}")).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(repl.eval("double (1 + 2)").unwrap(), vec!["6"]);
+        assert_eq!(
+            repl.eval("str_append \"n = \" 1").unwrap(),
+            vec!["\"n = 1\""]
+        );
+        assert_eq!(repl.eval("one").unwrap(), vec!["1"]);
+        assert_eq!(repl.eval("seven").unwrap(), vec!["7"]);
+
+        repl.eval(&format!(":dump {path_}")).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("private def one"));
+
+        // A dump is checked again, so an edited one cannot bring in
+        // ill-typed definitions.
+        fs::write(&path, "def bad : Int := \"one\"").unwrap();
+        let mut repl = Repl::new(History::default());
+        assert_eq!(
+            repl.eval(&format!(":load {path_}")).unwrap_err().message,
+            "string literal checked against a non-string type"
+        );
+        assert_eq!(repl.eval("bad").unwrap_err().message, "unknown identifier");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            repl.eval(":load /nonexistent/session.sd")
+                .unwrap_err()
+                .message,
+            "cannot read file"
+        );
+    }
+
+    #[test]
+    fn definitions_are_browsed_and_searched() {
+        let mut repl = Repl::new(History::default());
+        let src = "def N : U 0 := Int
+def double : Int -> Int := \\x. int_mul x 2
+def show : N -> String := int_show
+def twice : (Int -> Int) -> Int -> Int := \\f x. f (f x)";
+        repl.eval(src).unwrap();
+
+        assert_eq!(
+            repl.eval(":browse").unwrap(),
+            vec![
+                "N : U 0",
+                "double : Int -> Int",
+                "show : N -> String",
+                "twice : (Int -> Int) -> Int -> Int"
+            ]
+        );
+        assert_eq!(
+            repl.eval(":search Int -> Int").unwrap(),
+            vec!["double : Int -> Int"]
+        );
+        assert_eq!(
+            repl.eval(":search A -> A").unwrap(),
+            vec!["double : Int -> Int", "twice : (Int -> Int) -> Int -> Int"]
+        );
+        assert_eq!(repl.eval(":search Int -> B").unwrap().len(), 2);
+        assert_eq!(
+            repl.eval(":search N -> String").unwrap(),
+            vec!["show : N -> String"]
+        );
+        assert_eq!(
+            repl.eval(":frobnicate").unwrap_err().message,
+            "unknown command"
+        );
+        assert_eq!(
+            repl.eval(":why-equal double 2, 4").unwrap(),
+            vec![
+                "different after 0 beta steps:",
+                "  at the root: double 2 ~> 4",
+                "unfolding double",
+                "equal after 1 beta steps",
+                "equal"
+            ]
+        );
+        assert_eq!(
+            repl.eval(":unify A -> double 1, Int -> 2").unwrap(),
+            vec!["A := Int"]
+        );
+        assert_eq!(
+            repl.eval(":unify A -> A, Int -> String").unwrap(),
+            vec!["conflict: A is both Int and String"]
+        );
+    }
+
+    #[test]
+    fn pending_definitions_are_split() {
+        let mut repl = Repl::new(History::default());
+        let src = "def sum : List Int -> Int := \\xs. ?h";
+        assert_eq!(repl.eval(src).unwrap(), vec!["xs : List Int |- ?h : Int"]);
+        assert!(repl.driver.defs.is_empty());
+
+        assert_eq!(
+            repl.eval(":split ?h xs").unwrap(),
+            vec![
+                "def sum : List Int -> Int := \\xs. ListRec xs ?h_nil (\\y ys r. ?h_cons)",
+                "xs : List Int |- ?h_nil : Int",
+                "xs : List Int, y : Int, ys : List Int, r : Int |- ?h_cons : Int"
+            ]
+        );
+        assert_eq!(
+            repl.eval(":split h xs").unwrap_err().message,
+            "unknown hole"
+        );
+        assert!(repl.pending.is_some());
+
+        let src = "def add : Int -> Int -> Int := ?h";
+        assert_eq!(repl.eval(src).unwrap(), vec!["|- ?h : Int -> Int -> Int"]);
+        assert_eq!(
+            repl.eval(":refine h").unwrap(),
+            vec![
+                "def add : Int -> Int -> Int := \\x x'. ?h",
+                "x : Int, x' : Int |- ?h : Int"
+            ]
+        );
+        assert_eq!(
+            repl.eval(":refine h").unwrap_err().message,
+            "nothing to refine"
+        );
+    }
+}
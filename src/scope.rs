@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Error, Expr, Identifier};
+
+// The candidates for every ambiguous name, which the elaborator tells apart
+// by the type a reference is checked against.
+pub type Overloads = BTreeMap<Identifier, Vec<Identifier>>;
+
+// The names visible in a module, each standing for the fully qualified names
+// of the global definitions it may refer to.
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    names: HashMap<Identifier, Vec<Identifier>>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Definitions of the module itself shadow everything else.
+    pub fn define(&mut self, x: Identifier, y: Identifier) {
+        self.names.insert(x, vec![y]);
+    }
+
+    // Names brought in by imports and `open` are ambiguous where they clash.
+    pub fn add(&mut self, x: Identifier, y: Identifier) {
+        let ys = self.names.entry(x).or_default();
+
+        if !ys.contains(&y) {
+            ys.push(y);
+        }
+    }
+
+    pub fn resolve(&self, x: &str) -> Result<Option<&Identifier>, Error> {
+        match self.names.get(x).map(Vec::as_slice) {
+            None | Some([]) => Ok(None),
+            Some([y]) => Ok(Some(y)),
+            Some(_) => Err("ambiguous name".into()),
+        }
+    }
+
+    pub fn overloads(&self) -> Overloads {
+        self.names
+            .iter()
+            .filter(|(_, ys)| ys.len() > 1)
+            .map(|(x, ys)| (x.to_owned(), ys.to_owned()))
+            .collect()
+    }
+}
+
+impl Expr {
+    // Replaces every free variable in scope by the qualified name it stands
+    // for, leaving the others to be reported when checking.
+    pub fn resolve(&self, scope: &Scope) -> Result<Expr, Error> {
+        self.resolve_in(scope, false, &mut vec![])
+    }
+
+    // Resolves the term like `resolve` does, but leaves the ambiguous names
+    // for the elaborator to tell apart.
+    pub fn resolve_overloaded(&self, scope: &Scope) -> Result<Expr, Error> {
+        self.resolve_in(scope, true, &mut vec![])
+    }
+
+    fn resolve_in<'a>(
+        &'a self,
+        scope: &Scope,
+        overloaded: bool,
+        bs: &mut Vec<&'a str>,
+    ) -> Result<Expr, Error> {
+        let go =
+            |e: &'a Expr, bs: &mut Vec<&'a str>| e.resolve_in(scope, overloaded, bs).map(Box::new);
+
+        Ok(match self {
+            Self::App(e_1, e_2) => Self::App(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Cons(e_1, e_2) => Self::Cons(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Fun(e_1, e_2) => Self::Fun(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Inst(e_1, e_2) => Self::Inst(go(e_1, bs)?, go(e_2, bs)?),
+            Self::Lam(x, a, e) => {
+                let a_ = a.as_deref().map(|a| go(a, bs)).transpose()?;
+                bs.push(x);
+                let e_ = go(e, bs)?;
+                bs.pop();
+                Self::Lam(x.to_owned(), a_, e_)
+            }
+            Self::Let(bs_, e) => {
+                let bs_1 = bs_
+                    .iter()
+                    .map(|(x, e)| Ok((x.to_owned(), *go(e, bs)?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                bs.extend(bs_.iter().map(|(x, _)| x.as_str()));
+                let e_ = go(e, bs)?;
+                bs.truncate(bs.len() - bs_.len());
+                Self::Let(bs_1, e_)
+            }
+            Self::LetRec(bs_, e) => {
+                let ts = bs_
+                    .iter()
+                    .map(|(_, a, _)| go(a, bs))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                bs.extend(bs_.iter().map(|(x, _, _)| x.as_str()));
+                let bs_1 = bs_
+                    .iter()
+                    .zip(ts)
+                    .map(|((x, _, e), a)| Ok((x.to_owned(), *a, *go(e, bs)?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let e_ = go(e, bs)?;
+                bs.truncate(bs.len() - bs_.len());
+                Self::LetRec(bs_1, e_)
+            }
+            Self::List(e) => Self::List(go(e, bs)?),
+            Self::ListRec(e_1, e_2, e_3) => Self::ListRec(go(e_1, bs)?, go(e_2, bs)?, go(e_3, bs)?),
+            Self::Quote(e) => Self::Quote(go(e, bs)?),
+            Self::Splice(e) => Self::Splice(go(e, bs)?),
+            Self::Pi(x, e_1, e_2) => {
+                let e_1_ = go(e_1, bs)?;
+                bs.push(x);
+                let e_2_ = go(e_2, bs)?;
+                bs.pop();
+                Self::Pi(x.to_owned(), e_1_, e_2_)
+            }
+            Self::Sub(x, e_1, e_2) => {
+                let e_1_ = go(e_1, bs)?;
+                bs.push(x);
+                let e_2_ = go(e_2, bs)?;
+                bs.pop();
+                Self::Sub(x.to_owned(), e_1_, e_2_)
+            }
+            Self::Var(x) if !bs.contains(&x.as_str()) => match scope.resolve(x) {
+                Ok(Some(y)) => Self::Var(y.to_owned()),
+                Ok(None) => self.to_owned(),
+                Err(_) if overloaded => self.to_owned(),
+                Err(e) => return Err(e),
+            },
+            e => e.to_owned(),
+        })
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    conv::unify, freshen, options::Guard, Context, Env, Error, Expr, Identifier, Neutral, Value,
+};
+
+// A global definition whose type matches a goal, with the terms its type and
+// the goal were instantiated with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub name: Identifier,
+    pub ty: Expr,
+    pub solution: HashMap<Identifier, Expr>,
+}
+
+// The global definitions of `cx` whose types match `goal` up to conversion,
+// in order of their names. The variables of `metas` in the goal stand for
+// any term, and so do the binders of the leading dependent function types of
+// a definition. Instance arguments are left to the elaborator, so the types
+// after them match as well.
+pub fn find_by_type(
+    goal: &Expr,
+    metas: &HashSet<Identifier>,
+    cx: &Context,
+    d: &Env,
+    g: &Guard,
+) -> Result<Vec<Match>, Error> {
+    let mut ms = vec![];
+
+    for x in cx.keys() {
+        let mut metas_ = metas.to_owned();
+        let mut t = cx[x].to_owned();
+
+        loop {
+            t = match t.unfolded(g)? {
+                Value::Inst(_, t_2) => t_2.as_ref().to_owned(),
+                Value::Pi(_, c) => {
+                    let xs = cx.keys().chain(&metas_).map(String::as_str).collect();
+                    let y = freshen(c.x.as_str().to_owned(), &xs);
+                    metas_.insert(y.to_owned());
+                    c.apply_with(Value::Neutral(Neutral::Var(y)), g)?
+                }
+                _ => break,
+            };
+        }
+
+        let xs = cx.keys().chain(&metas_).map(String::as_str).collect();
+        let t = t.quote_with(&xs, g)?;
+
+        if let Ok(solution) = unify(goal, &t, &metas_, d, g)? {
+            ms.push(Match {
+                name: x.to_owned(),
+                ty: t,
+                solution,
+            });
+        }
+    }
+
+    Ok(ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::Driver, notation::Notations, parser::parse_expr};
+
+    #[test]
+    fn types_are_instantiated() {
+        let src = "def id : forall (A : U 0) -> A -> A := \\A x. x
+def show : [Int] -> Int -> String := \\i n. int_show n
+def length : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. int_add r 1)";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let find = |a: &str, metas: &[&str]| {
+            let metas = metas.iter().map(|x| x.to_string()).collect();
+            let g = Guard::default();
+            find_by_type(&parse(a), &metas, &driver.cx, &driver.d, &g).unwrap()
+        };
+
+        let ms = find("Int -> Int", &[]);
+        assert_eq!(ms.len(), 1);
+        assert_eq!(ms[0].name, "id");
+        assert_eq!(ms[0].solution["A"], Expr::Int);
+
+        let names = |ms: Vec<Match>| ms.into_iter().map(|m| m.name).collect::<Vec<_>>();
+        assert_eq!(names(find("Int -> String", &[])), vec!["show"]);
+        assert_eq!(names(find("List B -> Int", &["B"])), vec!["length"]);
+        assert_eq!(names(find("String -> Int", &[])), Vec::<String>::new());
+    }
+}
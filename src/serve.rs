@@ -0,0 +1,462 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, Write},
+    iter::Peekable,
+    mem,
+    str::Chars,
+};
+
+use crate::{
+    decl::Decl,
+    driver::{Diagnostic, Driver},
+    holes::Goal,
+    lexer::Pos,
+    options::CheckOptions,
+    session::Checker,
+    store::Hash,
+};
+
+// The JSON values requests and responses are made of. Objects keep their
+// keys in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(a: &str) -> Option<Self> {
+        let mut cs = a.chars().peekable();
+        let v = value(&mut cs)?;
+        skip_whitespace(&mut cs);
+        cs.peek().is_none().then_some(v)
+    }
+
+    pub fn get(&self, k: &str) -> Option<&Self> {
+        match self {
+            Self::Object(kvs) => kvs.iter().find(|(k_, _)| k_ == k).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(a: &str) -> Self {
+        Self::String(a.to_owned())
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Self::Number(n as f64)
+    }
+}
+
+fn object<const N: usize>(kvs: [(&str, Json); N]) -> Json {
+    Json::Object(kvs.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
+
+fn skip_whitespace(cs: &mut Peekable<Chars>) {
+    while cs.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn value(cs: &mut Peekable<Chars>) -> Option<Json> {
+    skip_whitespace(cs);
+
+    let word =
+        |cs: &mut Peekable<Chars>, w: &str, v| w.chars().all(|c| cs.next() == Some(c)).then_some(v);
+
+    match *cs.peek()? {
+        'n' => word(cs, "null", Json::Null),
+        't' => word(cs, "true", Json::Bool(true)),
+        'f' => word(cs, "false", Json::Bool(false)),
+        '"' => string(cs).map(Json::String),
+        '[' => {
+            cs.next();
+            let mut vs = vec![];
+            skip_whitespace(cs);
+
+            if cs.next_if_eq(&']').is_some() {
+                return Some(Json::Array(vs));
+            }
+
+            loop {
+                vs.push(value(cs)?);
+                skip_whitespace(cs);
+
+                match cs.next()? {
+                    ',' => {}
+                    ']' => return Some(Json::Array(vs)),
+                    _ => return None,
+                }
+            }
+        }
+        '{' => {
+            cs.next();
+            let mut kvs = vec![];
+            skip_whitespace(cs);
+
+            if cs.next_if_eq(&'}').is_some() {
+                return Some(Json::Object(kvs));
+            }
+
+            loop {
+                skip_whitespace(cs);
+                let k = string(cs)?;
+                skip_whitespace(cs);
+                cs.next_if_eq(&':')?;
+                kvs.push((k, value(cs)?));
+                skip_whitespace(cs);
+
+                match cs.next()? {
+                    ',' => {}
+                    '}' => return Some(Json::Object(kvs)),
+                    _ => return None,
+                }
+            }
+        }
+        _ => {
+            let mut a = String::new();
+
+            while let Some(c) = cs.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                a.push(c);
+            }
+
+            a.parse().ok().map(Json::Number)
+        }
+    }
+}
+
+fn string(cs: &mut Peekable<Chars>) -> Option<String> {
+    cs.next_if_eq(&'"')?;
+    let mut a = String::new();
+
+    loop {
+        match cs.next()? {
+            '"' => return Some(a),
+            '\\' => a.push(match cs.next()? {
+                'b' => '\x08',
+                'f' => '\x0c',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let n = hex(cs)?;
+
+                    // Characters outside the basic plane come as a pair of
+                    // surrogates.
+                    if (0xd800..0xdc00).contains(&n) {
+                        cs.next_if_eq(&'\\')?;
+                        cs.next_if_eq(&'u')?;
+                        let m = hex(cs)?;
+                        char::from_u32(0x10000 + ((n - 0xd800) << 10) + m.checked_sub(0xdc00)?)?
+                    } else {
+                        char::from_u32(n)?
+                    }
+                }
+                c @ ('"' | '\\' | '/') => c,
+                _ => return None,
+            }),
+            c if c < ' ' => return None,
+            c => a.push(c),
+        }
+    }
+}
+
+fn hex(cs: &mut Peekable<Chars>) -> Option<u32> {
+    (0..4).try_fold(0, |n, _| Some(n * 16 + cs.next()?.to_digit(16)?))
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, a: &str) -> fmt::Result {
+    write!(f, "\"")?;
+
+    for c in a.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+// On a single line, so that a response is a line of output.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(a) => write_string(f, a),
+            Self::Array(vs) => {
+                write!(f, "[")?;
+
+                for (k, v) in vs.iter().enumerate() {
+                    let sep = if k > 0 { "," } else { "" };
+                    write!(f, "{sep}{v}")?;
+                }
+
+                write!(f, "]")
+            }
+            Self::Object(kvs) => {
+                write!(f, "{{")?;
+
+                for (k, (k_, v)) in kvs.iter().enumerate() {
+                    if k > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write_string(f, k_)?;
+                    write!(f, ":{v}")?;
+                }
+
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// A JSON-RPC error: its code and message.
+type Failure = (i32, String);
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+// A term that does not check.
+const CHECK_ERROR: i32 = 1;
+
+fn at(pos: Pos) -> [(&'static str, Json); 2] {
+    [("line", pos.line.into()), ("column", pos.column.into())]
+}
+
+// A long-running checker answering JSON-RPC requests, one per line, so that
+// front-ends do not pay for starting a process each time. The methods are
+//
+// - `check {source}`, which checks a source file and makes its definitions
+//   the environment of the next requests, returning the output of its
+//   commands, its warnings and its errors;
+// - `infer {term}` and `normalize {term}`, which return the type and the
+//   normal form of a term;
+// - `goals {term, type}`, which returns the goals of the holes of a term.
+//
+// Declarations that did not change since a previous `check` are not checked
+// again.
+#[derive(Default)]
+pub struct Server {
+    checker: Checker,
+    cache: HashMap<Hash, (Decl, Decl)>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: CheckOptions) -> Self {
+        Self {
+            checker: Checker::with_options(options),
+            ..Self::default()
+        }
+    }
+
+    // Answers the requests of `input` on `output` until the end of the
+    // input. Notifications, which have no id, get no response.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{response}")?;
+                output.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // The response to a request.
+    pub fn handle(&mut self, line: &str) -> Option<Json> {
+        let Some(request) = Json::parse(line) else {
+            return Some(response(
+                Json::Null,
+                Err((PARSE_ERROR, "parse error".to_owned())),
+            ));
+        };
+
+        let id = request.get("id").cloned();
+        let result = match request.get("method").and_then(Json::as_str) {
+            Some(m) => self.call(m, request.get("params").unwrap_or(&Json::Null)),
+            None => Err((INVALID_REQUEST, "invalid request".to_owned())),
+        };
+
+        id.map(|id| response(id, result))
+    }
+
+    fn call(&mut self, method: &str, params: &Json) -> Result<Json, Failure> {
+        let param = |k| {
+            params
+                .get(k)
+                .and_then(Json::as_str)
+                .ok_or((INVALID_PARAMS, format!("expected a string {k}")))
+        };
+
+        match method {
+            "check" => Ok(self.check(param("source")?)),
+            "infer" => Ok(self
+                .checker
+                .infer(param("term")?)
+                .map_err(failure)?
+                .to_string()
+                .as_str()
+                .into()),
+            "normalize" => Ok(self
+                .checker
+                .normalize(param("term")?)
+                .map_err(failure)?
+                .to_string()
+                .as_str()
+                .into()),
+            "goals" => {
+                let gs = self
+                    .checker
+                    .goals(param("term")?, param("type")?)
+                    .map_err(failure)?;
+                Ok(Json::Array(gs.iter().map(goal).collect()))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {method}"))),
+        }
+    }
+
+    fn check(&mut self, src: &str) -> Json {
+        let mut driver = Driver::new();
+        driver.options = self.checker.driver().options.to_owned();
+        driver.cache = mem::take(&mut self.cache);
+        let es = driver.check(src);
+
+        let output = driver.out.iter().map(|(pos, text)| {
+            let [l, c] = at(*pos);
+            object([l, c, ("text", text.as_str().into())])
+        });
+
+        let warnings = driver.warnings.iter().map(|w| {
+            let [l, c] = at(w.pos);
+            object([
+                l,
+                c,
+                ("lint", w.lint.name().into()),
+                ("message", w.message.as_str().into()),
+            ])
+        });
+
+        let errors = es.iter().map(|e| {
+            let [l, c] = at(e.pos);
+            object([l, c, ("message", e.message.message().into())])
+        });
+
+        let result = object([
+            ("output", Json::Array(output.collect())),
+            ("warnings", Json::Array(warnings.collect())),
+            ("errors", Json::Array(errors.collect())),
+            ("reused", driver.reused.into()),
+        ]);
+
+        self.cache = mem::take(&mut driver.cache);
+        self.checker = driver.into();
+        result
+    }
+}
+
+fn failure(e: Diagnostic) -> Failure {
+    (CHECK_ERROR, e.message.message().to_owned())
+}
+
+fn goal(g: &Goal) -> Json {
+    let context = g.context.iter().map(|(x, t)| {
+        object([
+            ("name", x.as_str().into()),
+            ("type", t.to_string().as_str().into()),
+        ])
+    });
+
+    object([
+        ("name", g.name.as_str().into()),
+        ("type", g.ty.to_string().as_str().into()),
+        ("context", Json::Array(context.collect())),
+    ])
+}
+
+fn response(id: Json, result: Result<Json, Failure>) -> Json {
+    let outcome = match result {
+        Ok(v) => ("result", v),
+        Err((code, message)) => (
+            "error",
+            object([
+                ("code", Json::Number(code.into())),
+                ("message", message.as_str().into()),
+            ]),
+        ),
+    };
+
+    object([("jsonrpc", "2.0".into()), ("id", id), outcome])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn servers_answer_json_rpc_requests() {
+        let mut server = Server::new();
+        let mut ask = |a: &str| server.handle(a).map(|v| v.to_string());
+
+        let check = r#"{"jsonrpc":"2.0","id":1,"method":"check","params":{"source":"def one : Int := 1\n#eval int_add one one\ndef two : Int := int_show 2"}}"#;
+        assert_eq!(
+            ask(check).unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"output":[{"line":2,"column":1,"text":"2"}],"warnings":[],"errors":[{"line":3,"column":1,"message":"type mismatch"}],"reused":0}}"#
+        );
+        assert!(ask(check).unwrap().ends_with(r#""reused":1}}"#));
+
+        assert_eq!(
+            ask(r#"{"jsonrpc":"2.0","id":"a","method":"normalize","params":{"term":"int_add one 2"}}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":"a","result":"3"}"#
+        );
+        assert_eq!(
+            ask(r#"{"jsonrpc":"2.0","id":2,"method":"goals","params":{"term":"\\x. ?h","type":"Int -> Int"}}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":2,"result":[{"name":"h","type":"Int","context":[{"name":"x","type":"Int"}]}]}"#
+        );
+        assert_eq!(
+            ask(r#"{"jsonrpc":"2.0","id":3,"method":"infer","params":{"term":"two"}}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":3,"error":{"code":1,"message":"unknown identifier"}}"#
+        );
+        assert_eq!(
+            ask(r#"{"jsonrpc":"2.0","method":"infer","params":{"term":"one"}}"#),
+            None
+        );
+        assert_eq!(
+            ask("{").unwrap(),
+            r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"parse error"}}"#
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    decl::{Decl, Def},
+    driver::{Diagnostic, Driver},
+    elab,
+    holes::{goals, Goal},
+    lexer::Pos,
+    options::{CheckOptions, Guard},
+    parser::{parse_expr, Command, Item},
+    CheckedExpr, Error, Expr, Identifier,
+};
+
+// Everything checking a program needs, for applications embedding saida.
+// Sources are given as text, and errors come back with their location in
+// it. The environment is shared between a checker and its clones and
+// snapshots until one of them adds to it, so a checked prelude can be
+// queried from several threads at once without being copied.
+#[derive(Clone, Default)]
+pub struct Checker {
+    driver: Arc<Driver>,
+}
+
+impl From<Driver> for Checker {
+    fn from(driver: Driver) -> Self {
+        Self {
+            driver: Arc::new(driver),
+        }
+    }
+}
+
+// The state of a checker at some point, to go back to.
+#[derive(Clone)]
+pub struct Snapshot(Arc<Driver>);
+
+impl Checker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: CheckOptions) -> Self {
+        let mut driver = Driver::new();
+        driver.options = options;
+
+        Self {
+            driver: Arc::new(driver),
+        }
+    }
+
+    pub fn driver(&self) -> &Driver {
+        &self.driver
+    }
+
+    pub fn options_mut(&mut self) -> &mut CheckOptions {
+        &mut Arc::make_mut(&mut self.driver).options
+    }
+
+    // Checks the declarations of a source file and brings them into scope,
+    // returning the output of its commands. Nothing is added unless they
+    // all check.
+    pub fn add_declaration(&mut self, src: &str) -> Result<Vec<String>, Diagnostic> {
+        self.add(|driver| driver.run(src))
+    }
+
+    // Checks the declarations of a program built in Rust and brings them
+    // into scope, like `add_declaration`.
+    pub fn add_program(&mut self, p: ProgramBuilder) -> Result<Vec<String>, Diagnostic> {
+        self.add(|driver| driver.run_items(p.items))
+    }
+
+    fn add(
+        &mut self,
+        run: impl FnOnce(&mut Driver) -> Result<(), Diagnostic>,
+    ) -> Result<Vec<String>, Diagnostic> {
+        let s = self.snapshot();
+        let driver = Arc::make_mut(&mut self.driver);
+        let k = driver.out.len();
+
+        match run(driver) {
+            Ok(()) => Ok(driver.out.drain(k..).map(|(_, a)| a).collect()),
+            Err(e) => {
+                self.restore(s);
+                Err(e)
+            }
+        }
+    }
+
+    // The type of a term.
+    pub fn infer(&self, src: &str) -> Result<Expr, Diagnostic> {
+        let g = Guard::new(self.driver.options.to_owned());
+        let e = self.elaborate(src, &g)?;
+        at_start(e.ty().quote_with(&self.names(), &g))
+    }
+
+    // The normal form of a term.
+    pub fn normalize(&self, src: &str) -> Result<Expr, Diagnostic> {
+        let g = Guard::new(self.driver.options.to_owned());
+        let e = self.elaborate(src, &g)?;
+        let e = at_start(e.term().eval_open(&self.driver.cx, &self.driver.d, &g))?;
+        at_start(e.quote_with(&self.names(), &g))
+    }
+
+    // The goals of the holes of a term checked against a type.
+    pub fn goals(&self, src: &str, ty: &str) -> Result<Vec<Goal>, Diagnostic> {
+        let driver = &self.driver;
+        let g = Guard::new(driver.options.to_owned());
+
+        let parse = |a| -> Result<Expr, Diagnostic> {
+            let e = parse_expr(a, &mut driver.ns.to_owned())?;
+            let e = at_start(e.expand(&driver.macros))?;
+            at_start(e.resolve(&driver.scope))
+        };
+
+        let (e, t) = (parse(src)?, parse(ty)?);
+        let t = at_start(t.check_type(&driver.cx, &driver.d, &g))?;
+        at_start(goals(&e, &t, &driver.cx, &driver.d, &g))
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(Arc::clone(&self.driver))
+    }
+
+    pub fn restore(&mut self, s: Snapshot) {
+        self.driver = s.0;
+    }
+
+    fn names(&self) -> HashSet<&str> {
+        self.driver.cx.keys().map(String::as_str).collect()
+    }
+
+    fn elaborate(&self, src: &str, g: &Guard) -> Result<CheckedExpr, Diagnostic> {
+        let driver = &self.driver;
+        let e = parse_expr(src, &mut driver.ns.to_owned())?;
+        let e = at_start(e.expand(&driver.macros))?;
+        let e = at_start(e.resolve(&driver.scope))?;
+        at_start(elab::elaborate_infer(
+            &e,
+            &driver.cx,
+            &driver.d,
+            &driver.hints,
+            g,
+        ))
+    }
+}
+
+// A program given as declarations rather than text, for Rust programs that
+// generate them. Terms are written as they would be parsed, so names are
+// resolved and macros expanded when the program is checked. An error is
+// reported at the line of the position of its declaration in the program,
+// counting from 1.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramBuilder {
+    items: Vec<(Pos, Item)>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn def(self, name: impl Into<Identifier>, ty: Expr, body: Expr) -> Self {
+        self.decl(Decl::Def(def(name.into(), ty, body)))
+    }
+
+    // A definition whose body stays hidden from importing modules.
+    pub fn opaque_def(self, name: impl Into<Identifier>, ty: Expr, body: Expr) -> Self {
+        let mut d = def(name.into(), ty, body);
+        d.opaque = true;
+        self.decl(Decl::Def(d))
+    }
+
+    // Definitions that may refer to each other, given as name, type and
+    // body.
+    pub fn mutual(self, defs: impl IntoIterator<Item = (Identifier, Expr, Expr)>) -> Self {
+        let defs = defs.into_iter().map(|(x, t, e)| def(x, t, e)).collect();
+        self.decl(Decl::Mutual(defs))
+    }
+
+    pub fn axiom(self, name: impl Into<Identifier>, ty: Expr) -> Self {
+        self.item(Item::Axiom(name.into(), ty))
+    }
+
+    pub fn eval(self, e: Expr) -> Self {
+        self.item(Item::Command(Command::Eval(e)))
+    }
+
+    pub fn decl(self, decl: Decl) -> Self {
+        self.item(Item::Decl(decl))
+    }
+
+    // Checks the program on its own.
+    pub fn check(self) -> Result<Checker, Diagnostic> {
+        let mut checker = Checker::new();
+        checker.add_program(self)?;
+        Ok(checker)
+    }
+
+    fn item(mut self, item: Item) -> Self {
+        let pos = Pos {
+            line: self.items.len() + 1,
+            column: 1,
+        };
+
+        self.items.push((pos, item));
+        self
+    }
+}
+
+fn def(name: Identifier, ty: Expr, body: Expr) -> Def {
+    Def {
+        name,
+        ty,
+        body,
+        doc: None,
+        comments: vec![],
+        opaque: false,
+        private: false,
+    }
+}
+
+// Errors in a single term are reported at its start.
+fn at_start<T>(result: Result<T, Error>) -> Result<T, Diagnostic> {
+    result.map_err(|message| Diagnostic {
+        pos: Pos { line: 1, column: 1 },
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn checked_environments_are_shared() {
+        fn shared<T: Send + Sync>() {}
+        shared::<Checker>();
+        shared::<Snapshot>();
+
+        let mut checker = Checker::new();
+        checker
+            .add_declaration("def double : Int -> Int := \\n. int_mul n 2")
+            .unwrap();
+        let workers = (0..4)
+            .map(|n| {
+                let checker = checker.to_owned();
+                thread::spawn(move || checker.normalize(&format!("double {n}")).unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        for (n, w) in workers.into_iter().enumerate() {
+            assert_eq!(w.join().unwrap(), Expr::IntLit(2 * n as i64));
+        }
+    }
+
+    #[test]
+    fn checkers_add_declarations_atomically() {
+        let mut checker = Checker::new();
+        let out = checker
+            .add_declaration("def one : Int := 1\n#eval int_add one one")
+            .unwrap();
+        assert_eq!(out, vec!["2"]);
+
+        let s = checker.snapshot();
+        let e = checker
+            .add_declaration("def two : Int := 2\ndef three : Int := int_show 3")
+            .unwrap_err();
+        assert_eq!((e.pos.line, e.message), (2, "type mismatch".into()));
+        assert!(checker.infer("two").is_err());
+
+        checker.add_declaration("def two : Int := 2").unwrap();
+        assert_eq!(checker.infer("two").unwrap(), Expr::Int);
+        assert_eq!(
+            checker.normalize("int_add one two").unwrap(),
+            Expr::IntLit(3)
+        );
+
+        checker.restore(s);
+        assert_eq!(
+            checker.infer("two").unwrap_err().message,
+            "unknown identifier"
+        );
+    }
+
+    #[test]
+    fn programs_are_built_without_text() {
+        let var = |x: &str| Box::new(Expr::Var(x.to_owned()));
+        let succ = |x: &str| {
+            let e = Expr::App(Box::new(Expr::Prim(crate::Prim::IntAdd)), var(x));
+            Expr::App(Box::new(e), Box::new(Expr::IntLit(1)))
+        };
+
+        let mut p = ProgramBuilder::new().def("n0", Expr::Int, Expr::IntLit(0));
+        for k in 1..=5 {
+            p = p.def(format!("n{k}"), Expr::Int, succ(&format!("n{}", k - 1)));
+        }
+
+        let checker = p.to_owned().check().unwrap();
+        assert_eq!(checker.normalize("n5").unwrap(), Expr::IntLit(5));
+
+        let Err(e) = p.def("s", Expr::Str, succ("n5")).check() else {
+            panic!("expected an error");
+        };
+        assert_eq!((e.pos.line, e.message.message()), (7, "type mismatch"));
+
+        let mut checker = Checker::new();
+        let p = ProgramBuilder::new()
+            .axiom("a", Expr::Int)
+            .opaque_def("b", Expr::Int, succ("a"));
+        let out = checker.add_program(p.eval(*var("b"))).unwrap();
+        assert_eq!(out, vec!["int_add a 1"]);
+        assert_eq!(checker.infer("b").unwrap(), Expr::Int);
+        assert!(checker.driver().interface.axioms.contains(&"a".to_owned()));
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use crate::{Error, Expr, Identifier};
+
+// An upper bound on the length of a list: that of the parameter it was taken
+// apart from, if any, plus the offset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Size {
+    pub param: Option<usize>,
+    pub offset: isize,
+}
+
+impl Size {
+    const NIL: Self = Self {
+        param: None,
+        offset: 0,
+    };
+
+    fn plus(self, n: isize) -> Self {
+        Self {
+            offset: self.offset + n,
+            ..self
+        }
+    }
+
+    // Lengths are never negative, so a constant bound is below a parameter
+    // plus an offset at least as large.
+    fn le(self, other: Self) -> bool {
+        match (self.param, other.param) {
+            (None, _) => self.offset <= other.offset,
+            (Some(k), Some(j)) => k == j && self.offset <= other.offset,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+type Sizes<'a> = HashMap<&'a str, Option<Size>>;
+
+// Infers a bound on the length of the list a term evaluates to. The fold of
+// a list is bounded by it when the base case is empty and each step adds at
+// most one element to the result for the tail, which covers the functions
+// that only ever take a list apart.
+pub fn size<'a>(e: &'a Expr, sz: &Sizes<'a>) -> Option<Size> {
+    match e {
+        Expr::Cons(_, e) => size(e, sz).map(|s| s.plus(1)),
+        Expr::ListRec(e_1, e_2, e_3) => {
+            let s = size(e_1, sz)?;
+            let (h, t, r, e) = step(e_3)?;
+            let mut sz_ = sz.to_owned();
+            sz_.insert(h, None);
+            sz_.insert(t, Some(s.plus(-1)));
+            sz_.insert(r, Some(s.plus(-1)));
+
+            let bounded = size(e_2, sz).is_some_and(|s_| s_.le(Size::NIL))
+                && size(e, &sz_).is_some_and(|s_| s_.le(s));
+
+            bounded.then_some(s)
+        }
+        Expr::Let(bs, e) => {
+            let mut sz_ = sz.to_owned();
+
+            for (x, e) in bs {
+                sz_.insert(x, size(e, sz));
+            }
+
+            size(e, &sz_)
+        }
+        Expr::Nil => Some(Size::NIL),
+        Expr::Sub(x, e_1, e_2) => {
+            let mut sz_ = sz.to_owned();
+            sz_.insert(x, size(e_1, sz));
+            size(e_2, &sz_)
+        }
+        Expr::Var(x) => sz.get(x.as_str()).copied().flatten(),
+        _ => None,
+    }
+}
+
+// The binders of the step of a fold: the head, the tail and the result for
+// the tail.
+fn step(e: &Expr) -> Option<(&str, &str, &str, &Expr)> {
+    let Expr::Lam(h, _, e) = e else { return None };
+    let Expr::Lam(t, _, e) = &**e else {
+        return None;
+    };
+    let Expr::Lam(r, _, e) = &**e else {
+        return None;
+    };
+    Some((h, t, r, e))
+}
+
+// Checks that a block of recursive definitions terminates, given the normal
+// forms of their bodies. There must be a parameter position at which every
+// recursive call passes a list strictly shorter than the one the caller got
+// there. Sizes are inferred, so a call may pass the result of a function
+// like `tail` or `filter` rather than a variable bound by a fold. Gives that
+// position, unless the bodies make no recursive calls.
+pub fn check_termination(names: &[Identifier], bodies: &[Expr]) -> Result<Option<usize>, Error> {
+    let mut calls = vec![];
+
+    for e in bodies {
+        let mut sz = HashMap::new();
+        let mut e = e;
+        let mut k = 0;
+
+        while let Expr::Lam(x, _, e_) = e {
+            sz.insert(
+                x.as_str(),
+                Some(Size {
+                    param: Some(k),
+                    offset: 0,
+                }),
+            );
+            e = e_;
+            k += 1;
+        }
+
+        collect_calls(e, names, &sz, &mut calls)?;
+    }
+
+    let n = calls.iter().map(Vec::len).min().unwrap_or(0);
+
+    let decreasing = |j: usize| {
+        calls
+            .iter()
+            .all(|args| args[j].is_some_and(|s| s.param == Some(j) && s.offset < 0))
+    };
+
+    if calls.is_empty() {
+        return Ok(None);
+    }
+
+    match (0..n).find(|&j| decreasing(j)) {
+        Some(j) => Ok(Some(j)),
+        None => Err("cannot show that recursion terminates".into()),
+    }
+}
+
+// The sizes of the arguments of every recursive call in a term.
+fn collect_calls<'a>(
+    e: &'a Expr,
+    names: &[Identifier],
+    sz: &Sizes<'a>,
+    calls: &mut Vec<Vec<Option<Size>>>,
+) -> Result<(), Error> {
+    let mut head = e;
+    let mut args = vec![];
+
+    while let Expr::App(e_1, e_2) = head {
+        args.push(&**e_2);
+        head = e_1;
+    }
+
+    args.reverse();
+
+    match head {
+        Expr::Var(x) if names.contains(x) && !sz.contains_key(x.as_str()) => {
+            if args.is_empty() {
+                return Err("cannot show that recursion terminates".into());
+            }
+
+            for e in &args {
+                collect_calls(e, names, sz, calls)?;
+            }
+
+            calls.push(args.iter().map(|e| size(e, sz)).collect());
+            return Ok(());
+        }
+        _ if !args.is_empty() => {
+            collect_calls(head, names, sz, calls)?;
+
+            return args
+                .into_iter()
+                .try_for_each(|e| collect_calls(e, names, sz, calls));
+        }
+        _ => {}
+    }
+
+    let mut go = |e: &'a Expr, sz: &Sizes<'a>| collect_calls(e, names, sz, calls);
+
+    match e {
+        Expr::Cons(e_1, e_2) | Expr::Fun(e_1, e_2) | Expr::Inst(e_1, e_2) => {
+            go(e_1, sz)?;
+            go(e_2, sz)
+        }
+        Expr::Lam(x, a, e) => {
+            if let Some(a) = a {
+                go(a, sz)?;
+            }
+
+            let mut sz_ = sz.to_owned();
+            sz_.insert(x, None);
+            go(e, &sz_)
+        }
+        Expr::Let(bs, e) => {
+            let mut sz_ = sz.to_owned();
+
+            for (x, e) in bs {
+                go(e, sz)?;
+                sz_.insert(x, size(e, sz));
+            }
+
+            go(e, &sz_)
+        }
+        Expr::LetRec(bs, e) => {
+            let mut sz_ = sz.to_owned();
+
+            for (x, a, _) in bs {
+                go(a, sz)?;
+                sz_.insert(x, None);
+            }
+
+            for (_, _, e) in bs {
+                go(e, &sz_)?;
+            }
+
+            go(e, &sz_)
+        }
+        Expr::List(e) => go(e, sz),
+        Expr::ListRec(e_1, e_2, e_3) => {
+            go(e_1, sz)?;
+            go(e_2, sz)?;
+
+            // The result for the tail is only as short as the tail when the
+            // whole fold is bounded by the list.
+            match (size(e_1, sz), step(e_3)) {
+                (Some(s), Some((h, t, r, e_))) => {
+                    let mut sz_ = sz.to_owned();
+                    sz_.insert(h, None);
+                    sz_.insert(t, Some(s.plus(-1)));
+                    sz_.insert(r, size(e, sz).map(|_| s.plus(-1)));
+                    go(e_, &sz_)
+                }
+                _ => go(e_3, sz),
+            }
+        }
+        Expr::Pi(x, e_1, e_2) | Expr::Sub(x, e_1, e_2) => {
+            go(e_1, sz)?;
+            let mut sz_ = sz.to_owned();
+
+            let s = match e {
+                Expr::Sub(..) => size(e_1, sz),
+                _ => None,
+            };
+
+            sz_.insert(x, s);
+            go(e_2, &sz_)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::driver::Driver;
+
+    #[test]
+    fn recursion_on_smaller_lists_terminates() {
+        let src = "def drop : List Int -> List Int := \\xs. ListRec xs nil (\\h t r. t)
+mutual
+def len : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. int_add 1 (len t))
+def odds : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. int_add h (odds (drop t)))
+end";
+
+        let mut driver = Driver::new();
+        driver.run(src).unwrap();
+
+        let xs = HashSet::new();
+        assert_eq!(
+            driver.d["len"].quote(&xs).to_string(),
+            "\\xs. ListRec xs 0 (\\h t r. int_add 1 (len t))"
+        );
+
+        let fails = |a: &str| {
+            let src = format!("mutual\n{a}\nend");
+            let e = Driver::new().run(&src).unwrap_err();
+            assert_eq!(e.message, "cannot show that recursion terminates");
+        };
+
+        fails("def loop : List Int -> Int := \\xs. loop xs");
+        fails("def grow : List Int -> Int := \\xs. grow (cons 1 xs)");
+        fails("def f : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. f (cons h t))");
+        fails(
+            "def f : List Int -> Int := \\xs. ListRec xs 0 (\\h t r. g t)
+def g : List Int -> Int := \\xs. f xs",
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    decl::Def,
+    options::{CheckOptions, NormStrategy},
+    Context, Env, Expr, Identifier,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hash(pub u64);
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:016x}", self.0)
+    }
+}
+
+// 64-bit FNV-1a, which unlike `DefaultHasher` is stable across builds.
+struct Hasher(u64);
+
+impl Hasher {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn bytes(&mut self, bs: &[u8]) {
+        for &b in bs {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn tag(&mut self, k: u8) {
+        self.bytes(&[k]);
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.bytes(&n.to_le_bytes());
+    }
+
+    fn str(&mut self, a: &str) {
+        self.u64(a.len() as u64);
+        self.bytes(a.as_bytes());
+    }
+}
+
+impl Expr {
+    // Hashes the term up to renaming of bound variables, with free variables
+    // resolved by `refs` standing for the hash they are given, so that
+    // renaming a definition leaves the hashes of its dependents unchanged.
+    pub fn hash_with(&self, refs: &dyn Fn(&str) -> Option<Hash>) -> Hash {
+        let mut h = Hasher::new();
+        self.write_hash(&mut h, &mut vec![], refs);
+        Hash(h.0)
+    }
+
+    fn write_hash<'a>(
+        &'a self,
+        h: &mut Hasher,
+        bs: &mut Vec<&'a str>,
+        refs: &dyn Fn(&str) -> Option<Hash>,
+    ) {
+        match self {
+            Self::App(e_1, e_2) => {
+                h.tag(0);
+                e_1.write_hash(h, bs, refs);
+                e_2.write_hash(h, bs, refs);
+            }
+            Self::Cons(e_1, e_2) => {
+                h.tag(1);
+                e_1.write_hash(h, bs, refs);
+                e_2.write_hash(h, bs, refs);
+            }
+            Self::Fun(e_1, e_2) => {
+                h.tag(2);
+                e_1.write_hash(h, bs, refs);
+                e_2.write_hash(h, bs, refs);
+            }
+            Self::Int => h.tag(3),
+            &Self::IntLit(n) => {
+                h.tag(4);
+                h.u64(n as u64);
+            }
+            Self::Lam(x, a, e) => {
+                h.tag(5);
+
+                if let Some(a) = a {
+                    h.tag(18);
+                    a.write_hash(h, bs, refs);
+                }
+
+                bs.push(x);
+                e.write_hash(h, bs, refs);
+                bs.pop();
+            }
+            Self::Let(bs_, e) => {
+                h.tag(25);
+                h.u64(bs_.len() as u64);
+
+                for (_, e) in bs_ {
+                    e.write_hash(h, bs, refs);
+                }
+
+                bs.extend(bs_.iter().map(|(x, _)| x.as_str()));
+                e.write_hash(h, bs, refs);
+                bs.truncate(bs.len() - bs_.len());
+            }
+            Self::LetRec(bs_, e) => {
+                h.tag(26);
+                h.u64(bs_.len() as u64);
+
+                for (_, a, _) in bs_ {
+                    a.write_hash(h, bs, refs);
+                }
+
+                bs.extend(bs_.iter().map(|(x, _, _)| x.as_str()));
+
+                for (_, _, e) in bs_ {
+                    e.write_hash(h, bs, refs);
+                }
+
+                e.write_hash(h, bs, refs);
+                bs.truncate(bs.len() - bs_.len());
+            }
+            Self::List(e) => {
+                h.tag(6);
+                e.write_hash(h, bs, refs);
+            }
+            Self::ListRec(e_1, e_2, e_3) => {
+                h.tag(7);
+                e_1.write_hash(h, bs, refs);
+                e_2.write_hash(h, bs, refs);
+                e_3.write_hash(h, bs, refs);
+            }
+            Self::Nil => h.tag(8),
+            Self::Prim(p) => {
+                h.tag(9);
+                h.str(p.name());
+            }
+            Self::Str => h.tag(10),
+            Self::StrLit(a) => {
+                h.tag(11);
+                h.str(a);
+            }
+            Self::Sub(x, e_1, e_2) => {
+                h.tag(12);
+                e_1.write_hash(h, bs, refs);
+                bs.push(x);
+                e_2.write_hash(h, bs, refs);
+                bs.pop();
+            }
+            &Self::U(i) => {
+                h.tag(13);
+                h.tag(i);
+            }
+            Self::Pi(x, e_1, e_2) => {
+                h.tag(19);
+                e_1.write_hash(h, bs, refs);
+                bs.push(x);
+                e_2.write_hash(h, bs, refs);
+                bs.pop();
+            }
+            Self::Code => h.tag(22),
+            Self::Error => h.tag(21),
+            Self::Hole(x) => {
+                h.tag(20);
+                h.str(x);
+            }
+            Self::Quote(e) => {
+                h.tag(23);
+                e.write_hash(h, bs, refs);
+            }
+            Self::Splice(e) => {
+                h.tag(24);
+                e.write_hash(h, bs, refs);
+            }
+            Self::Inst(e_1, e_2) => {
+                h.tag(17);
+                e_1.write_hash(h, bs, refs);
+                e_2.write_hash(h, bs, refs);
+            }
+            Self::Var(x) => match bs.iter().rev().position(|&y| y == x) {
+                Some(k) => {
+                    h.tag(14);
+                    h.u64(k as u64);
+                }
+                None => match refs(x) {
+                    Some(Hash(n)) => {
+                        h.tag(15);
+                        h.u64(n);
+                    }
+                    None => {
+                        h.tag(16);
+                        h.str(x);
+                    }
+                },
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub ty: Expr,
+    pub body: Expr,
+}
+
+// Checked definitions keyed by the hash of their type and body, with names
+// as mere pointers into the store. Identical definitions share an entry
+// whatever they are called.
+#[derive(Clone, Debug, Default)]
+pub struct Store {
+    entries: HashMap<Hash, Entry>,
+    names: HashMap<Identifier, Hash>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hash(&self, def: &Def) -> Hash {
+        let refs = |x: &str| self.names.get(x).copied();
+        let mut h = Hasher::new();
+        h.u64(def.ty.hash_with(&refs).0);
+        h.u64(def.body.hash_with(&refs).0);
+        Hash(h.0)
+    }
+
+    // References to the names already in the store are hashed by content;
+    // any other free variable, such as a recursive occurrence in a mutual
+    // block, is hashed by name.
+    pub fn insert(&mut self, def: &Def) -> Hash {
+        let hash = self.hash(def);
+
+        self.entries.entry(hash).or_insert_with(|| Entry {
+            ty: def.ty.to_owned(),
+            body: def.body.to_owned(),
+        });
+
+        self.names.insert(def.name.to_owned(), hash);
+        hash
+    }
+
+    // Identifies declarations before they are checked, by their names, docs,
+    // flags and syntax, the names of the hints elaboration may insert and the
+    // options that change what checks. Names that are not in the store stand
+    // for their type and value, so the fingerprint changes whenever a
+    // dependency does.
+    pub fn fingerprint<'a>(
+        &self,
+        defs: &[Def],
+        hints: impl IntoIterator<Item = &'a str>,
+        options: &CheckOptions,
+        cx: &Context,
+        d: &Env,
+    ) -> Hash {
+        let xs = HashSet::new();
+
+        let refs = |x: &str| {
+            self.lookup(x).or_else(|| {
+                let t = cx.get(x)?.quote(&xs).hash_with(&|_| None);
+                let v = d.get(x)?.quote(&xs).hash_with(&|_| None);
+                let mut h = Hasher::new();
+                h.u64(t.0);
+                h.u64(v.0);
+                Some(Hash(h.0))
+            })
+        };
+
+        let mut h = Hasher::new();
+
+        for def in defs {
+            h.str(&def.name);
+
+            match &def.doc {
+                Some(a) => {
+                    h.tag(1);
+                    h.str(a);
+                }
+                None => h.tag(0),
+            }
+
+            h.tag(def.opaque as u8);
+            h.tag(def.private as u8);
+            h.u64(def.ty.hash_with(&refs).0);
+            h.u64(def.body.hash_with(&refs).0);
+        }
+
+        for x in hints {
+            h.str(x);
+        }
+
+        h.tag(options.opaque_lets as u8);
+        h.tag(options.observational as u8);
+        h.tag(match options.strategy {
+            NormStrategy::ByNeed => 0,
+            NormStrategy::ByName => 1,
+        });
+
+        for n in [options.max_depth, options.max_size, options.max_unfoldings] {
+            match n {
+                Some(n) => {
+                    h.tag(1);
+                    h.u64(n as u64);
+                }
+                None => h.tag(0),
+            }
+        }
+
+        Hash(h.0)
+    }
+
+    pub fn get(&self, hash: Hash) -> Option<&Entry> {
+        self.entries.get(&hash)
+    }
+
+    pub fn lookup(&self, x: &str) -> Option<Hash> {
+        self.names.get(x).copied()
+    }
+
+    pub fn rename(&mut self, x: &str, y: Identifier) -> Option<Hash> {
+        let hash = self.names.remove(x)?;
+        self.names.insert(y, hash);
+        Some(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{notation::Notations, parser::parse_expr};
+
+    use super::*;
+
+    fn def(x: &str, ty: &str, body: &str) -> Def {
+        let mut ns = Notations::new();
+
+        Def {
+            name: x.to_string(),
+            ty: parse_expr(ty, &mut ns).unwrap(),
+            body: parse_expr(body, &mut ns).unwrap(),
+            doc: None,
+            comments: vec![],
+            opaque: false,
+            private: false,
+        }
+    }
+
+    #[test]
+    fn store_shares_by_content() {
+        let mut store = Store::new();
+        let h_1 = store.insert(&def("id", "Int -> Int", "\\x. x"));
+        let h_2 = store.insert(&def("id_", "Int -> Int", "\\y. y"));
+        assert_eq!(h_1, h_2);
+        assert_eq!(store.len(), 1);
+
+        let h_3 = store.insert(&def("twice", "Int -> Int", "\\x. id (id x)"));
+        store.rename("id", "identity".to_string());
+        let h_4 = store.hash(&def("twice", "Int -> Int", "\\x. identity (identity x)"));
+        assert_eq!(h_3, h_4);
+        assert_ne!(h_1, h_3);
+    }
+}
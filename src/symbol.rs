@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{OnceLock, PoisonError, RwLock},
+};
+
+use crate::Expr;
+
+// A name interned for the life of the process, compared and hashed as an
+// integer. Interned names are never freed, so the table holds one entry per
+// distinct name the process has evaluated with.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Table {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, Symbol>,
+}
+
+fn table() -> &'static RwLock<Table> {
+    static TABLE: OnceLock<RwLock<Table>> = OnceLock::new();
+    TABLE.get_or_init(RwLock::default)
+}
+
+impl Symbol {
+    // The symbol of a name, the same every time.
+    pub fn intern(x: &str) -> Self {
+        if let Some(y) = Self::lookup(x) {
+            return y;
+        }
+
+        let mut t = table().write().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(&y) = t.ids.get(x) {
+            return y;
+        }
+
+        let y = Symbol(t.names.len() as u32);
+        let x = Box::leak(Box::from(x));
+        t.names.push(x);
+        t.ids.insert(x, y);
+        y
+    }
+
+    // The symbol of a name interned before, without interning it.
+    pub fn lookup(x: &str) -> Option<Self> {
+        let t = table().read().unwrap_or_else(PoisonError::into_inner);
+        t.ids.get(x).copied()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        let t = table().read().unwrap_or_else(PoisonError::into_inner);
+        t.names[self.0 as usize]
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Expr {
+    // The term with its names interned, to be compared or hashed cheaply.
+    pub fn interned(&self) -> Expr<Symbol> {
+        self.map_names(&mut |x| Symbol::intern(x))
+    }
+}
+
+impl Expr<Symbol> {
+    pub fn named(&self) -> Expr {
+        self.map_names(&mut |x| x.as_str().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation::Notations, parser::parse_expr};
+
+    #[test]
+    fn names_are_converted_between_representations() {
+        let src = "let f := \\x. int_add x x; a := y in f (f a)";
+        let e = parse_expr(src, &mut Notations::new()).unwrap();
+
+        let e_ = e.interned();
+        let Expr::Let(bs, body) = &e_ else {
+            panic!("expected a let block");
+        };
+        let Expr::App(f, _) = &**body else {
+            panic!("expected an application");
+        };
+        assert!(matches!(**f, Expr::Var(x) if x == bs[0].0));
+        assert_eq!(bs[1].0.as_str(), "a");
+        assert_eq!(Symbol::lookup("a"), Some(bs[1].0));
+        assert_eq!(e_.named().to_string(), src);
+
+        let borrowed = Expr::<&str>::from(&e);
+        assert!(matches!(borrowed, Expr::Let(ref bs, _) if bs[0].0 == "f"));
+        assert_eq!(Expr::from(borrowed).to_string(), src);
+    }
+}
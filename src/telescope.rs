@@ -0,0 +1,134 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{freshen, Expr, Identifier};
+
+// Variables with their types in order, each type in the scope of the
+// variables before it, as bound by the parameters of a section or the
+// context of a goal.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Telescope {
+    binders: Vec<(Identifier, Expr)>,
+}
+
+impl Telescope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, x: Identifier, t: Expr) {
+        self.binders.push((x, t));
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(Identifier, Expr)> {
+        self.binders.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.binders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.binders.is_empty()
+    }
+
+    // \(x_1 : A_1) ... (x_n : A_n). e
+    pub fn lam(&self, e: Expr) -> Expr {
+        self.binders.iter().rev().fold(e, |e, (x, t)| {
+            Expr::Lam(x.to_owned(), Some(Box::new(t.to_owned())), Box::new(e))
+        })
+    }
+
+    // forall (x_1 : A_1) ... (x_n : A_n) -> t, with the binders `t` and the
+    // types after them do not mention left out.
+    pub fn pi(&self, t: Expr) -> Expr {
+        self.binders.iter().rev().fold(t, |t, (x, a)| {
+            let a = Box::new(a.to_owned());
+
+            if t.occurs(x) {
+                Expr::Pi(x.to_owned(), a, Box::new(t))
+            } else {
+                Expr::Fun(a, Box::new(t))
+            }
+        })
+    }
+
+    // e x_1 ... x_n
+    pub fn apply(&self, e: Expr) -> Expr {
+        self.binders.iter().fold(e, |e, (x, _)| {
+            Expr::App(Box::new(e), Box::new(Expr::Var(x.to_owned())))
+        })
+    }
+
+    // The telescope with its variables renamed apart from `xs` and from each
+    // other, and the types after a renamed variable referring to the new
+    // name.
+    pub fn freshen(&self, xs: &HashSet<&str>) -> Telescope {
+        let mut taken = xs.iter().map(|&x| x.to_owned()).collect::<HashSet<_>>();
+        let mut s = HashMap::new();
+        let mut binders = vec![];
+
+        for (x, t) in &self.binders {
+            let t = t.instantiate(&s);
+            let x_ = freshen(x.to_owned(), &taken.iter().map(String::as_str).collect());
+            taken.insert(x_.to_owned());
+            s.insert(x.to_owned(), Expr::Var(x_.to_owned()));
+            binders.push((x_, t));
+        }
+
+        Self { binders }
+    }
+}
+
+impl FromIterator<(Identifier, Expr)> for Telescope {
+    fn from_iter<T: IntoIterator<Item = (Identifier, Expr)>>(binders: T) -> Self {
+        Self {
+            binders: binders.into_iter().collect(),
+        }
+    }
+}
+
+impl fmt::Display for Telescope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, (x, t)) in self.binders.iter().enumerate() {
+            let sep = if k > 0 { " " } else { "" };
+            write!(f, "{sep}({x} : {t})")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation::Notations, parser::parse_expr};
+
+    #[test]
+    fn terms_are_abstracted_over_telescopes() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let ts = Telescope::from_iter([
+            ("A".to_owned(), parse("U 0")),
+            ("x".to_owned(), parse("A")),
+            ("n".to_owned(), parse("Int")),
+        ]);
+        assert_eq!(ts.to_string(), "(A : U 0) (x : A) (n : Int)");
+
+        let f = ts.apply(parse("f"));
+        assert_eq!(f.to_string(), "f A x n");
+        assert_eq!(
+            ts.lam(f).to_string(),
+            "\\(A : U 0) (x : A) (n : Int). f A x n"
+        );
+        assert_eq!(
+            ts.pi(parse("A")).to_string(),
+            "forall (A : U 0) -> A -> Int -> A"
+        );
+
+        let ts_ = ts.freshen(&HashSet::from(["A", "n"]));
+        assert_eq!(ts_.to_string(), "(A' : U 0) (x : A') (n' : Int)");
+        assert_eq!(ts_.len(), ts.len());
+    }
+}
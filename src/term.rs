@@ -0,0 +1,365 @@
+use std::{cmp::Ordering, collections::HashSet, sync::Arc};
+
+use crate::{
+    decl::fixpoints,
+    options::{Guard, NormStrategy},
+    symbol::Symbol,
+    Closure, Env, Error, Expr, Identifier, Level, Prim, Value,
+};
+
+// A term as the evaluator sees it, its local variables resolved to the
+// levels they are bound at, so that finding their values indexes the
+// locals of an environment. Globals and variables bound nowhere keep their
+// names, interned, and binders keep theirs for quotation to start from.
+// Every node fits in 16 bytes: the children of a node are boxed together,
+// and the bodies of binders are shared with the closures made of them.
+#[derive(Clone, Debug)]
+pub enum Term {
+    App(Box<[Term; 2]>),
+    Code,
+    Cons(Box<[Term; 2]>),
+    Error,
+    Fun(Box<[Term; 2]>),
+    Global(Symbol),
+    Hole,
+    Inst(Box<[Term; 2]>),
+    Int,
+    IntLit(i64),
+    Lam(Symbol, Arc<Term>),
+    Let(Box<(Vec<(Symbol, Term)>, Term)>),
+    // The bindings of a recursive block are resolved under all of its names.
+    LetRec(Box<(Vec<(Symbol, Term)>, Term)>),
+    List(Box<Term>),
+    ListRec(Box<[Term; 3]>),
+    Local(u32),
+    Nil,
+    Pi(Symbol, Box<(Term, Arc<Term>)>),
+    Prim(Prim),
+    // Quoted syntax is not evaluated, so its names are left alone.
+    Quote(Box<Expr>),
+    Splice(Box<Term>),
+    Str,
+    StrLit(Box<String>),
+    Sub(Symbol, Box<[Term; 2]>),
+    U(Level),
+}
+
+impl Expr {
+    // The term with its variables resolved against the locals of `d`, and
+    // those it binds itself at the levels after them.
+    pub fn resolve_levels(&self, d: &Env) -> Term {
+        self.levels_in(&mut d.local_symbols().collect())
+    }
+
+    // The body of a binder of `x` in the scope of `d`.
+    pub(crate) fn resolve_under(&self, x: Symbol, d: &Env) -> Term {
+        under(x, self, &mut d.local_symbols().collect())
+    }
+
+    fn levels_in(&self, xs: &mut Vec<Symbol>) -> Term {
+        let mut two = |e_1: &Expr, e_2: &Expr| Box::new([e_1.levels_in(xs), e_2.levels_in(xs)]);
+
+        match self {
+            Self::App(e_1, e_2) => Term::App(two(e_1, e_2)),
+            Self::Code => Term::Code,
+            Self::Cons(e_1, e_2) => Term::Cons(two(e_1, e_2)),
+            Self::Error => Term::Error,
+            Self::Fun(e_1, e_2) => Term::Fun(two(e_1, e_2)),
+            Self::Hole(_) => Term::Hole,
+            Self::Inst(e_1, e_2) => Term::Inst(two(e_1, e_2)),
+            Self::Int => Term::Int,
+            &Self::IntLit(n) => Term::IntLit(n),
+            Self::Lam(x, _, e) => {
+                let x = Symbol::intern(x);
+                Term::Lam(x, Arc::new(under(x, e, xs)))
+            }
+            Self::Let(bs, e) => {
+                let ts = bs
+                    .iter()
+                    .map(|(x, e)| (Symbol::intern(x), e.levels_in(xs)))
+                    .collect::<Vec<_>>();
+                xs.extend(ts.iter().map(|&(x, _)| x));
+                let t = e.levels_in(xs);
+                xs.truncate(xs.len() - bs.len());
+                Term::Let(Box::new((ts, t)))
+            }
+            Self::LetRec(bs, e) => {
+                xs.extend(bs.iter().map(|(x, _, _)| Symbol::intern(x)));
+                let ts = bs
+                    .iter()
+                    .map(|(x, _, e)| (Symbol::intern(x), e.levels_in(xs)))
+                    .collect::<Vec<_>>();
+                let t = e.levels_in(xs);
+                xs.truncate(xs.len() - bs.len());
+                Term::LetRec(Box::new((ts, t)))
+            }
+            Self::List(e) => Term::List(Box::new(e.levels_in(xs))),
+            Self::ListRec(e_1, e_2, e_3) => Term::ListRec(Box::new([
+                e_1.levels_in(xs),
+                e_2.levels_in(xs),
+                e_3.levels_in(xs),
+            ])),
+            Self::Nil => Term::Nil,
+            Self::Pi(x, e_1, e_2) => {
+                let x = Symbol::intern(x);
+                let t = e_1.levels_in(xs);
+                Term::Pi(x, Box::new((t, Arc::new(under(x, e_2, xs)))))
+            }
+            &Self::Prim(p) => Term::Prim(p),
+            Self::Quote(e) => Term::Quote(e.to_owned()),
+            Self::Splice(e) => Term::Splice(Box::new(e.levels_in(xs))),
+            Self::Str => Term::Str,
+            Self::StrLit(a) => Term::StrLit(Box::new(a.to_owned())),
+            Self::Sub(x, e_1, e_2) => {
+                let x = Symbol::intern(x);
+                let t = e_1.levels_in(xs);
+                Term::Sub(x, Box::new([t, under(x, e_2, xs)]))
+            }
+            &Self::U(i) => Term::U(i),
+            Self::Var(x) => {
+                let x = Symbol::intern(x);
+
+                match xs.iter().rposition(|&y| y == x) {
+                    Some(k) => Term::local(k),
+                    None => Term::Global(x),
+                }
+            }
+        }
+    }
+}
+
+// Resolves `e` under a binder of `x`.
+fn under(x: Symbol, e: &Expr, xs: &mut Vec<Symbol>) -> Term {
+    xs.push(x);
+    let t = e.levels_in(xs);
+    xs.pop();
+    t
+}
+
+impl Term {
+    fn local(k: usize) -> Self {
+        Self::Local(u32::try_from(k).expect("too many locals"))
+    }
+
+    pub fn eval_with(&self, d: &Env, g: &Guard) -> Result<Value, Error> {
+        g.enter()?;
+        let v = self.eval_step(d, g);
+        g.leave();
+        v
+    }
+
+    fn eval_step(&self, d: &Env, g: &Guard) -> Result<Value, Error> {
+        let ev = |t: &Term| t.eval_with(d, g);
+        let two = |[t_1, t_2]: &[Term; 2]| Ok::<_, Error>((Box::new(ev(t_1)?), Box::new(ev(t_2)?)));
+
+        Ok(match self {
+            Self::App(ts) => ev(&ts[0])?.apply_with(ev(&ts[1])?, g)?,
+            Self::Code => Value::Code,
+            Self::Cons(ts) => {
+                let (v_1, v_2) = two(ts)?;
+                Value::Cons(v_1, v_2)
+            }
+            Self::Error => return Err("syntax error".into()),
+            Self::Fun(ts) => {
+                let (v_1, v_2) = two(ts)?;
+                Value::Fun(v_1, v_2)
+            }
+            Self::Global(x) => d.global_value(x.as_str()),
+            Self::Hole => return Err("unsolved hole".into()),
+            Self::Inst(ts) => {
+                let (v_1, v_2) = two(ts)?;
+                Value::Inst(v_1, v_2)
+            }
+            Self::Int => Value::Int,
+            &Self::IntLit(n) => Value::IntLit(n),
+            Self::Lam(x, t) => Value::Lam(Closure::resolved(*x, t, d)),
+            Self::Let(b) => {
+                let (ts, t) = &**b;
+                let mut d_1 = d.to_owned();
+
+                for (x, t_1) in ts {
+                    d_1.push(*x, ev(t_1)?);
+                }
+
+                t.eval_with(&d_1, g)?
+            }
+            Self::LetRec(b) => {
+                let (ts, t) = &**b;
+                let n = d.level();
+                let names = ts
+                    .iter()
+                    .map(|(x, _)| x.as_str().to_owned())
+                    .collect::<Vec<_>>();
+                let refers = |k: usize, j: usize| ts[k].1.uses(n + j) > 0;
+
+                let bind = |vs: &[Value]| {
+                    let mut d_1 = d.to_owned();
+
+                    for (&(x, _), v) in ts.iter().zip(vs) {
+                        d_1.push(x, v.to_owned());
+                    }
+
+                    d_1
+                };
+
+                let vs = fixpoints(&names, refers, &d.names(), g, |k, vs| {
+                    ts[k].1.eval_with(&bind(vs), g)
+                })?;
+
+                t.eval_with(&bind(&vs), g)?
+            }
+            Self::List(t) => Value::List(Box::new(ev(t)?)),
+            Self::ListRec(ts) => {
+                let [t_1, t_2, t_3] = &**ts;
+                ev(t_1)?.list_rec_with(ev(t_2)?, ev(t_3)?, g)?
+            }
+            &Self::Local(k) => d.at(k as usize).to_owned(),
+            Self::Nil => Value::Nil,
+            Self::Pi(x, b) => Value::Pi(Box::new(ev(&b.0)?), Closure::resolved(*x, &b.1, d)),
+            &Self::Prim(p) => p.apply(vec![]),
+            Self::Quote(e) => Value::Quote(e.to_owned()),
+            // The syntax a splice computes is resolved where it is spliced.
+            Self::Splice(t) => match ev(t)?.unfolded(g)? {
+                Value::Quote(e) => e.eval_with(d, g)?,
+                _ => return Err("cannot splice an unknown term".into()),
+            },
+            Self::Str => Value::Str,
+            Self::StrLit(a) => Value::StrLit(a.as_ref().to_owned()),
+            Self::Sub(_, ts) if g.strategy() == NormStrategy::ByName => {
+                ev(&ts[1].substitute(d.level(), &ts[0]))?
+            }
+            Self::Sub(x, ts) => {
+                let [t_1, t_2] = &**ts;
+                let v = ev(t_1)?;
+                g.share(t_2.uses(d.level()));
+                let mut d_1 = d.to_owned();
+                d_1.push(*x, v);
+                t_2.eval_with(&d_1, g)?
+            }
+            &Self::U(i) => Value::U(i),
+        })
+    }
+
+    // The immediate subterms, quoted syntax aside.
+    fn children(&self) -> Vec<&Term> {
+        match self {
+            Self::App(ts) | Self::Cons(ts) | Self::Fun(ts) | Self::Inst(ts) | Self::Sub(_, ts) => {
+                ts.iter().collect()
+            }
+            Self::Lam(_, t) => vec![t],
+            Self::List(t) | Self::Splice(t) => vec![t],
+            Self::Let(b) | Self::LetRec(b) => b.0.iter().map(|(_, t)| t).chain([&b.1]).collect(),
+            Self::ListRec(ts) => ts.iter().collect(),
+            Self::Pi(_, b) => vec![&b.0, &b.1],
+            _ => vec![],
+        }
+    }
+
+    // How many times the local bound at level `k` occurs.
+    fn uses(&self, k: usize) -> usize {
+        match self {
+            &Self::Local(j) => usize::from(j as usize == k),
+            t => t.children().into_iter().map(|t| t.uses(k)).sum(),
+        }
+    }
+
+    // The locals bound below level `n` and the globals the term mentions.
+    pub(crate) fn free(&self, n: usize, ks: &mut HashSet<usize>, xs: &mut HashSet<Identifier>) {
+        match self {
+            &Self::Local(k) if (k as usize) < n => {
+                ks.insert(k as usize);
+            }
+            Self::Global(x) => {
+                xs.insert(x.as_str().to_owned());
+            }
+            Self::Quote(e) => xs.extend(e.free_vars()),
+            t => t.children().into_iter().for_each(|t| t.free(n, ks, xs)),
+        }
+    }
+
+    // The term in which the local bound at level `k` is replaced by `t`,
+    // and those after it move down a level. `t` was resolved at level `k`,
+    // so the locals it binds itself move up by the binders it is put under.
+    fn substitute(&self, k: usize, t: &Term) -> Term {
+        self.map_locals(0, &|j, depth| match j.cmp(&k) {
+            Ordering::Less => Self::local(j),
+            Ordering::Equal => {
+                t.map_locals(0, &|i, _| Self::local(if i < k { i } else { i + depth }))
+            }
+            Ordering::Greater => Self::local(j - 1),
+        })
+    }
+
+    // The term with each local replaced by `f` of its level and of how many
+    // binders of the term it is under.
+    fn map_locals(&self, depth: usize, f: &impl Fn(usize, usize) -> Term) -> Term {
+        let m = |t: &Term| t.map_locals(depth, f);
+        let under = |n: usize, t: &Term| t.map_locals(depth + n, f);
+        let two = |ts: &[Term; 2]| Box::new(ts.each_ref().map(m));
+
+        match self {
+            &Self::Local(j) => f(j as usize, depth),
+            Self::App(ts) => Self::App(two(ts)),
+            Self::Cons(ts) => Self::Cons(two(ts)),
+            Self::Fun(ts) => Self::Fun(two(ts)),
+            Self::Inst(ts) => Self::Inst(two(ts)),
+            Self::Lam(x, t) => Self::Lam(*x, Arc::new(under(1, t))),
+            Self::Let(b) => {
+                let ts = b.0.iter().map(|&(x, ref t)| (x, m(t))).collect();
+                Self::Let(Box::new((ts, under(b.0.len(), &b.1))))
+            }
+            Self::LetRec(b) => {
+                let n = b.0.len();
+                let ts = b.0.iter().map(|&(x, ref t)| (x, under(n, t))).collect();
+                Self::LetRec(Box::new((ts, under(n, &b.1))))
+            }
+            Self::List(t) => Self::List(Box::new(m(t))),
+            Self::ListRec(ts) => Self::ListRec(Box::new(ts.each_ref().map(m))),
+            Self::Pi(x, b) => Self::Pi(*x, Box::new((m(&b.0), Arc::new(under(1, &b.1))))),
+            Self::Splice(t) => Self::Splice(Box::new(m(t))),
+            Self::Sub(x, ts) => Self::Sub(*x, Box::new([m(&ts[0]), under(1, &ts[1])])),
+            t => t.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation::Notations, options::CheckOptions, parser::parse_expr};
+
+    #[test]
+    fn locals_are_found_by_level() {
+        let parse = |a: &str| parse_expr(a, &mut Notations::new()).unwrap();
+        let d = Env::from([("x".to_owned(), Value::IntLit(1))]);
+
+        let Term::Lam(_, t) = parse("\\y. \\x. int_add x (f y)").resolve_levels(&d) else {
+            panic!("expected a lambda");
+        };
+        let Term::Lam(_, ref t) = *t else {
+            panic!("expected a lambda");
+        };
+        let Term::App(ref ts) = **t else {
+            panic!("expected an application");
+        };
+        assert!(matches!(&ts[0], Term::App(ts) if matches!(ts[1], Term::Local(2))));
+        assert!(matches!(&ts[1], Term::App(ts)
+            if matches!(ts[0], Term::Global(f) if f.as_str() == "f")
+                && matches!(ts[1], Term::Local(1))));
+
+        // The binders of a let body move up when it is substituted by name.
+        let e = parse("let f := \\a. \\b. a in \\x. \\y. f y x");
+        let by_name = Guard::new(CheckOptions {
+            strategy: NormStrategy::ByName,
+            ..CheckOptions::default()
+        });
+        let v = e.eval_with(&d, &by_name).unwrap();
+        let v = v.apply(Value::IntLit(2)).apply(Value::IntLit(3));
+        assert!(matches!(v, Value::IntLit(3)));
+    }
+
+    #[test]
+    fn terms_are_compact() {
+        assert_eq!(std::mem::size_of::<Term>(), 16);
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    diff::TermDiff,
+    options::{unguarded, CheckOptions, Guard, NormStrategy},
+    Context, Env, Expr, Identifier, Prim,
+};
+
+// xorshift64*, enough to drive generators reproducibly from a seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+    // Choices to make before drawing from the state, the next one last.
+    tape: Vec<u8>,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed.max(1),
+            tape: vec![],
+        }
+    }
+
+    // Makes the choices given by `bytes`, one byte each, so that a fuzzer
+    // changing a byte changes a single choice, and carries on from a fixed
+    // seed once they run out.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            tape: bytes.iter().rev().copied().collect(),
+            ..Self::new(1)
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // A number in `0..n`, for `n` greater than zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        if let Some(b) = self.tape.pop() {
+            return usize::from(b) % n;
+        }
+
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn chance(&mut self, k: usize, n: usize) -> bool {
+        self.below(n) < k
+    }
+}
+
+// A source of closed, well-typed terms along with their types, which the
+// properties below are checked against.
+pub trait Generator {
+    fn generate(&mut self, rng: &mut Rng, size: usize) -> (Expr, Expr);
+}
+
+// Terms over integers, strings, lists and functions, with redexes, lets and
+// folds to compute and a few binder names reused to exercise shadowing.
+#[derive(Clone, Debug, Default)]
+pub struct Terms;
+
+const NAMES: [&str; 3] = ["x", "y", "z"];
+
+impl Generator for Terms {
+    fn generate(&mut self, rng: &mut Rng, size: usize) -> (Expr, Expr) {
+        let t = ty(rng, size.min(3));
+        let e = term(&t, false, &[], rng, size);
+        (e, t)
+    }
+}
+
+fn ty(rng: &mut Rng, size: usize) -> Expr {
+    match rng.below(if size == 0 { 2 } else { 4 }) {
+        0 => Expr::Int,
+        1 => Expr::Str,
+        2 => Expr::List(Box::new(ty(rng, size - 1))),
+        _ => Expr::Fun(Box::new(ty(rng, size - 1)), Box::new(ty(rng, size - 1))),
+    }
+}
+
+// A term of type `t` with the variables of `bs` in scope, the last binding
+// of a name shadowing the others. The kernel only infers the types of some
+// terms, such as those bound by `let`, which `infer` asks for.
+fn term(t: &Expr, infer: bool, bs: &[(Identifier, Expr)], rng: &mut Rng, size: usize) -> Expr {
+    let b = Box::new;
+    let app = |e_1, e_2| Expr::App(b(e_1), b(e_2));
+
+    let vars = bs
+        .iter()
+        .enumerate()
+        .filter(|&(k, (x, t_))| t_ == t && !bs[k + 1..].iter().any(|(y, _)| y == x))
+        .map(|(_, (x, _))| x)
+        .collect::<Vec<_>>();
+
+    if !vars.is_empty() && rng.chance(1, 3) {
+        return Expr::Var(vars[rng.below(vars.len())].to_owned());
+    }
+
+    let size_ = size.saturating_sub(1);
+
+    if size > 0 {
+        match rng.below(6) {
+            // (\(x : A). e) a
+            0 => {
+                let t_1 = ty(rng, 1);
+                let x = NAMES[rng.below(NAMES.len())].to_owned();
+                let e = under(&x, &t_1, t, true, bs, rng, size_);
+                let f = Expr::Lam(x, Some(b(t_1.to_owned())), b(e));
+                return app(f, term(&t_1, false, bs, rng, size_));
+            }
+            1 => {
+                let t_1 = ty(rng, 1);
+                let x = NAMES[rng.below(NAMES.len())].to_owned();
+                let e_1 = term(&t_1, true, bs, rng, size_);
+                let e_2 = under(&x, &t_1, t, infer, bs, rng, size_);
+                return Expr::Sub(x, b(e_1), b(e_2));
+            }
+            // ListRec xs z (\h t r. e)
+            2 => {
+                let t_1 = ty(rng, 0);
+                let t_2 = Expr::List(b(t_1.to_owned()));
+                let e_1 = term(&t_2, true, bs, rng, size_);
+                let e_2 = term(t, infer, bs, rng, size_);
+                let mut bs_ = bs.to_owned();
+                bs_.push(("h".to_owned(), t_1));
+                bs_.push(("t".to_owned(), t_2));
+                bs_.push(("r".to_owned(), t.to_owned()));
+                let e_3 = term(t, false, &bs_, rng, size_);
+                let lam = |x: &str, e| Expr::Lam(x.to_owned(), None, b(e));
+                let e_3 = lam("h", lam("t", lam("r", e_3)));
+                return Expr::ListRec(b(e_1), b(e_2), b(e_3));
+            }
+            _ => {}
+        }
+    }
+
+    let sub = |rng: &mut Rng, t: &Expr| term(t, false, bs, rng, size_);
+    let prim = |p, es: Vec<Expr>| es.into_iter().fold(Expr::Prim(p), app);
+
+    match t {
+        Expr::Int => match rng.below(if size == 0 { 1 } else { 4 }) {
+            0 => Expr::IntLit(rng.below(10) as i64),
+            1 => prim(
+                Prim::IntAdd,
+                vec![sub(rng, &Expr::Int), sub(rng, &Expr::Int)],
+            ),
+            2 => prim(
+                Prim::IntMul,
+                vec![sub(rng, &Expr::Int), sub(rng, &Expr::Int)],
+            ),
+            _ => prim(Prim::StrLength, vec![sub(rng, &Expr::Str)]),
+        },
+        Expr::Str => match rng.below(if size == 0 { 1 } else { 3 }) {
+            0 => Expr::StrLit(["", "a", "bc"][rng.below(3)].to_owned()),
+            1 => prim(
+                Prim::StrAppend,
+                vec![sub(rng, &Expr::Str), sub(rng, &Expr::Str)],
+            ),
+            _ => prim(Prim::IntShow, vec![sub(rng, &Expr::Int)]),
+        },
+        Expr::List(t_1) if infer || (size > 0 && rng.chance(2, 3)) => {
+            let e_1 = term(t_1, infer, bs, rng, size_);
+            Expr::Cons(b(e_1), b(sub(rng, t)))
+        }
+        Expr::List(_) => Expr::Nil,
+        Expr::Fun(t_1, t_2) => {
+            let x = NAMES[rng.below(NAMES.len())].to_owned();
+            let a = (infer || rng.chance(1, 2)).then(|| t_1.to_owned());
+            let e = under(&x, t_1, t_2, infer, bs, rng, size_);
+            Expr::Lam(x, a, b(e))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn under(
+    x: &str,
+    t_1: &Expr,
+    t: &Expr,
+    infer: bool,
+    bs: &[(Identifier, Expr)],
+    rng: &mut Rng,
+    size: usize,
+) -> Expr {
+    let mut bs_ = bs.to_owned();
+    bs_.push((x.to_owned(), t_1.to_owned()));
+    term(t, infer, &bs_, rng, size)
+}
+
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub property: &'static str,
+    pub terms: Vec<Expr>,
+    // Where two of the terms differ, when the property expects them to be
+    // alpha-equivalent.
+    pub diff: TermDiff,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} fails for", self.property)?;
+
+        for e in &self.terms {
+            write!(f, "\n  {e}")?;
+        }
+
+        for a in self.diff.to_string().lines() {
+            write!(f, "\n    {a}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn fail(property: &'static str, terms: &[&Expr]) -> Result<(), Failure> {
+    fail_with(property, terms, TermDiff::default())
+}
+
+fn fail_with(property: &'static str, terms: &[&Expr], diff: TermDiff) -> Result<(), Failure> {
+    Err(Failure {
+        property,
+        terms: terms.iter().map(|&e| e.to_owned()).collect(),
+        diff,
+    })
+}
+
+// A term of type `t` has a normal form of type `t`.
+pub fn preservation(e: &Expr, t: &Expr, d: &Env) -> Result<(), Failure> {
+    let cx = Context::new();
+    let t_ = t.eval(d);
+
+    if e.check(&t_, &cx).is_err() {
+        return fail("well-typedness of generated terms", &[e, t]);
+    }
+
+    let e_ = e.eval(d).quote(&HashSet::new());
+
+    if e_.check(&t_, &cx).is_err() {
+        return fail("preservation", &[e, &e_, t]);
+    }
+
+    Ok(())
+}
+
+// Normal forms evaluate to themselves: quote ∘ eval ∘ quote = quote.
+pub fn idempotence(e: &Expr, d: &Env) -> Result<(), Failure> {
+    let xs = HashSet::new();
+    let e_1 = e.eval(d).quote(&xs);
+    let e_2 = e_1.eval(d).quote(&xs);
+
+    if e_1 != e_2 {
+        let diff = e_1.diff(&e_2);
+        return fail_with("idempotence of normalization", &[e, &e_1, &e_2], diff);
+    }
+
+    Ok(())
+}
+
+// Sharing the value of a let binding does not change the normal form, only
+// how much work it takes.
+pub fn strictness(e: &Expr, d: &Env) -> Result<(), Failure> {
+    let xs = HashSet::new();
+
+    let normalize = |strategy| {
+        let g = Guard::new(CheckOptions {
+            strategy,
+            ..CheckOptions::default()
+        });
+        unguarded(e.eval_with(d, &g)).quote(&xs)
+    };
+
+    let (e_1, e_2) = (
+        normalize(NormStrategy::ByNeed),
+        normalize(NormStrategy::ByName),
+    );
+
+    if e_1 != e_2 {
+        let diff = e_1.diff(&e_2);
+        return fail_with("agreement of evaluation strategies", &[e, &e_1, &e_2], diff);
+    }
+
+    Ok(())
+}
+
+// Alpha-equivalence is reflexive, symmetric and transitive. Comparing terms
+// with themselves is the point here.
+#[allow(clippy::eq_op)]
+pub fn equivalence(e_1: &Expr, e_2: &Expr, e_3: &Expr) -> Result<(), Failure> {
+    if e_1 != e_1 {
+        return fail("reflexivity of alpha-equivalence", &[e_1]);
+    }
+
+    if (e_1 == e_2) != (e_2 == e_1) {
+        return fail("symmetry of alpha-equivalence", &[e_1, e_2]);
+    }
+
+    if e_1 == e_2 && e_2 == e_3 && e_1 != e_3 {
+        return fail("transitivity of alpha-equivalence", &[e_1, e_2, e_3]);
+    }
+
+    Ok(())
+}
+
+// Renames every binder to a name that occurs nowhere in the term, giving an
+// alpha-equivalent term that is syntactically different.
+pub fn rename(e: &Expr, rng: &mut Rng) -> Expr {
+    let mut ys = HashMap::new();
+    rename_in(e, &mut vec![], &mut ys, rng)
+}
+
+fn rename_in(
+    e: &Expr,
+    bs: &mut Vec<(Identifier, Identifier)>,
+    ys: &mut HashMap<Identifier, usize>,
+    rng: &mut Rng,
+) -> Expr {
+    let b = Box::new;
+
+    let fresh = |x: &str, ys: &mut HashMap<Identifier, usize>, rng: &mut Rng| {
+        let k = ys.entry(x.to_owned()).or_default();
+        *k += 1 + rng.below(3);
+        format!("{x}_{k}")
+    };
+
+    macro_rules! go {
+        ($e:expr) => {
+            b(rename_in($e, bs, ys, rng))
+        };
+    }
+
+    match e {
+        Expr::App(e_1, e_2) => Expr::App(go!(e_1), go!(e_2)),
+        Expr::Cons(e_1, e_2) => Expr::Cons(go!(e_1), go!(e_2)),
+        Expr::Fun(e_1, e_2) => Expr::Fun(go!(e_1), go!(e_2)),
+        Expr::Inst(e_1, e_2) => Expr::Inst(go!(e_1), go!(e_2)),
+        Expr::Lam(x, a, e) => {
+            let a = a.as_deref().map(|a| go!(a));
+            let y = fresh(x, ys, rng);
+            bs.push((x.to_owned(), y.to_owned()));
+            let e = go!(e);
+            bs.pop();
+            Expr::Lam(y, a, e)
+        }
+        Expr::Let(bs_, e) => {
+            let mut bs_1 = vec![];
+
+            for (x, e) in bs_ {
+                bs_1.push((fresh(x, ys, rng), *go!(e)));
+            }
+
+            for ((x, _), (y, _)) in bs_.iter().zip(&bs_1) {
+                bs.push((x.to_owned(), y.to_owned()));
+            }
+
+            let e = go!(e);
+            bs.truncate(bs.len() - bs_.len());
+            Expr::Let(bs_1, e)
+        }
+        Expr::LetRec(bs_, e) => {
+            let mut ts = vec![];
+
+            for (x, a, _) in bs_ {
+                ts.push((fresh(x, ys, rng), go!(a)));
+            }
+
+            for ((x, _, _), (y, _)) in bs_.iter().zip(&ts) {
+                bs.push((x.to_owned(), y.to_owned()));
+            }
+
+            let bs_1 = bs_
+                .iter()
+                .zip(ts)
+                .map(|((_, _, e), (y, a))| (y, *a, *go!(e)))
+                .collect();
+            let e = go!(e);
+            bs.truncate(bs.len() - bs_.len());
+            Expr::LetRec(bs_1, e)
+        }
+        Expr::List(e) => Expr::List(go!(e)),
+        Expr::ListRec(e_1, e_2, e_3) => Expr::ListRec(go!(e_1), go!(e_2), go!(e_3)),
+        Expr::Pi(x, e_1, e_2) | Expr::Sub(x, e_1, e_2) => {
+            let e_1 = go!(e_1);
+            let y = fresh(x, ys, rng);
+            bs.push((x.to_owned(), y.to_owned()));
+            let e_2 = go!(e_2);
+            bs.pop();
+
+            match e {
+                Expr::Pi(_, _, _) => Expr::Pi(y, e_1, e_2),
+                _ => Expr::Sub(y, e_1, e_2),
+            }
+        }
+        Expr::Var(x) => match bs.iter().rev().find(|(y, _)| y == x) {
+            Some((_, y)) => Expr::Var(y.to_owned()),
+            None => e.to_owned(),
+        },
+        _ => e.to_owned(),
+    }
+}
+
+// Checks every property on `n` terms drawn from `gen`, returning the first
+// counterexample found.
+pub fn check_properties(
+    gen: &mut dyn Generator,
+    seed: u64,
+    n: usize,
+    d: &Env,
+) -> Result<(), Failure> {
+    let mut rng = Rng::new(seed);
+
+    for k in 0..n {
+        check_term(gen, &mut rng, k % 6, d)?;
+    }
+
+    Ok(())
+}
+
+// Checks every property on a term of the given size drawn from `gen`.
+pub fn check_term(
+    gen: &mut dyn Generator,
+    rng: &mut Rng,
+    size: usize,
+    d: &Env,
+) -> Result<(), Failure> {
+    let (e, t) = gen.generate(rng, size);
+    preservation(&e, &t, d)?;
+    idempotence(&e, d)?;
+    strictness(&e, d)?;
+
+    let e_2 = rename(&e, rng);
+    let e_3 = rename(&e_2, rng);
+
+    if e != e_2 {
+        let diff = e.diff(&e_2);
+        return fail_with("alpha-equivalence of renamed terms", &[&e, &e_2], diff);
+    }
+
+    equivalence(&e, &e_2, &e_3)?;
+    let (e_4, _) = gen.generate(rng, size);
+    equivalence(&e, &e_4, &e_2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn properties_hold() {
+        if let Err(e) = check_properties(&mut Terms, 1, 500, &Env::new()) {
+            panic!("{e}");
+        }
+    }
+}
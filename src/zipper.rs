@@ -0,0 +1,277 @@
+use std::slice;
+
+use crate::{Error, Expr, Identifier};
+
+#[derive(Clone, Debug)]
+enum Shape {
+    App,
+    Cons,
+    Fun,
+    Inst,
+    Lam(Identifier),
+    // A lambda whose binder is annotated, the annotation being the first
+    // child.
+    AnnLam(Identifier),
+    // A let block, the bindings being the first children and the body the
+    // last.
+    Let(Vec<Identifier>),
+    // A recursive let block, the types being the first children, the
+    // bindings the next ones and the body the last.
+    LetRec(Vec<Identifier>),
+    List,
+    ListRec,
+    Pi(Identifier),
+    Quote,
+    Splice,
+    Sub(Identifier),
+}
+
+impl Shape {
+    fn split(e: Expr) -> Result<(Self, Vec<Expr>), Expr> {
+        match e {
+            Expr::App(e_1, e_2) => Ok((Self::App, vec![*e_1, *e_2])),
+            Expr::Cons(e_1, e_2) => Ok((Self::Cons, vec![*e_1, *e_2])),
+            Expr::Fun(e_1, e_2) => Ok((Self::Fun, vec![*e_1, *e_2])),
+            Expr::Inst(e_1, e_2) => Ok((Self::Inst, vec![*e_1, *e_2])),
+            Expr::Lam(x, None, e) => Ok((Self::Lam(x), vec![*e])),
+            Expr::Lam(x, Some(a), e) => Ok((Self::AnnLam(x), vec![*a, *e])),
+            Expr::Let(bs, e) => {
+                let (xs, mut es) = bs.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+                es.push(*e);
+                Ok((Self::Let(xs), es))
+            }
+            Expr::LetRec(bs, e) => {
+                let (mut xs, mut ts, mut es) = (vec![], vec![], vec![]);
+
+                for (x, a, e) in bs {
+                    xs.push(x);
+                    ts.push(a);
+                    es.push(e);
+                }
+
+                ts.extend(es);
+                ts.push(*e);
+                Ok((Self::LetRec(xs), ts))
+            }
+            Expr::List(e) => Ok((Self::List, vec![*e])),
+            Expr::ListRec(e_1, e_2, e_3) => Ok((Self::ListRec, vec![*e_1, *e_2, *e_3])),
+            Expr::Pi(x, e_1, e_2) => Ok((Self::Pi(x), vec![*e_1, *e_2])),
+            Expr::Quote(e) => Ok((Self::Quote, vec![*e])),
+            Expr::Splice(e) => Ok((Self::Splice, vec![*e])),
+            Expr::Sub(x, e_1, e_2) => Ok((Self::Sub(x), vec![*e_1, *e_2])),
+            e => Err(e),
+        }
+    }
+
+    fn build(self, es: Vec<Expr>) -> Expr {
+        let mut es = es.into_iter().map(Box::new);
+        let mut next = || es.next().unwrap();
+
+        match self {
+            Self::App => Expr::App(next(), next()),
+            Self::Cons => Expr::Cons(next(), next()),
+            Self::Fun => Expr::Fun(next(), next()),
+            Self::Inst => Expr::Inst(next(), next()),
+            Self::Lam(x) => Expr::Lam(x, None, next()),
+            Self::AnnLam(x) => Expr::Lam(x, Some(next()), next()),
+            Self::Let(xs) => {
+                let bs = xs.into_iter().map(|x| (x, *next())).collect();
+                Expr::Let(bs, next())
+            }
+            Self::LetRec(xs) => {
+                let ts = xs.iter().map(|_| *next()).collect::<Vec<_>>();
+                let bs = xs
+                    .into_iter()
+                    .zip(ts)
+                    .map(|(x, a)| (x, a, *next()))
+                    .collect();
+                Expr::LetRec(bs, next())
+            }
+            Self::List => Expr::List(next()),
+            Self::ListRec => Expr::ListRec(next(), next(), next()),
+            Self::Pi(x) => Expr::Pi(x, next(), next()),
+            Self::Quote => Expr::Quote(next()),
+            Self::Splice => Expr::Splice(next()),
+            Self::Sub(x) => Expr::Sub(x, next(), next()),
+        }
+    }
+
+    fn binds(&self, k: usize) -> &[Identifier] {
+        match (self, k) {
+            (Self::Lam(x), 0) | (Self::AnnLam(x), 1) | (Self::Pi(x), 1) | (Self::Sub(x), 1) => {
+                slice::from_ref(x)
+            }
+            (Self::Let(xs), k) if k == xs.len() => xs,
+            (Self::LetRec(xs), k) if k >= xs.len() => xs,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Frame {
+    shape: Shape,
+    left: Vec<Expr>,
+    right: Vec<Expr>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExprZipper {
+    focus: Expr,
+    path: Vec<Frame>,
+}
+
+impl ExprZipper {
+    pub fn new(e: Expr) -> Self {
+        Self {
+            focus: e,
+            path: vec![],
+        }
+    }
+
+    pub fn focus(&self) -> &Expr {
+        &self.focus
+    }
+
+    pub fn replace(&mut self, e: Expr) -> Expr {
+        std::mem::replace(&mut self.focus, e)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    pub fn bound(&self) -> Vec<&Identifier> {
+        self.path
+            .iter()
+            .flat_map(|f| f.shape.binds(f.left.len()))
+            .collect()
+    }
+
+    pub fn down(&mut self) -> Result<(), Error> {
+        let e = self.replace(Expr::U(0));
+
+        match Shape::split(e) {
+            Ok((shape, es)) => {
+                let mut es = es.into_iter();
+                self.focus = es.next().unwrap();
+
+                self.path.push(Frame {
+                    shape,
+                    left: vec![],
+                    right: es.rev().collect(),
+                });
+
+                Ok(())
+            }
+            Err(e) => {
+                self.focus = e;
+                Err("no subterm".into())
+            }
+        }
+    }
+
+    pub fn up(&mut self) -> Result<(), Error> {
+        let f = self.path.pop().ok_or("already at the root")?;
+        let mut es = f.left;
+        es.push(self.replace(Expr::U(0)));
+        es.extend(f.right.into_iter().rev());
+        self.focus = f.shape.build(es);
+        Ok(())
+    }
+
+    pub fn left(&mut self) -> Result<(), Error> {
+        let f = self.path.last_mut().ok_or("already at the root")?;
+        let e = f.left.pop().ok_or("no left sibling")?;
+        f.right.push(std::mem::replace(&mut self.focus, e));
+        Ok(())
+    }
+
+    pub fn right(&mut self) -> Result<(), Error> {
+        let f = self.path.last_mut().ok_or("already at the root")?;
+        let e = f.right.pop().ok_or("no right sibling")?;
+        f.left.push(std::mem::replace(&mut self.focus, e));
+        Ok(())
+    }
+
+    pub fn into_expr(mut self) -> Expr {
+        while self.up().is_ok() {}
+        self.focus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zipper_rewrite_works() {
+        // \x. f x => \x. f (g x)
+        let e = Expr::Lam(
+            "x".to_string(),
+            None,
+            Box::new(Expr::App(
+                Box::new(Expr::Var("f".to_string())),
+                Box::new(Expr::Var("x".to_string())),
+            )),
+        );
+
+        let mut z = ExprZipper::new(e);
+        z.down().unwrap();
+        z.down().unwrap();
+        z.right().unwrap();
+        assert_eq!(z.bound(), vec!["x"]);
+
+        let x = z.replace(Expr::U(0));
+        z.replace(Expr::App(Box::new(Expr::Var("g".to_string())), Box::new(x)));
+        assert!(z.right().is_err());
+        z.left().unwrap();
+        assert_eq!(z.focus(), &Expr::Var("f".to_string()));
+
+        assert_eq!(
+            z.into_expr(),
+            Expr::Lam(
+                "x".to_string(),
+                None,
+                Box::new(Expr::App(
+                    Box::new(Expr::Var("f".to_string())),
+                    Box::new(Expr::App(
+                        Box::new(Expr::Var("g".to_string())),
+                        Box::new(Expr::Var("x".to_string())),
+                    )),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn zipper_enters_quotes() {
+        // quote (f (splice c)) => quote (f (splice (g c)))
+        let e = Expr::Quote(Box::new(Expr::App(
+            Box::new(Expr::Var("f".to_string())),
+            Box::new(Expr::Splice(Box::new(Expr::Var("c".to_string())))),
+        )));
+
+        let mut z = ExprZipper::new(e);
+        z.down().unwrap();
+        z.down().unwrap();
+        z.right().unwrap();
+        z.down().unwrap();
+        assert_eq!(z.focus(), &Expr::Var("c".to_string()));
+        assert!(z.right().is_err());
+
+        let c = z.replace(Expr::U(0));
+        z.replace(Expr::App(Box::new(Expr::Var("g".to_string())), Box::new(c)));
+
+        assert_eq!(
+            z.into_expr(),
+            Expr::Quote(Box::new(Expr::App(
+                Box::new(Expr::Var("f".to_string())),
+                Box::new(Expr::Splice(Box::new(Expr::App(
+                    Box::new(Expr::Var("g".to_string())),
+                    Box::new(Expr::Var("c".to_string())),
+                )))),
+            )))
+        );
+    }
+}